@@ -0,0 +1,147 @@
+use super::ray;
+use super::vec3;
+
+// An axis aligned bounding box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: vec3::Point3,
+    pub max: vec3::Point3,
+}
+
+impl Aabb {
+    pub fn new(min: vec3::Point3, max: vec3::Point3) -> Self {
+        Aabb { min, max }
+    }
+
+    // The smallest box containing all the points, None if there are none.
+    pub fn from_points<I: IntoIterator<Item = vec3::Point3>>(points: I) -> Option<Self> {
+        points.into_iter().fold(None, |aabb: Option<Aabb>, p| {
+            Some(match aabb {
+                Some(aabb) => aabb.surrounding(Aabb::new(p, p)),
+                None => Aabb::new(p, p),
+            })
+        })
+    }
+
+    pub fn size(&self) -> vec3::Vec3 {
+        self.max - self.min
+    }
+
+    // The smallest box containing both boxes.
+    pub fn surrounding(self, other: Aabb) -> Aabb {
+        Aabb::new(
+            vec3::Point3(
+                self.min.x().min(other.min.x()),
+                self.min.y().min(other.min.y()),
+                self.min.z().min(other.min.z()),
+            ),
+            vec3::Point3(
+                self.max.x().max(other.max.x()),
+                self.max.y().max(other.max.y()),
+                self.max.z().max(other.max.z()),
+            ),
+        )
+    }
+
+    // The box common to both, empty (with min above max) if they don't overlap.
+    pub fn overlap(self, other: Aabb) -> Aabb {
+        Aabb::new(
+            vec3::Point3(
+                self.min.x().max(other.min.x()),
+                self.min.y().max(other.min.y()),
+                self.min.z().max(other.min.z()),
+            ),
+            vec3::Point3(
+                self.max.x().min(other.max.x()),
+                self.max.y().min(other.max.y()),
+                self.max.z().min(other.max.z()),
+            ),
+        )
+    }
+
+    // Bounds in world space of a box given in the local frame placed at origin.
+    pub fn to_world(self, origin: &vec3::Point3, frame: &vec3::Frame) -> Aabb {
+        let corners = (0..8).map(|corner| {
+            let local = vec3::Vec3(
+                if corner & 1 == 0 {
+                    self.min.x()
+                } else {
+                    self.max.x()
+                },
+                if corner & 2 == 0 {
+                    self.min.y()
+                } else {
+                    self.max.y()
+                },
+                if corner & 4 == 0 {
+                    self.min.z()
+                } else {
+                    self.max.z()
+                },
+            );
+            *origin + frame.to_world(&local)
+        });
+        Aabb::from_points(corners).unwrap()
+    }
+
+    // Range of t within [t_min, t_max] where the ray is inside the box, using the
+    // slab method.
+    pub fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let origin = [r.origin.x(), r.origin.y(), r.origin.z()];
+        let direction = [r.direction.x(), r.direction.y(), r.direction.z()];
+        let min = [self.min.x(), self.min.y(), self.min.z()];
+        let max = [self.max.x(), self.max.y(), self.max.z()];
+
+        let mut t_enter = t_min;
+        let mut t_exit = t_max;
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / direction[axis];
+            let mut t0 = (min[axis] - origin[axis]) * inverse_direction;
+            let mut t1 = (max[axis] - origin[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // Written so a NaN from a ray in the plane of a slab keeps the old bound.
+            t_enter = if t0 > t_enter { t0 } else { t_enter };
+            t_exit = if t1 < t_exit { t1 } else { t_exit };
+            if t_exit < t_enter {
+                return None;
+            }
+        }
+        Some((t_enter, t_exit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aabb_hit() {
+        let aabb = Aabb::new(vec3::Point3(-1.0, -1.0, -1.0), vec3::Point3(1.0, 1.0, 1.0));
+        let r = ray::Ray::new(vec3::Point3(0.0, 0.0, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        assert!(aabb.hit(&r, 0.0, f32::INFINITY) == Some((4.0, 6.0)));
+        assert!(aabb.hit(&r, 0.0, 3.0).is_none());
+
+        // Starting inside, the range begins at t_min.
+        let inside = ray::Ray::new(vec3::Point3(0.0, 0.5, 0.0), vec3::Vec3(1.0, 0.0, 0.0));
+        assert!(aabb.hit(&inside, 0.0, f32::INFINITY) == Some((0.0, 1.0)));
+
+        let miss = ray::Ray::new(vec3::Point3(0.0, 2.0, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        assert!(aabb.hit(&miss, 0.0, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_aabb_to_world() {
+        let aabb = Aabb::new(vec3::Point3(-1.0, -1.0, 0.0), vec3::Point3(1.0, 1.0, 2.0));
+        // Laying the z axis along +x.
+        let frame = vec3::Frame::from_normal(&vec3::Vec3(1.0, 0.0, 0.0));
+        let world = aabb.to_world(&vec3::Point3(0.0, 1.0, 0.0), &frame);
+        assert!((world.min - vec3::Point3(0.0, 0.0, -1.0)).norm() < 1e-6);
+        assert!((world.max - vec3::Point3(2.0, 2.0, 1.0)).norm() < 1e-6);
+
+        let merged = aabb.surrounding(world);
+        assert!((merged.min - vec3::Point3(-1.0, -1.0, -1.0)).norm() < 1e-6);
+        assert!((merged.max - vec3::Point3(2.0, 2.0, 2.0)).norm() < 1e-6);
+    }
+}
@@ -0,0 +1,109 @@
+use super::color;
+use super::framebuffer::Framebuffer;
+use super::hittable;
+use super::pfm;
+use super::vec3;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Arbitrary output variables recorded at the first hit of a camera ray.
+// albedo: The material albedo at the hit point.
+// normal: The shading normal at the hit point.
+// depth: t along the camera ray.
+// position: The world space hit point.
+// object_id: Index of the object in the world that was hit.
+// material_id: Key identifying the material that was hit.
+#[derive(Debug, Copy, Clone)]
+pub struct AovSample {
+    pub albedo: color::Color,
+    pub normal: vec3::Vec3,
+    pub depth: f32,
+    pub position: vec3::Point3,
+    pub object_id: usize,
+    pub material_id: usize,
+}
+
+impl AovSample {
+    pub fn from_hit(hit_record: &hittable::HitRecord) -> Self {
+        AovSample {
+            albedo: hit_record.material.albedo(hit_record),
+            normal: hit_record.normal,
+            depth: hit_record.t,
+            position: hit_record.p,
+            object_id: hit_record.object_id,
+            // Materials are shared between objects, so the allocation identifies them.
+            material_id: Rc::as_ptr(&hit_record.material) as *const () as usize,
+        }
+    }
+}
+
+// Per pixel AOV images. Continuous values are averaged over all samples in a pixel,
+// ids are taken from the first sample that hit something.
+// Pixels where nothing was hit have an id of 0, hit objects and materials start from 1.
+pub struct AovBuffers {
+    pub albedo: Framebuffer,
+    pub normal: Framebuffer,
+    pub depth: Framebuffer,
+    pub position: Framebuffer,
+    pub object_id: Framebuffer,
+    pub material_id: Framebuffer,
+    material_keys: HashMap<usize, usize>,
+}
+
+impl AovBuffers {
+    pub fn new(width: usize, height: usize) -> Self {
+        AovBuffers {
+            albedo: Framebuffer::new(width, height),
+            normal: Framebuffer::new(width, height),
+            depth: Framebuffer::new(width, height),
+            position: Framebuffer::new(width, height),
+            object_id: Framebuffer::new(width, height),
+            material_id: Framebuffer::new(width, height),
+            material_keys: HashMap::new(),
+        }
+    }
+
+    // Store the samples taken for the pixel at (x, y), row 0 being the top of the image.
+    pub fn write_pixel(&mut self, x: usize, y: usize, samples: &[Option<AovSample>]) {
+        if samples.is_empty() {
+            return;
+        }
+        let scale = 1.0 / samples.len() as f32;
+
+        for sample in samples.iter().flatten() {
+            self.albedo.add(x, y, sample.albedo * scale);
+            self.normal.add(x, y, sample.normal * scale);
+            self.depth.add(
+                x,
+                y,
+                color::Color(sample.depth, sample.depth, sample.depth) * scale,
+            );
+            self.position.add(x, y, sample.position * scale);
+        }
+
+        if let Some(first) = samples.iter().flatten().next() {
+            let object_id = (first.object_id + 1) as f32;
+            self.object_id
+                .set(x, y, color::Color(object_id, object_id, object_id));
+
+            let next_key = self.material_keys.len() + 1;
+            let material_id = *self
+                .material_keys
+                .entry(first.material_id)
+                .or_insert(next_key) as f32;
+            self.material_id
+                .set(x, y, color::Color(material_id, material_id, material_id));
+        }
+    }
+
+    // Write every AOV as a float image named <prefix>_<aov>.pfm.
+    pub fn write(&self, prefix: &str) -> std::io::Result<()> {
+        pfm::write_pfm(&format!("{}_albedo.pfm", prefix), &self.albedo)?;
+        pfm::write_pfm(&format!("{}_normal.pfm", prefix), &self.normal)?;
+        pfm::write_pfm(&format!("{}_depth.pfm", prefix), &self.depth)?;
+        pfm::write_pfm(&format!("{}_position.pfm", prefix), &self.position)?;
+        pfm::write_pfm(&format!("{}_object_id.pfm", prefix), &self.object_id)?;
+        pfm::write_pfm(&format!("{}_material_id.pfm", prefix), &self.material_id)?;
+        Ok(())
+    }
+}
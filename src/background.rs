@@ -0,0 +1,195 @@
+use super::color;
+use super::framebuffer::Framebuffer;
+use super::sampling;
+use super::util;
+use super::vec3;
+use std::f32::consts::PI;
+use std::ops;
+
+// t == 0, returns start, t == 1 returns end.
+pub fn linear_blend<T>(start: &T, end: &T, t: f32) -> T
+where
+    T: ops::Mul<f32, Output = T> + ops::Add<Output = T> + Copy,
+{
+    *start * (1.0 - t) + (*end * t)
+}
+
+// A direction towards the background picked for direct lighting.
+// direction: Unit vector pointing away from the scene.
+// radiance: Light arriving from that direction.
+// pdf: Solid angle density the direction was picked with.
+#[derive(Debug, Copy, Clone)]
+pub struct BackgroundSample {
+    pub direction: vec3::Vec3,
+    pub radiance: color::Color,
+    pub pdf: f32,
+}
+
+// Light arriving from infinitely far away, seen by rays that leave the scene.
+pub trait Background {
+    // Radiance arriving along the unit direction.
+    fn radiance(&self, direction: &vec3::Vec3) -> color::Color;
+
+    // Pick a direction to sample direct lighting from. Backgrounds without much
+    // variation return None and are only found by rays bouncing off surfaces.
+    fn sample(&self) -> Option<BackgroundSample> {
+        None
+    }
+
+    // Solid angle density with which sample picks the unit direction.
+    fn pdf(&self, _direction: &vec3::Vec3) -> f32 {
+        0.0
+    }
+}
+
+pub struct ConstantBackground {
+    color: color::Color,
+}
+
+impl ConstantBackground {
+    pub fn new(color: color::Color) -> Self {
+        ConstantBackground { color }
+    }
+}
+
+impl Background for ConstantBackground {
+    fn radiance(&self, _direction: &vec3::Vec3) -> color::Color {
+        self.color
+    }
+}
+
+// Vertical gradient, from bottom at straight down to top at straight up.
+pub struct GradientBackground {
+    bottom: color::Color,
+    top: color::Color,
+}
+
+impl GradientBackground {
+    pub fn new(bottom: color::Color, top: color::Color) -> Self {
+        GradientBackground { bottom, top }
+    }
+}
+
+impl Background for GradientBackground {
+    fn radiance(&self, direction: &vec3::Vec3) -> color::Color {
+        let t = 0.5 * (direction.y() + 1.0);
+        linear_blend(&self.bottom, &self.top, t)
+    }
+}
+
+// An equirectangular (latitude-longitude) map of the light around the scene.
+// The top row of the image is straight up, the left and right edges face -x.
+// rotation: Rotation around the vertical axis, in radians.
+// intensity: Scale applied to the map's values.
+pub struct EnvironmentMap {
+    image: Framebuffer,
+    rotation: f32,
+    intensity: f32,
+    distribution: sampling::Distribution2D,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Framebuffer, rotation: f32, intensity: f32) -> Self {
+        assert!(image.width > 0 && image.height > 0, "empty environment map");
+        // Rows near the poles cover less solid angle, so weigh texels by sin(theta).
+        let mut weights = Vec::with_capacity(image.width * image.height);
+        for y in 0..image.height {
+            let sin_theta = (PI * (y as f32 + 0.5) / image.height as f32).sin();
+            for x in 0..image.width {
+                weights.push(color::luminance(image.get(x, y)) * sin_theta);
+            }
+        }
+        let distribution = sampling::Distribution2D::new(&weights, image.width, image.height);
+        EnvironmentMap {
+            image,
+            rotation,
+            intensity,
+            distribution,
+        }
+    }
+
+    // Map a unit direction to image coordinates in [0, 1]^2.
+    fn direction_to_uv(&self, direction: &vec3::Vec3) -> (f32, f32) {
+        let theta = direction.y().clamp(-1.0, 1.0).acos();
+        let phi = direction.z().atan2(direction.x()) - self.rotation;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        (u, theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: f32, v: f32) -> vec3::Vec3 {
+        let theta = v * PI;
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        vec3::Vec3(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn radiance(&self, direction: &vec3::Vec3) -> color::Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let x = ((u * self.image.width as f32) as usize).min(self.image.width - 1);
+        let y = ((v * self.image.height as f32) as usize).min(self.image.height - 1);
+        self.image.get(x, y) * self.intensity
+    }
+
+    fn sample(&self) -> Option<BackgroundSample> {
+        let ((u, v), map_pdf) = self
+            .distribution
+            .sample_continuous(util::random_float(), util::random_float());
+        let sin_theta = (v * PI).sin();
+        if map_pdf == 0.0 || sin_theta == 0.0 {
+            return None;
+        }
+
+        let direction = self.uv_to_direction(u, v);
+        Some(BackgroundSample {
+            direction,
+            radiance: self.radiance(&direction),
+            // Change of variables from the unit square to solid angle.
+            pdf: map_pdf / (2.0 * PI * PI * sin_theta),
+        })
+    }
+
+    fn pdf(&self, direction: &vec3::Vec3) -> f32 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gradient() {
+        let background = GradientBackground::new(color::WHITE, color::BLUE);
+        assert!(background.radiance(&vec3::Vec3(0.0, 1.0, 0.0)) == color::BLUE);
+        assert!(background.radiance(&vec3::Vec3(0.0, -1.0, 0.0)) == color::WHITE);
+    }
+
+    #[test]
+    fn test_environment_map_directions() {
+        let mut image = Framebuffer::new(8, 4);
+        image.set(5, 1, color::Color(10.0, 10.0, 10.0));
+        let map = EnvironmentMap::new(image, 0.3, 2.0);
+
+        for &(u, v) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)].iter() {
+            let (u2, v2) = map.direction_to_uv(&map.uv_to_direction(u, v));
+            assert!((u - u2).abs() < 1e-4 && (v - v2).abs() < 1e-4);
+        }
+
+        // All the light is in one texel, so every sample lands on it.
+        for _ in 0..16 {
+            let sample = map.sample().unwrap();
+            assert!(sample.radiance == color::Color(20.0, 20.0, 20.0));
+            assert!((sample.pdf - map.pdf(&sample.direction)).abs() < 1e-3 * sample.pdf);
+        }
+    }
+}
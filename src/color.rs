@@ -5,3 +5,17 @@ pub use vec3::Vec3 as Color;
 pub static WHITE: Color = Color(1.0, 1.0, 1.0);
 pub static BLUE: Color = Color(0.5, 0.7, 1.0);
 pub static RED: Color = Color(1.0, 0.0, 0.0);
+
+// Convert CIE XYZ to linear sRGB (D65 white point).
+pub fn xyz_to_rgb(xyz: vec3::Vec3) -> Color {
+    Color(
+        3.240_454 * xyz.0 - 1.537_138_5 * xyz.1 - 0.498_531_4 * xyz.2,
+        -0.969_266 * xyz.0 + 1.876_010_8 * xyz.1 + 0.041_556 * xyz.2,
+        0.055_643_4 * xyz.0 - 0.204_025_9 * xyz.1 + 1.057_225_2 * xyz.2,
+    )
+}
+
+// Relative luminance (CIE Y) of a linear sRGB color.
+pub fn luminance(c: Color) -> f32 {
+    0.2126 * c.0 + 0.7152 * c.1 + 0.0722 * c.2
+}
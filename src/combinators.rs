@@ -0,0 +1,347 @@
+use super::color;
+use super::hittable;
+use super::material::{Evaluation, Material, Scattering};
+use super::medium;
+use super::ray;
+use super::texture::{SolidColor, Texture};
+use super::util;
+use super::vec3;
+use std::rc::Rc;
+
+fn black() -> Evaluation {
+    Evaluation {
+        value: color::Color(0.0, 0.0, 0.0),
+        pdf: 0.0,
+    }
+}
+
+// Blends two materials, scattering off second with probability amount and off
+// first otherwise.
+// amount: Texture giving the weight of the second material, read from the red channel.
+pub struct Mix {
+    first: Rc<dyn Material>,
+    second: Rc<dyn Material>,
+    amount: Rc<dyn Texture>,
+}
+
+impl Mix {
+    pub fn new(first: Rc<dyn Material>, second: Rc<dyn Material>, amount: f32) -> Self {
+        Mix::textured(first, second, Rc::new(SolidColor::scalar(amount)))
+    }
+
+    pub fn textured(
+        first: Rc<dyn Material>,
+        second: Rc<dyn Material>,
+        amount: Rc<dyn Texture>,
+    ) -> Self {
+        Mix {
+            first,
+            second,
+            amount,
+        }
+    }
+
+    fn amount(&self, hit_record: &hittable::HitRecord) -> f32 {
+        self.amount
+            .value_scalar(hit_record.u, hit_record.v, &hit_record.p)
+            .clamp(0.0, 1.0)
+    }
+
+    // The weight of the second material among light that didn't pass through the
+    // surface, which is all scatter and eval ever see.
+    fn opaque_amount(&self, hit_record: &hittable::HitRecord) -> f32 {
+        let amount = self.amount(hit_record);
+        let first = (1.0 - amount) * (1.0 - self.first.transparency(hit_record));
+        let second = amount * (1.0 - self.second.transparency(hit_record));
+        if first + second > 0.0 {
+            second / (first + second)
+        } else {
+            amount
+        }
+    }
+}
+
+impl Material for Mix {
+    fn scatter(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> Option<Scattering> {
+        let amount = self.opaque_amount(hit_record);
+        let chosen = if util::random_float() < amount {
+            &self.second
+        } else {
+            &self.first
+        };
+        let scattering = chosen.scatter(ray, hit_record)?;
+        // Specular directions can only come from the chosen material, whose weight
+        // already accounts for being picked.
+        if scattering.pdf.is_none() {
+            return Some(scattering);
+        }
+
+        // Otherwise either material could have scattered this way, so weigh the
+        // direction by both.
+        let direction = scattering.scattered.direction.unit_vector();
+        let evaluation = match self.eval(ray, hit_record, &direction) {
+            Some(evaluation) if evaluation.pdf > 0.0 => evaluation,
+            _ => return Some(scattering),
+        };
+        Some(Scattering {
+            attenuation: evaluation.value / evaluation.pdf,
+            pdf: Some(evaluation.pdf),
+            ..scattering
+        })
+    }
+
+    fn albedo(&self, hit_record: &hittable::HitRecord) -> color::Color {
+        let amount = self.amount(hit_record);
+        self.first.albedo(hit_record) * (1.0 - amount) + self.second.albedo(hit_record) * amount
+    }
+
+    fn eval(
+        &self,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        direction: &vec3::Vec3,
+    ) -> Option<Evaluation> {
+        let first = self.first.eval(ray, hit_record, direction);
+        let second = self.second.eval(ray, hit_record, direction);
+        if first.is_none() && second.is_none() {
+            return None;
+        }
+        let amount = self.opaque_amount(hit_record);
+        let first = first.unwrap_or_else(black);
+        let second = second.unwrap_or_else(black);
+        Some(Evaluation {
+            value: first.value * (1.0 - amount) + second.value * amount,
+            pdf: first.pdf * (1.0 - amount) + second.pdf * amount,
+        })
+    }
+
+    fn medium(&self) -> Option<medium::Medium> {
+        self.first.medium().or_else(|| self.second.medium())
+    }
+
+    // Only mixes of two surfaces are crossed, so volumes never enter or leave media.
+    fn is_surface(&self) -> bool {
+        self.first.is_surface() && self.second.is_surface()
+    }
+
+    fn transparency(&self, hit_record: &hittable::HitRecord) -> f32 {
+        let amount = self.amount(hit_record);
+        self.first.transparency(hit_record) * (1.0 - amount)
+            + self.second.transparency(hit_record) * amount
+    }
+}
+
+// Uses one material for the front of surfaces and another for the back.
+pub struct TwoSided {
+    front: Rc<dyn Material>,
+    back: Rc<dyn Material>,
+}
+
+impl TwoSided {
+    pub fn new(front: Rc<dyn Material>, back: Rc<dyn Material>) -> Self {
+        TwoSided { front, back }
+    }
+
+    fn side(&self, hit_record: &hittable::HitRecord) -> &Rc<dyn Material> {
+        if hit_record.front_face {
+            &self.front
+        } else {
+            &self.back
+        }
+    }
+}
+
+impl Material for TwoSided {
+    fn scatter(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> Option<Scattering> {
+        self.side(hit_record).scatter(ray, hit_record)
+    }
+
+    fn albedo(&self, hit_record: &hittable::HitRecord) -> color::Color {
+        self.side(hit_record).albedo(hit_record)
+    }
+
+    fn eval(
+        &self,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        direction: &vec3::Vec3,
+    ) -> Option<Evaluation> {
+        self.side(hit_record).eval(ray, hit_record, direction)
+    }
+
+    // The inside is entered through the front face.
+    fn medium(&self) -> Option<medium::Medium> {
+        self.front.medium()
+    }
+
+    fn is_surface(&self) -> bool {
+        self.front.is_surface() && self.back.is_surface()
+    }
+
+    fn transparency(&self, hit_record: &hittable::HitRecord) -> f32 {
+        self.side(hit_record).transparency(hit_record)
+    }
+}
+
+// Cuts holes into a surface, letting rays pass straight through where alpha is low.
+// The integrator and shadow rays handle the holes through transparency, so the
+// wrapped material only ever sees the opaque part.
+// alpha: Texture giving the opacity, read from the red channel.
+pub struct AlphaMask {
+    material: Rc<dyn Material>,
+    alpha: Rc<dyn Texture>,
+}
+
+impl AlphaMask {
+    pub fn new(material: Rc<dyn Material>, alpha: Rc<dyn Texture>) -> Self {
+        AlphaMask { material, alpha }
+    }
+
+    fn alpha(&self, hit_record: &hittable::HitRecord) -> f32 {
+        self.alpha
+            .value_scalar(hit_record.u, hit_record.v, &hit_record.p)
+            .clamp(0.0, 1.0)
+    }
+}
+
+impl Material for AlphaMask {
+    fn scatter(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> Option<Scattering> {
+        self.material.scatter(ray, hit_record)
+    }
+
+    fn albedo(&self, hit_record: &hittable::HitRecord) -> color::Color {
+        self.material.albedo(hit_record)
+    }
+
+    fn eval(
+        &self,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        direction: &vec3::Vec3,
+    ) -> Option<Evaluation> {
+        self.material.eval(ray, hit_record, direction)
+    }
+
+    fn medium(&self) -> Option<medium::Medium> {
+        self.material.medium()
+    }
+
+    fn transparency(&self, hit_record: &hittable::HitRecord) -> f32 {
+        1.0 - self.alpha(hit_record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::ConstantBackground;
+    use crate::hittable::Hittable;
+    use crate::hittable_list::HittableList;
+    use crate::material::tests::check_consistent;
+    use crate::material::{Lambertian, Metal, RoughConductor};
+    use crate::scene::Scene;
+    use crate::sphere::Sphere;
+    use crate::volume::HenyeyGreenstein;
+
+    fn hit(material: Rc<dyn Material>, front_face: bool) -> (ray::Ray, hittable::HitRecord) {
+        let normal = vec3::Vec3(0.0, 1.0, 0.0);
+        let outward_normal = if front_face { normal } else { -normal };
+        let ray = ray::Ray::new(vec3::Point3(-1.0, 1.0, 0.0), vec3::Vec3(1.0, -1.0, 0.0));
+        let hit_record = hittable::HitRecord::new(
+            1.0,
+            vec3::Point3(0.0, 0.0, 0.0),
+            &ray,
+            outward_normal,
+            material,
+        );
+        (ray, hit_record)
+    }
+
+    #[test]
+    fn test_mix_consistent() {
+        let mix = Mix::new(
+            Rc::new(Lambertian::new(color::Color(0.8, 0.1, 0.1))),
+            Rc::new(RoughConductor::copper(0.3)),
+            0.4,
+        );
+        check_consistent(Rc::new(mix), true);
+
+        let red = Rc::new(Lambertian::new(color::RED));
+        let white = Rc::new(Lambertian::new(color::WHITE));
+        let mix: Rc<dyn Material> = Rc::new(Mix::new(red, white, 0.25));
+        let (_, hit_record) = hit(mix.clone(), true);
+        assert!(mix.albedo(&hit_record) == color::Color(1.0, 0.25, 0.25));
+    }
+
+    #[test]
+    fn test_two_sided() {
+        let two_sided: Rc<dyn Material> = Rc::new(TwoSided::new(
+            Rc::new(Lambertian::new(color::RED)),
+            Rc::new(Metal::new(color::WHITE, 0.0)),
+        ));
+        let (ray, front) = hit(two_sided.clone(), true);
+        assert!(two_sided.albedo(&front) == color::RED);
+        assert!(two_sided.scatter(&ray, &front).unwrap().pdf.is_some());
+
+        let (ray, back) = hit(two_sided.clone(), false);
+        assert!(two_sided.albedo(&back) == color::WHITE);
+        assert!(two_sided.scatter(&ray, &back).unwrap().pdf.is_none());
+
+        // Scattering in a volume isn't a surface crossing, whatever it is mixed with.
+        let fog: Rc<dyn Material> = Rc::new(HenyeyGreenstein::new(color::WHITE, 0.0));
+        let lambertian: Rc<dyn Material> = Rc::new(Lambertian::new(color::RED));
+        assert!(TwoSided::new(lambertian.clone(), lambertian.clone()).is_surface());
+        assert!(!TwoSided::new(fog.clone(), fog.clone()).is_surface());
+        assert!(!Mix::new(fog, lambertian, 0.5).is_surface());
+    }
+
+    #[test]
+    fn test_alpha_mask() {
+        let lambertian = Rc::new(Lambertian::new(color::RED));
+        let cut_out: Rc<dyn Material> = Rc::new(AlphaMask::new(
+            lambertian.clone(),
+            Rc::new(SolidColor::scalar(0.25)),
+        ));
+        let (ray, hit_record) = hit(cut_out.clone(), true);
+        assert!(cut_out.transparency(&hit_record) == 0.75);
+        assert!(cut_out.scatter(&ray, &hit_record).unwrap().pdf.is_some());
+        assert!(cut_out.albedo(&hit_record) == color::RED);
+
+        // Shadow rays get through the holes on both sides of the sphere.
+        let sphere = Sphere::new(vec3::Point3(0.0, 0.0, 0.0), 1.0, cut_out);
+        let shadow_ray = ray::Ray::new(vec3::Point3(0.0, 0.0, -2.0), vec3::Vec3(0.0, 0.0, 1.0));
+        let transmittance = sphere.transmittance(&shadow_ray, 0.0, f32::INFINITY);
+        assert!((transmittance - color::WHITE * 0.5625).norm() < 1e-6);
+
+        let opaque = AlphaMask::new(lambertian, Rc::new(SolidColor::scalar(1.0)));
+        assert!(opaque.transparency(&hit_record) == 0.0);
+    }
+
+    #[test]
+    fn test_wrapped_alpha_mask() {
+        // Cut-outs keep their holes inside the other combinators.
+        let lambertian: Rc<dyn Material> = Rc::new(Lambertian::new(color::RED));
+        let cut_out: Rc<dyn Material> = Rc::new(AlphaMask::new(
+            lambertian.clone(),
+            Rc::new(SolidColor::scalar(0.0)),
+        ));
+        let shadow_ray = ray::Ray::new(vec3::Point3(0.0, 0.0, -2.0), vec3::Vec3(0.0, 0.0, 1.0));
+        let transmittance = |material: Rc<dyn Material>| {
+            let sphere = Sphere::new(vec3::Point3(0.0, 0.0, 0.0), 1.0, material);
+            let scene = Scene::new(
+                HittableList::new(Box::new(sphere)),
+                Box::new(ConstantBackground::new(color::WHITE)),
+            );
+            scene.world.transmittance(&shadow_ray, 0.0, f32::INFINITY)
+        };
+
+        let mix = Rc::new(Mix::new(cut_out.clone(), lambertian.clone(), 0.5));
+        // Light that doesn't get through must have hit the opaque material.
+        let (_, hit_record) = hit(mix.clone(), true);
+        assert!(mix.opaque_amount(&hit_record) == 1.0);
+        assert!((transmittance(mix) - color::WHITE * 0.25).norm() < 1e-6);
+        let two_sided = Rc::new(TwoSided::new(cut_out.clone(), cut_out));
+        assert!(transmittance(two_sided) == color::WHITE);
+        assert!(transmittance(lambertian) == color::Color(0.0, 0.0, 0.0));
+    }
+}
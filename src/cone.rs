@@ -0,0 +1,174 @@
+use super::aabb::Aabb;
+use super::disk::Disk;
+use super::hittable;
+use super::material;
+use super::ray;
+use super::util;
+use super::vec3;
+use std::f32::consts::PI;
+use std::option::Option;
+use std::rc::Rc;
+
+// A cone on a disk of the given radius centered on base, with its apex height
+// along axis. The base is closed by a disk unless made open.
+// u: Angle around the axis.
+// v: From the base at 0 to the apex at 1.
+pub struct Cone {
+    base: vec3::Point3,
+    frame: vec3::Frame,
+    radius: f32,
+    height: f32,
+    cap: Option<Disk>,
+    material: Rc<dyn material::Material>,
+}
+
+impl Cone {
+    pub fn new(
+        base: vec3::Point3,
+        axis: vec3::Vec3,
+        radius: f32,
+        height: f32,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        let cap = Disk::new(base, -axis, radius, material.clone());
+        Cone {
+            cap: Some(cap),
+            ..Cone::open(base, axis, radius, height, material)
+        }
+    }
+
+    pub fn open(
+        base: vec3::Point3,
+        axis: vec3::Vec3,
+        radius: f32,
+        height: f32,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        Cone {
+            base,
+            frame: vec3::Frame::from_normal(&axis.unit_vector()),
+            radius,
+            height,
+            cap: None,
+            material,
+        }
+    }
+
+    fn hit_side(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<hittable::HitRecord> {
+        let local = r.to_local(&self.base, &self.frame);
+        let (o, d) = (local.origin, local.direction);
+        // The radius shrinks linearly, x^2 + y^2 = (radius - slope z)^2.
+        let slope = self.radius / self.height;
+        let radius_at_origin = self.radius - slope * o.z();
+        let a = d.x() * d.x() + d.y() * d.y() - slope * slope * d.z() * d.z();
+        let half_b = o.x() * d.x() + o.y() * d.y() + slope * d.z() * radius_at_origin;
+        let c = o.x() * o.x() + o.y() * o.y() - radius_at_origin * radius_at_origin;
+
+        let roots = if a.abs() < 1e-9 {
+            // Parallel to the slope, crossing the cone only once.
+            if half_b == 0.0 {
+                return None;
+            }
+            vec![-c / (2.0 * half_b)]
+        } else {
+            let discriminant = half_b * half_b - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let sqrt_discriminant = discriminant.sqrt();
+            let (t0, t1) = (
+                (-half_b - sqrt_discriminant) / a,
+                (-half_b + sqrt_discriminant) / a,
+            );
+            vec![t0.min(t1), t0.max(t1)]
+        };
+        // The equation also holds on the mirrored cone above the apex.
+        let t = roots.into_iter().find(|&t| {
+            let z = local.at(t).z();
+            t >= t_min && t <= t_max && z >= 0.0 && z <= self.height
+        })?;
+
+        let p = local.at(t);
+        let distance = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        // At the apex itself the normal is undefined, point it along the axis.
+        let outward_normal = if distance > 0.0 {
+            let local_normal = vec3::Vec3(p.x(), p.y(), slope * distance).unit_vector();
+            self.frame.to_world(&local_normal)
+        } else {
+            self.frame.normal
+        };
+        let mut hit_record =
+            hittable::HitRecord::new(t, r.at(t), r, outward_normal, self.material.clone());
+        let (phi, u) = util::turn(p.y(), p.x());
+        hit_record.u = u;
+        hit_record.v = p.z() / self.height;
+        // Around the apex the direction of u is undefined, use the angle instead.
+        let radial = vec3::Vec3(phi.cos(), phi.sin(), 0.0);
+        let dpdu = vec3::Vec3(-radial.y(), radial.x(), 0.0) * (2.0 * PI * distance);
+        let dpdv = vec3::Vec3(0.0, 0.0, self.height) - radial * self.radius;
+        hit_record.dpdu = self.frame.to_world(&dpdu);
+        hit_record.dpdv = self.frame.to_world(&dpdv);
+        Some(hit_record)
+    }
+}
+
+impl hittable::Hittable for Cone {
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<hittable::HitRecord> {
+        let side = self.hit_side(r, t_min, t_max);
+        let closest_so_far = side.as_ref().map_or(t_max, |record| record.t);
+        match &self.cap {
+            Some(cap) => cap.hit(r, t_min, closest_so_far).or(side),
+            None => side,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = Aabb::new(
+            vec3::Point3(-self.radius, -self.radius, 0.0),
+            vec3::Point3(self.radius, self.radius, self.height),
+        );
+        Some(local.to_world(&self.base, &self.frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{check_tangents, lambertian};
+    use crate::hittable::Hittable;
+
+    #[test]
+    fn test_cone_hit() {
+        // A 45 degree cone with its apex at y = 1.
+        let cone = Cone::new(
+            vec3::Point3(0.0, 0.0, 0.0),
+            vec3::Vec3(0.0, 1.0, 0.0),
+            1.0,
+            1.0,
+            lambertian(),
+        );
+        let r = ray::Ray::new(vec3::Point3(0.0, 0.5, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        let hit_record = cone.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 4.5).abs() < 1e-5);
+        let expected = vec3::Vec3(0.0, 1.0, 1.0).unit_vector();
+        assert!((hit_record.normal - expected).norm() < 1e-5);
+        assert!((hit_record.v - 0.5).abs() < 1e-5);
+        check_tangents(&hit_record);
+
+        // Above the apex is the mirrored cone, which isn't part of the shape.
+        let above = ray::Ray::new(vec3::Point3(0.0, 1.5, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        assert!(cone.hit(&above, 0.001, f32::INFINITY).is_none());
+
+        // The base from below.
+        let up = ray::Ray::new(vec3::Point3(0.2, -1.0, 0.0), vec3::Vec3(0.0, 1.0, 0.0));
+        let hit_record = cone.hit(&up, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 1.0).abs() < 1e-5);
+        assert!((hit_record.normal - vec3::Vec3(0.0, -1.0, 0.0)).norm() < 1e-5);
+
+        // Straight down onto the apex.
+        let down = ray::Ray::new(vec3::Point3(0.0, 2.0, 0.0), vec3::Vec3(0.0, -1.0, 0.0));
+        let hit_record = cone.hit(&down, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 1.0).abs() < 1e-5);
+        assert!(hit_record.normal == vec3::Vec3(0.0, 1.0, 0.0));
+    }
+}
@@ -0,0 +1,249 @@
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable, Interval};
+use super::ray;
+use std::option::Option;
+
+// How the insides of the two operands combine.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl Operation {
+    fn inside(self, inside_left: bool, inside_right: bool) -> bool {
+        match self {
+            Operation::Union => inside_left || inside_right,
+            Operation::Intersection => inside_left && inside_right,
+            Operation::Difference => inside_left && !inside_right,
+        }
+    }
+}
+
+// A solid built from two closed operands, such as a lens from the intersection of
+// two spheres or a drilled part from the difference of a box and a cylinder. Each
+// keeps its own materials on the parts of its boundary that remain.
+pub struct Csg {
+    operation: Operation,
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+}
+
+// A place where the ray crosses the boundary of one of the operands.
+struct Crossing {
+    t: f32,
+    from_right: bool,
+    entering: bool,
+    hit_record: Option<HitRecord>,
+}
+
+impl Csg {
+    pub fn new(operation: Operation, left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Csg {
+            operation,
+            left,
+            right,
+        }
+    }
+
+    pub fn union(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Csg::new(Operation::Union, left, right)
+    }
+
+    pub fn intersection(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Csg::new(Operation::Intersection, left, right)
+    }
+
+    // The left operand with the right one carved out of it.
+    pub fn difference(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Csg::new(Operation::Difference, left, right)
+    }
+}
+
+// Crossings of the intervals, and whether the ray starts inside.
+fn crossings(intervals: Vec<Interval>, t_min: f32, from_right: bool) -> (Vec<Crossing>, bool) {
+    let starts_inside = intervals
+        .first()
+        .is_some_and(|interval| interval.enter.is_none() && interval.t_enter <= t_min);
+    let mut crossings = Vec::with_capacity(2 * intervals.len());
+    for interval in intervals {
+        if interval.enter.is_some() || interval.t_enter > t_min {
+            crossings.push(Crossing {
+                t: interval.t_enter,
+                from_right,
+                entering: true,
+                hit_record: interval.enter,
+            });
+        }
+        if interval.exit.is_some() {
+            crossings.push(Crossing {
+                t: interval.t_exit,
+                from_right,
+                entering: false,
+                hit_record: interval.exit,
+            });
+        }
+    }
+    (crossings, starts_inside)
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        // The first boundary of the result, an exit if the ray starts inside.
+        self.intervals(r, t_min, t_max)
+            .into_iter()
+            .find_map(|interval| interval.enter.or(interval.exit))
+    }
+
+    // Sweep through the crossings of both operands in order, keeping track of which
+    // ones the ray is inside, and keep those where the combined inside changes.
+    fn intervals(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Vec<Interval> {
+        let (mut events, mut inside_left) =
+            crossings(self.left.intervals(r, t_min, t_max), t_min, false);
+        let (right_events, mut inside_right) =
+            crossings(self.right.intervals(r, t_min, t_max), t_min, true);
+        events.extend(right_events);
+        events.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+
+        let mut intervals = vec![];
+        let mut inside = self.operation.inside(inside_left, inside_right);
+        let mut current = if inside { Some((t_min, None)) } else { None };
+        for event in events {
+            if event.from_right {
+                inside_right = event.entering;
+            } else {
+                inside_left = event.entering;
+            }
+            let now_inside = self.operation.inside(inside_left, inside_right);
+            if now_inside == inside {
+                continue;
+            }
+            inside = now_inside;
+
+            // Surfaces carved out by the right operand face the other way.
+            let mut hit_record = event.hit_record;
+            if event.from_right && self.operation == Operation::Difference {
+                if let Some(hit_record) = hit_record.as_mut() {
+                    hit_record.front_face = !hit_record.front_face;
+                }
+            }
+            match current.take() {
+                None => current = Some((event.t, hit_record)),
+                Some((t_enter, enter)) => intervals.push(Interval {
+                    t_enter,
+                    t_exit: event.t,
+                    enter,
+                    exit: hit_record,
+                }),
+            }
+        }
+        if let Some((t_enter, enter)) = current {
+            intervals.push(Interval {
+                t_enter,
+                t_exit: t_max,
+                enter,
+                exit: None,
+            });
+        }
+        intervals
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let left = self.left.bounding_box();
+        let right = self.right.bounding_box();
+        match self.operation {
+            Operation::Union => Some(left?.surrounding(right?)),
+            Operation::Intersection => match (left, right) {
+                (Some(left), Some(right)) => Some(left.overlap(right)),
+                (left, right) => left.or(right),
+            },
+            Operation::Difference => left,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3;
+
+    fn sphere(x: f32, radius: f32) -> Box<dyn Hittable> {
+        Box::new(Sphere::new(vec3::Point3(x, 0.0, 0.0), radius, lambertian()))
+    }
+
+    // Along the x axis from the left.
+    fn along_x() -> ray::Ray {
+        ray::Ray::new(vec3::Point3(-5.0, 0.0, 0.0), vec3::Vec3(1.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn test_default_intervals() {
+        let r = along_x();
+        let intervals = sphere(0.0, 1.0).intervals(&r, 0.001, f32::INFINITY);
+        assert!(intervals.len() == 1);
+        assert!((intervals[0].t_enter - 4.0).abs() < 1e-5);
+        assert!((intervals[0].t_exit - 6.0).abs() < 1e-5);
+
+        // Starting inside there is no entry.
+        let intervals = sphere(0.0, 1.0).intervals(&r, 5.0, f32::INFINITY);
+        assert!(intervals.len() == 1 && intervals[0].enter.is_none());
+        assert!(intervals[0].t_enter == 5.0);
+    }
+
+    #[test]
+    fn test_distant_intervals() {
+        // Far enough away that a fixed step past each hit would be lost in rounding.
+        let r = ray::Ray::new(vec3::Point3(-5000.0, 0.0, 0.0), vec3::Vec3(1.0, 0.0, 0.0));
+        let intervals = sphere(0.0, 1000.0).intervals(&r, 0.001, f32::INFINITY);
+        assert!(intervals.len() == 1);
+        assert!((intervals[0].t_enter - 4000.0).abs() < 1e-2);
+        assert!((intervals[0].t_exit - 6000.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_csg_operations() {
+        let r = along_x();
+        let t_max = f32::INFINITY;
+
+        // Two unit spheres overlapping between x = 0 and x = 1.
+        let union = Csg::union(sphere(0.0, 1.0), sphere(1.0, 1.0));
+        let intervals = union.intervals(&r, 0.001, t_max);
+        assert!(intervals.len() == 1);
+        assert!((intervals[0].t_enter - 4.0).abs() < 1e-5);
+        assert!((intervals[0].t_exit - 7.0).abs() < 1e-5);
+
+        // The lens between them.
+        let lens = Csg::intersection(sphere(0.0, 1.0), sphere(1.0, 1.0));
+        let hit_record = lens.hit(&r, 0.001, t_max).unwrap();
+        assert!((hit_record.t - 5.0).abs() < 1e-5);
+        assert!(hit_record.front_face);
+        assert!((hit_record.normal - vec3::Vec3(-1.0, 0.0, 0.0)).norm() < 1e-5);
+        let hit_record = lens.hit(&r, 5.5, t_max).unwrap();
+        assert!((hit_record.t - 6.0).abs() < 1e-5 && !hit_record.front_face);
+
+        // A bite taken out of the right of the first sphere: the bitten surface is
+        // the inside of the second sphere turned outwards.
+        let bitten = Csg::difference(sphere(0.0, 1.0), sphere(1.0, 1.0));
+        let intervals = bitten.intervals(&r, 0.001, t_max);
+        assert!(intervals.len() == 1);
+        assert!((intervals[0].t_exit - 5.0).abs() < 1e-5);
+        let exit = intervals[0].exit.as_ref().unwrap();
+        assert!(!exit.front_face);
+        assert!((exit.outward_normal() - vec3::Vec3(1.0, 0.0, 0.0)).norm() < 1e-5);
+
+        // A hollow shell, hit again on the far side of the cavity.
+        let shell = Csg::difference(sphere(0.0, 2.0), sphere(0.0, 1.0));
+        assert!(shell.intervals(&r, 0.001, t_max).len() == 2);
+        let hit_record = shell.hit(&r, 3.5, t_max).unwrap();
+        assert!((hit_record.t - 4.0).abs() < 1e-5 && !hit_record.front_face);
+        let hit_record = shell.hit(&r, 4.5, t_max).unwrap();
+        assert!((hit_record.t - 6.0).abs() < 1e-5 && hit_record.front_face);
+        assert!((hit_record.normal - vec3::Vec3(-1.0, 0.0, 0.0)).norm() < 1e-5);
+
+        let miss = ray::Ray::new(vec3::Point3(-5.0, 3.0, 0.0), vec3::Vec3(1.0, 0.0, 0.0));
+        assert!(union.hit(&miss, 0.001, t_max).is_none());
+    }
+}
@@ -0,0 +1,343 @@
+use super::aabb::Aabb;
+use super::hittable;
+use super::material;
+use super::ray;
+use super::vec3;
+use std::option::Option;
+use std::rc::Rc;
+
+// How the width of a curve is turned into a surface.
+// Flat: A strip always facing the ray, the usual choice for hair and fur.
+// Cylinder: Also facing the ray, with normals curving around as for a tube.
+// Ribbon: A strip facing along normals given at both ends, for grass and leaves.
+#[derive(Debug, Copy, Clone)]
+pub enum CurveKind {
+    Flat,
+    Cylinder,
+    Ribbon([vec3::Vec3; 2]),
+}
+
+// A cubic Bezier curve with a width varying linearly from one end to the other.
+// Intersection follows Nakamaru and Ohno 2002: in a frame where the ray is the z
+// axis, the curve is split until its pieces are nearly straight, which are then
+// tested for passing within half the width of the ray.
+// u: Position along the curve, from 0 to 1.
+// v: Position across the width, from 0 to 1.
+pub struct Curve {
+    control_points: [vec3::Point3; 4],
+    width: [f32; 2],
+    kind: CurveKind,
+    material: Rc<dyn material::Material>,
+}
+
+// A hit on the curve in ray space.
+struct CurveHit {
+    u: f32,
+    v: f32,
+    width: f32,
+}
+
+// The state of the search for the closest hit while splitting the curve.
+struct Search {
+    frame: vec3::Frame,
+    z_min: f32,
+    z_max: f32,
+    closest: Option<CurveHit>,
+}
+
+// Point and derivative of a cubic Bezier curve, using de Casteljau's algorithm.
+fn bezier(control_points: &[vec3::Point3; 4], u: f32) -> (vec3::Point3, vec3::Vec3) {
+    let lerp = |a: vec3::Point3, b: vec3::Point3| a + (b - a) * u;
+    let [p0, p1, p2, p3] = *control_points;
+    let (a, b, c) = (lerp(p0, p1), lerp(p1, p2), lerp(p2, p3));
+    let (d, e) = (lerp(a, b), lerp(b, c));
+    let derivative = if (e - d).near_zero() {
+        p3 - p0
+    } else {
+        (e - d) * 3.0
+    };
+    (lerp(d, e), derivative)
+}
+
+// The two halves of a curve, split at the middle.
+fn split(control_points: &[vec3::Point3; 4]) -> ([vec3::Point3; 4], [vec3::Point3; 4]) {
+    let mid = |a: vec3::Point3, b: vec3::Point3| (a + b) * 0.5;
+    let [p0, p1, p2, p3] = *control_points;
+    let (a, b, c) = (mid(p0, p1), mid(p1, p2), mid(p2, p3));
+    let (d, e) = (mid(a, b), mid(b, c));
+    let f = mid(d, e);
+    ([p0, a, d, f], [f, e, c, p3])
+}
+
+// Rotate v by angle around the unit axis, with Rodrigues' formula.
+fn rotate(v: vec3::Vec3, axis: vec3::Vec3, angle: f32) -> vec3::Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    v * cos + axis.cross(v) * sin + axis * (axis.dot(v) * (1.0 - cos))
+}
+
+impl Curve {
+    pub fn new(
+        control_points: [vec3::Point3; 4],
+        start_width: f32,
+        end_width: f32,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        Curve {
+            control_points,
+            width: [start_width, end_width],
+            kind: CurveKind::Flat,
+            material,
+        }
+    }
+
+    pub fn cylinder(
+        control_points: [vec3::Point3; 4],
+        start_width: f32,
+        end_width: f32,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        Curve {
+            kind: CurveKind::Cylinder,
+            ..Curve::new(control_points, start_width, end_width, material)
+        }
+    }
+
+    pub fn ribbon(
+        control_points: [vec3::Point3; 4],
+        normals: [vec3::Vec3; 2],
+        start_width: f32,
+        end_width: f32,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        Curve {
+            kind: CurveKind::Ribbon([normals[0].unit_vector(), normals[1].unit_vector()]),
+            ..Curve::new(control_points, start_width, end_width, material)
+        }
+    }
+
+    fn width_at(&self, u: f32) -> f32 {
+        self.width[0] + (self.width[1] - self.width[0]) * u
+    }
+
+    // Normal of a ribbon, spherically interpolated between the two ends.
+    fn ribbon_normal(normals: &[vec3::Vec3; 2], u: f32) -> vec3::Vec3 {
+        let cos_angle = normals[0].dot(normals[1]).clamp(-1.0, 1.0);
+        let angle = cos_angle.acos();
+        if angle < 1e-4 {
+            return normals[0];
+        }
+        let sin_angle = angle.sin();
+        (normals[0] * ((1.0 - u) * angle).sin() + normals[1] * (u * angle).sin()) / sin_angle
+    }
+
+    // Look for hits on the piece of curve between u0 and u1, given in ray space.
+    fn hit_piece(
+        &self,
+        control_points: &[vec3::Point3; 4],
+        u0: f32,
+        u1: f32,
+        depth: usize,
+        search: &mut Search,
+    ) {
+        let (z_min, z_max) = (search.z_min, search.z_max);
+        let half_width = 0.5 * self.width_at(u0).max(self.width_at(u1));
+        let bounds = Aabb::from_points(control_points.iter().cloned()).unwrap();
+        if bounds.min.x() - half_width > 0.0
+            || bounds.max.x() + half_width < 0.0
+            || bounds.min.y() - half_width > 0.0
+            || bounds.max.y() + half_width < 0.0
+            || bounds.min.z() - half_width > z_max
+            || bounds.max.z() + half_width < z_min
+        {
+            return;
+        }
+
+        if depth > 0 {
+            let (first, second) = split(control_points);
+            let middle = 0.5 * (u0 + u1);
+            self.hit_piece(&first, u0, middle, depth - 1, search);
+            self.hit_piece(&second, middle, u1, depth - 1, search);
+            return;
+        }
+
+        // The piece is close to straight. The ray must pass between the lines
+        // perpendicular to it at both ends, or the neighbouring piece is the one hit.
+        let [p0, p1, p2, p3] = *control_points;
+        if (p1.y() - p0.y()) * -p0.y() + p0.x() * (p0.x() - p1.x()) < 0.0
+            || (p2.y() - p3.y()) * -p3.y() + p3.x() * (p3.x() - p2.x()) < 0.0
+        {
+            return;
+        }
+        let segment = vec3::Vec3(p3.x() - p0.x(), p3.y() - p0.y(), 0.0);
+        let length_squared = segment.norm_squared();
+        if length_squared == 0.0 {
+            return;
+        }
+        let w = -vec3::Vec3(p0.x(), p0.y(), 0.0).dot(segment) / length_squared;
+        let u = (u0 + (u1 - u0) * w).clamp(u0, u1);
+
+        let mut width = self.width_at(u);
+        if let CurveKind::Ribbon(normals) = &self.kind {
+            // Ribbons seen edge on are narrower.
+            let normal = search.frame.to_local(&Curve::ribbon_normal(normals, u));
+            width *= normal.z().abs();
+        }
+        let (p, tangent) = bezier(control_points, w.clamp(0.0, 1.0));
+        let distance_squared = p.x() * p.x() + p.y() * p.y();
+        if distance_squared > 0.25 * width * width || p.z() < z_min || p.z() > z_max {
+            return;
+        }
+
+        // v grows towards the side of the curve to the right of the tangent.
+        let distance = distance_squared.sqrt();
+        let left = tangent.x() * -p.y() + p.x() * tangent.y() > 0.0;
+        let v = if left {
+            0.5 - distance / width
+        } else {
+            0.5 + distance / width
+        };
+        search.z_max = p.z();
+        search.closest = Some(CurveHit { u, v, width });
+    }
+}
+
+impl hittable::Hittable for Curve {
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<hittable::HitRecord> {
+        // In ray space the ray starts at the origin going along z with unit speed.
+        let length = r.direction.norm();
+        let frame = vec3::Frame::from_normal(&(r.direction / length));
+        let local = self.control_points.map(|p| frame.to_local(&(p - r.origin)));
+
+        // Split until the pieces are within a twentieth of the width of a line.
+        let curvature = (0..2)
+            .map(|i| {
+                let second = local[i] - local[i + 1] * 2.0 + local[i + 2];
+                second.x().abs().max(second.y().abs()).max(second.z().abs())
+            })
+            .fold(0.0, f32::max);
+        let epsilon = 0.05 * self.width[0].max(self.width[1]);
+        let ratio = std::f32::consts::SQRT_2 * 6.0 * curvature / (8.0 * epsilon);
+        let depth = if ratio > 1.0 {
+            (ratio.log2() as usize / 2).min(10)
+        } else {
+            0
+        };
+
+        let mut search = Search {
+            frame,
+            z_min: t_min * length,
+            z_max: t_max * length,
+            closest: None,
+        };
+        self.hit_piece(&local, 0.0, 1.0, depth, &mut search);
+        let hit = search.closest?;
+
+        let t = search.z_max / length;
+        let (_, dpdu) = bezier(&self.control_points, hit.u);
+        let dpdv = match &self.kind {
+            CurveKind::Ribbon(normals) => {
+                Curve::ribbon_normal(normals, hit.u)
+                    .cross(dpdu)
+                    .unit_vector()
+                    * hit.width
+            }
+            kind => {
+                // Across the curve in the plane facing the ray, so the normal faces
+                // back along it.
+                let tangent = search.frame.to_local(&dpdu);
+                let across = vec3::Vec3(tangent.y(), -tangent.x(), 0.0).unit_vector() * hit.width;
+                let across = if let CurveKind::Cylinder = kind {
+                    // Turn the normal around the tangent, from one edge to the other.
+                    let angle = (0.5 - hit.v) * std::f32::consts::PI;
+                    rotate(across, tangent.unit_vector(), angle)
+                } else {
+                    across
+                };
+                search.frame.to_world(&across)
+            }
+        };
+        let outward_normal = dpdu.cross(dpdv).unit_vector();
+
+        let mut hit_record =
+            hittable::HitRecord::new(t, r.at(t), r, outward_normal, self.material.clone());
+        hit_record.u = hit.u;
+        hit_record.v = hit.v;
+        hit_record.dpdu = dpdu;
+        hit_record.dpdv = dpdv;
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let aabb = Aabb::from_points(self.control_points.iter().cloned())?;
+        let half_width = 0.5 * self.width[0].max(self.width[1]);
+        let extent = vec3::Vec3(half_width, half_width, half_width);
+        Some(Aabb::new(aabb.min - extent, aabb.max + extent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{check_tangents, lambertian};
+    use crate::hittable::Hittable;
+
+    // An arc in the xy plane from x = -1 to 1, bulging up to y = 0.75.
+    fn arc() -> [vec3::Point3; 4] {
+        [
+            vec3::Point3(-1.0, 0.0, 0.0),
+            vec3::Point3(-0.5, 1.0, 0.0),
+            vec3::Point3(0.5, 1.0, 0.0),
+            vec3::Point3(1.0, 0.0, 0.0),
+        ]
+    }
+
+    #[test]
+    fn test_curve_hit() {
+        let curve = Curve::new(arc(), 0.2, 0.1, lambertian());
+        // The top of the arc, seen from +z.
+        let r = ray::Ray::new(vec3::Point3(0.0, 0.76, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        let hit_record = curve.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 5.0).abs() < 1e-3);
+        assert!((hit_record.u - 0.5).abs() < 1e-2);
+        // Facing the ray, with dpdu along the curve.
+        assert!(hit_record.front_face);
+        assert!((hit_record.normal - vec3::Vec3(0.0, 0.0, 1.0)).norm() < 1e-3);
+        assert!(hit_record.dpdu.x() > 0.0 && hit_record.dpdu.y().abs() < 1e-2);
+        assert!(hit_record.v > 0.5);
+
+        // Just outside the width, which is 0.15 there.
+        let miss = ray::Ray::new(vec3::Point3(0.0, 0.84, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        assert!(curve.hit(&miss, 0.001, f32::INFINITY).is_none());
+        assert!(curve.hit(&r, 0.001, 4.0).is_none());
+
+        // The sides of a tube face away from its middle.
+        let tube = Curve::cylinder(arc(), 0.2, 0.2, lambertian());
+        let hit_record = tube.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!(hit_record.normal.y() > 0.0 && hit_record.normal.z() > 0.0);
+    }
+
+    #[test]
+    fn test_ribbon_hit() {
+        // Facing +y, so seen edge on from +z and face on from above.
+        let ribbon = Curve::ribbon(
+            [
+                vec3::Point3(-1.0, 0.0, 0.0),
+                vec3::Point3(-0.3, 0.0, 0.0),
+                vec3::Point3(0.3, 0.0, 0.0),
+                vec3::Point3(1.0, 0.0, 0.0),
+            ],
+            [vec3::Vec3(0.0, 1.0, 0.0), vec3::Vec3(0.0, 1.0, 0.0)],
+            0.5,
+            0.5,
+            lambertian(),
+        );
+        let down = ray::Ray::new(vec3::Point3(0.2, 2.0, 0.2), vec3::Vec3(0.0, -1.0, 0.0));
+        let hit_record = ribbon.hit(&down, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 2.0).abs() < 1e-3);
+        assert!((hit_record.normal - vec3::Vec3(0.0, 1.0, 0.0)).norm() < 1e-3);
+        check_tangents(&hit_record);
+
+        let edge_on = ray::Ray::new(vec3::Point3(0.2, 0.2, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        assert!(ribbon.hit(&edge_on, 0.001, f32::INFINITY).is_none());
+    }
+}
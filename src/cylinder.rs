@@ -0,0 +1,175 @@
+use super::aabb::Aabb;
+use super::disk::Disk;
+use super::hittable;
+use super::material;
+use super::ray;
+use super::util;
+use super::vec3;
+use std::f32::consts::PI;
+use std::option::Option;
+use std::rc::Rc;
+
+// A cylinder standing on its base center and rising along axis, closed by two
+// disks unless made open.
+// u: Angle around the axis.
+// v: From the base at 0 to the top at 1.
+pub struct Cylinder {
+    base: vec3::Point3,
+    frame: vec3::Frame,
+    radius: f32,
+    height: f32,
+    caps: Vec<Disk>,
+    material: Rc<dyn material::Material>,
+}
+
+impl Cylinder {
+    pub fn new(
+        base: vec3::Point3,
+        axis: vec3::Vec3,
+        radius: f32,
+        height: f32,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        let axis = axis.unit_vector();
+        let caps = vec![
+            Disk::new(base, -axis, radius, material.clone()),
+            Disk::new(base + axis * height, axis, radius, material.clone()),
+        ];
+        Cylinder {
+            caps,
+            ..Cylinder::open(base, axis, radius, height, material)
+        }
+    }
+
+    // Only the curved side, like a tube.
+    pub fn open(
+        base: vec3::Point3,
+        axis: vec3::Vec3,
+        radius: f32,
+        height: f32,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        Cylinder {
+            base,
+            frame: vec3::Frame::from_normal(&axis.unit_vector()),
+            radius,
+            height,
+            caps: vec![],
+            material,
+        }
+    }
+
+    fn hit_side(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<hittable::HitRecord> {
+        let local = r.to_local(&self.base, &self.frame);
+        let (o, d) = (local.origin, local.direction);
+        let a = d.x() * d.x() + d.y() * d.y();
+        if a == 0.0 {
+            return None;
+        }
+        let half_b = o.x() * d.x() + o.y() * d.y();
+        let c = o.x() * o.x() + o.y() * o.y() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrt_discriminant = discriminant.sqrt();
+
+        // Nearest root in range whose point lies between the base and the top.
+        let t = [
+            (-half_b - sqrt_discriminant) / a,
+            (-half_b + sqrt_discriminant) / a,
+        ]
+        .iter()
+        .cloned()
+        .find(|&t| {
+            let z = local.at(t).z();
+            t >= t_min && t <= t_max && z >= 0.0 && z <= self.height
+        })?;
+
+        let p = local.at(t);
+        let outward_normal = vec3::Vec3(p.x(), p.y(), 0.0) / self.radius;
+        let mut hit_record = hittable::HitRecord::new(
+            t,
+            r.at(t),
+            r,
+            self.frame.to_world(&outward_normal),
+            self.material.clone(),
+        );
+        let (_, u) = util::turn(p.y(), p.x());
+        hit_record.u = u;
+        hit_record.v = p.z() / self.height;
+        hit_record.dpdu = self
+            .frame
+            .to_world(&(vec3::Vec3(-p.y(), p.x(), 0.0) * (2.0 * PI)));
+        hit_record.dpdv = self.frame.normal * self.height;
+        Some(hit_record)
+    }
+}
+
+impl hittable::Hittable for Cylinder {
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<hittable::HitRecord> {
+        let mut final_record = self.hit_side(r, t_min, t_max);
+        for cap in self.caps.iter() {
+            let closest_so_far = final_record.as_ref().map_or(t_max, |record| record.t);
+            if let Some(hit_record) = cap.hit(r, t_min, closest_so_far) {
+                final_record = Some(hit_record);
+            }
+        }
+        final_record
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = Aabb::new(
+            vec3::Point3(-self.radius, -self.radius, 0.0),
+            vec3::Point3(self.radius, self.radius, self.height),
+        );
+        Some(local.to_world(&self.base, &self.frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{check_tangents, lambertian};
+    use crate::hittable::Hittable;
+
+    #[test]
+    fn test_cylinder_hit() {
+        let cylinder = Cylinder::new(
+            vec3::Point3(0.0, 0.0, 0.0),
+            vec3::Vec3(0.0, 1.0, 0.0),
+            1.0,
+            2.0,
+            lambertian(),
+        );
+
+        // The side, from outside.
+        let r = ray::Ray::new(vec3::Point3(0.0, 0.5, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        let hit_record = cylinder.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 4.0).abs() < 1e-5);
+        assert!((hit_record.normal - vec3::Vec3(0.0, 0.0, 1.0)).norm() < 1e-5);
+        assert!((hit_record.v - 0.25).abs() < 1e-5);
+        check_tangents(&hit_record);
+
+        // The top cap, and the inside of the open tube.
+        let down = ray::Ray::new(vec3::Point3(0.5, 5.0, 0.0), vec3::Vec3(0.0, -1.0, 0.0));
+        let hit_record = cylinder.hit(&down, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 3.0).abs() < 1e-5);
+        assert!((hit_record.normal - vec3::Vec3(0.0, 1.0, 0.0)).norm() < 1e-5);
+        let tube = Cylinder::open(
+            vec3::Point3(0.0, 0.0, 0.0),
+            vec3::Vec3(0.0, 1.0, 0.0),
+            1.0,
+            2.0,
+            lambertian(),
+        );
+        let inside = ray::Ray::new(vec3::Point3(0.0, 1.0, 0.0), vec3::Vec3(1.0, 0.0, 0.0));
+        let hit_record = tube.hit(&inside, 0.001, f32::INFINITY).unwrap();
+        assert!(!hit_record.front_face);
+        assert!(tube.hit(&down, 0.001, f32::INFINITY).is_none());
+
+        let aabb = cylinder.bounding_box().unwrap();
+        assert!((aabb.min - vec3::Point3(-1.0, 0.0, -1.0)).norm() < 1e-5);
+        assert!((aabb.max - vec3::Point3(1.0, 2.0, 1.0)).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,176 @@
+use super::aov::AovBuffers;
+use super::color;
+use super::framebuffer::Framebuffer;
+
+// Edge-avoiding A-Trous wavelet filter, see Dammertz et al. 2010.
+// iterations: Number of filter passes, each doubling the filter footprint.
+// sigma_color: How strongly differences in (albedo demodulated) color stop the blur.
+// sigma_normal: How strongly differences in shading normal stop the blur.
+// sigma_depth: How strongly relative differences in depth stop the blur.
+#[derive(Debug, Copy, Clone)]
+pub struct DenoiseSettings {
+    pub iterations: usize,
+    pub sigma_color: f32,
+    pub sigma_normal: f32,
+    pub sigma_depth: f32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            sigma_color: 0.6,
+            sigma_normal: 0.3,
+            sigma_depth: 0.1,
+        }
+    }
+}
+
+// B3 spline kernel weights.
+static KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Albedo values below this don't demodulate, so textures of pure black don't blow up.
+static MIN_ALBEDO: f32 = 0.01;
+
+fn demodulation_factor(albedo: color::Color) -> color::Color {
+    let factor = |a: f32| if a < MIN_ALBEDO { 1.0 } else { a };
+    color::Color(factor(albedo.0), factor(albedo.1), factor(albedo.2))
+}
+
+fn divide(lhs: color::Color, rhs: color::Color) -> color::Color {
+    color::Color(lhs.0 / rhs.0, lhs.1 / rhs.1, lhs.2 / rhs.2)
+}
+
+// Run a single filter pass, with taps spaced step_width pixels apart.
+fn filter_pass(
+    image: &Framebuffer,
+    aovs: &AovBuffers,
+    step_width: usize,
+    sigma_color: f32,
+    settings: &DenoiseSettings,
+) -> Framebuffer {
+    let mut filtered = Framebuffer::new(image.width, image.height);
+    let step = step_width as isize;
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let center_color = image.get(x, y);
+            let center_normal = aovs.normal.get(x, y);
+            let center_depth = aovs.depth.get(x, y).0;
+
+            let mut sum = color::Color(0.0, 0.0, 0.0);
+            let mut weight_sum = 0.0;
+            for (ky, kernel_y) in KERNEL.iter().enumerate() {
+                for (kx, kernel_x) in KERNEL.iter().enumerate() {
+                    let qx = x as isize + (kx as isize - 2) * step;
+                    let qy = y as isize + (ky as isize - 2) * step;
+                    if qx < 0 || qy < 0 || qx >= image.width as isize || qy >= image.height as isize
+                    {
+                        continue;
+                    }
+                    let (qx, qy) = (qx as usize, qy as usize);
+
+                    let color = image.get(qx, qy);
+                    let color_distance = (color - center_color).norm_squared();
+                    let normal_distance = (aovs.normal.get(qx, qy) - center_normal).norm_squared();
+                    let depth_distance =
+                        (aovs.depth.get(qx, qy).0 - center_depth) / center_depth.max(f32::EPSILON);
+
+                    let weight = kernel_x
+                        * kernel_y
+                        * (-color_distance / (sigma_color * sigma_color)).exp()
+                        * (-normal_distance / (settings.sigma_normal * settings.sigma_normal))
+                            .exp()
+                        * (-(depth_distance * depth_distance)
+                            / (settings.sigma_depth * settings.sigma_depth))
+                            .exp();
+
+                    sum = sum + color * weight;
+                    weight_sum += weight;
+                }
+            }
+            // The center tap always contributes, so weight_sum is never zero.
+            filtered.set(x, y, sum / weight_sum);
+        }
+    }
+    filtered
+}
+
+// Denoise a rendered image, using the first hit AOVs to preserve edges.
+// Lighting is filtered separately from albedo so texture detail stays sharp.
+pub fn denoise(image: &Framebuffer, aovs: &AovBuffers, settings: &DenoiseSettings) -> Framebuffer {
+    let mut irradiance = Framebuffer::new(image.width, image.height);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let factor = demodulation_factor(aovs.albedo.get(x, y));
+            irradiance.set(x, y, divide(image.get(x, y), factor));
+        }
+    }
+
+    let mut sigma_color = settings.sigma_color;
+    for iteration in 0..settings.iterations {
+        irradiance = filter_pass(&irradiance, aovs, 1 << iteration, sigma_color, settings);
+        // Later passes mix already smoothed values, so be stricter about color differences.
+        sigma_color *= 0.5;
+    }
+
+    let mut denoised = Framebuffer::new(image.width, image.height);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let factor = demodulation_factor(aovs.albedo.get(x, y));
+            denoised.set(x, y, irradiance.get(x, y) * factor);
+        }
+    }
+    denoised
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_image_unchanged() {
+        let mut image = Framebuffer::new(8, 8);
+        let mut aovs = AovBuffers::new(8, 8);
+        for y in 0..8 {
+            for x in 0..8 {
+                image.set(x, y, color::Color(0.25, 0.5, 0.75));
+                aovs.albedo.set(x, y, color::Color(0.5, 0.5, 0.5));
+                aovs.depth.set(x, y, color::Color(2.0, 2.0, 2.0));
+            }
+        }
+
+        let denoised = denoise(&image, &aovs, &DenoiseSettings::default());
+        for pixel in denoised.pixels() {
+            assert!((*pixel - color::Color(0.25, 0.5, 0.75)).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_noise_reduced_but_edges_kept() {
+        let mut image = Framebuffer::new(16, 16);
+        let mut aovs = AovBuffers::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                // Left half faces up, right half faces the camera.
+                let normal = if x < 8 {
+                    color::Color(0.0, 1.0, 0.0)
+                } else {
+                    color::Color(0.0, 0.0, 1.0)
+                };
+                let noise = if (x + y) % 2 == 0 { 0.1 } else { -0.1 };
+                let base = if x < 8 { 0.2 } else { 0.8 };
+                image.set(x, y, color::Color(base, base, base) + color::WHITE * noise);
+                aovs.normal.set(x, y, normal);
+                aovs.depth.set(x, y, color::Color(1.0, 1.0, 1.0));
+            }
+        }
+
+        let denoised = denoise(&image, &aovs, &DenoiseSettings::default());
+        assert!((denoised.get(3, 8).0 - 0.2).abs() < 0.05);
+        assert!((denoised.get(12, 8).0 - 0.8).abs() < 0.05);
+        // Pixels next to the edge don't bleed into each other.
+        assert!(denoised.get(7, 8).0 < 0.35);
+        assert!(denoised.get(8, 8).0 > 0.65);
+    }
+}
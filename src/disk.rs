@@ -0,0 +1,131 @@
+use super::aabb::Aabb;
+use super::hittable;
+use super::material;
+use super::ray;
+use super::util;
+use super::vec3;
+use std::f32::consts::PI;
+use std::option::Option;
+use std::rc::Rc;
+
+// A flat disk facing along its normal, or an annulus when the inner radius is
+// positive.
+// u: Angle around the normal.
+// v: From the outer edge at 0 to the inner edge at 1.
+pub struct Disk {
+    center: vec3::Point3,
+    frame: vec3::Frame,
+    radius: f32,
+    inner_radius: f32,
+    material: Rc<dyn material::Material>,
+}
+
+impl Disk {
+    pub fn new(
+        center: vec3::Point3,
+        normal: vec3::Vec3,
+        radius: f32,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        Disk {
+            center,
+            frame: vec3::Frame::from_normal(&normal.unit_vector()),
+            radius,
+            inner_radius: 0.0,
+            material,
+        }
+    }
+
+    pub fn annulus(
+        center: vec3::Point3,
+        normal: vec3::Vec3,
+        radius: f32,
+        inner_radius: f32,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        Disk {
+            inner_radius,
+            ..Disk::new(center, normal, radius, material)
+        }
+    }
+}
+
+impl hittable::Hittable for Disk {
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<hittable::HitRecord> {
+        let local = r.to_local(&self.center, &self.frame);
+        if local.direction.z() == 0.0 {
+            return None;
+        }
+        let t = -local.origin.z() / local.direction.z();
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let p = local.at(t);
+        let distance = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        if distance > self.radius || distance < self.inner_radius {
+            return None;
+        }
+
+        let mut hit_record =
+            hittable::HitRecord::new(t, r.at(t), r, self.frame.normal, self.material.clone());
+        let (phi, u) = util::turn(p.y(), p.x());
+        let width = self.radius - self.inner_radius;
+        hit_record.u = u;
+        hit_record.v = (self.radius - distance) / width;
+        // Moving in v heads inwards, which keeps dpdu x dpdv along the normal.
+        let (dpdu, dpdv) = if distance > 0.0 {
+            (
+                vec3::Vec3(-p.y(), p.x(), 0.0) * (2.0 * PI),
+                vec3::Vec3(p.x(), p.y(), 0.0) * (-width / distance),
+            )
+        } else {
+            (vec3::Vec3(0.0, 2.0 * PI, 0.0), vec3::Vec3(width, 0.0, 0.0))
+        };
+        hit_record.dpdu = self.frame.to_world(&dpdu);
+        hit_record.dpdv = self.frame.to_world(&dpdv);
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = Aabb::new(
+            vec3::Point3(-self.radius, -self.radius, 0.0),
+            vec3::Point3(self.radius, self.radius, 0.0),
+        );
+        Some(local.to_world(&self.center, &self.frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{check_tangents, lambertian};
+    use crate::hittable::Hittable;
+
+    #[test]
+    fn test_annulus_hit() {
+        let annulus = Disk::annulus(
+            vec3::Point3(0.0, 1.0, 0.0),
+            vec3::Vec3(0.0, 2.0, 0.0),
+            2.0,
+            1.0,
+            lambertian(),
+        );
+        let r = ray::Ray::new(vec3::Point3(1.5, 3.0, 0.0), vec3::Vec3(0.0, -1.0, 0.0));
+        let hit_record = annulus.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!(hit_record.t == 2.0);
+        assert!(hit_record.front_face);
+        assert!((hit_record.normal - vec3::Vec3(0.0, 1.0, 0.0)).norm() < 1e-6);
+        assert!((hit_record.v - 0.5).abs() < 1e-6);
+        check_tangents(&hit_record);
+
+        // Through the hole and outside the rim.
+        let hole = ray::Ray::new(vec3::Point3(0.5, 3.0, 0.0), vec3::Vec3(0.0, -1.0, 0.0));
+        assert!(annulus.hit(&hole, 0.001, f32::INFINITY).is_none());
+        let outside = ray::Ray::new(vec3::Point3(2.5, 3.0, 0.0), vec3::Vec3(0.0, -1.0, 0.0));
+        assert!(annulus.hit(&outside, 0.001, f32::INFINITY).is_none());
+
+        let aabb = annulus.bounding_box().unwrap();
+        assert!((aabb.min - vec3::Point3(-2.0, 1.0, -2.0)).norm() < 1e-5);
+        assert!((aabb.max - vec3::Point3(2.0, 1.0, 2.0)).norm() < 1e-5);
+    }
+}
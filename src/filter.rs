@@ -0,0 +1,148 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+// Pixel reconstruction filter shapes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    // Mitchell-Netravali cubic with B = C = 1/3.
+    Mitchell,
+    // Sinc windowed by a wider sinc.
+    Lanczos,
+}
+
+impl FilterKind {
+    // Radius in pixels used when none is configured.
+    pub fn default_radius(&self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(FilterKind::Box),
+            "tent" => Ok(FilterKind::Tent),
+            "gaussian" => Ok(FilterKind::Gaussian),
+            "mitchell" => Ok(FilterKind::Mitchell),
+            "lanczos" => Ok(FilterKind::Lanczos),
+            _ => Err(format!("unknown filter {}", s)),
+        }
+    }
+}
+
+// A separable filter, weighting samples by their offset from a pixel center.
+// radius: Distance in pixels past which samples have no influence.
+#[derive(Debug, Copy, Clone)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f32,
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn mitchell(x: f32) -> f32 {
+    let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind, radius: f32) -> Self {
+        Filter { kind, radius }
+    }
+
+    // Filter weight along a single axis, for an offset in pixels.
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        if x >= self.radius {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / self.radius,
+            FilterKind::Gaussian => {
+                // Shifted down so the weight reaches zero at the radius.
+                let alpha = 2.0;
+                (-alpha * x * x).exp() - (-alpha * self.radius * self.radius).exp()
+            }
+            // The Mitchell cubic is defined over [-2, 2].
+            FilterKind::Mitchell => mitchell(2.0 * x / self.radius),
+            FilterKind::Lanczos => sinc(x) * sinc(x / self.radius),
+        }
+    }
+
+    // Filter weight for a sample offset by (x, y) pixels from a pixel center.
+    pub fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::new(FilterKind::Box, FilterKind::Box.default_radius())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_vanish_at_radius() {
+        for kind in [
+            FilterKind::Box,
+            FilterKind::Tent,
+            FilterKind::Gaussian,
+            FilterKind::Mitchell,
+            FilterKind::Lanczos,
+        ] {
+            let filter = Filter::new(kind, kind.default_radius());
+            assert!(filter.evaluate(0.0, 0.0) > 0.0);
+            assert!(filter.evaluate(filter.radius, 0.0) == 0.0);
+            assert!(filter.evaluate(0.0, -filter.radius) == 0.0);
+        }
+    }
+
+    #[test]
+    fn test_filter_shapes() {
+        let tent = Filter::new(FilterKind::Tent, 2.0);
+        assert!(tent.evaluate(1.0, 0.0) == 0.5);
+
+        // Mitchell and Lanczos have negative lobes, which sharpen the image.
+        let mitchell = Filter::new(FilterKind::Mitchell, 2.0);
+        assert!(mitchell.evaluate(1.5, 0.0) < 0.0);
+        let lanczos = Filter::new(FilterKind::Lanczos, 3.0);
+        assert!(lanczos.evaluate(1.5, 0.0) < 0.0);
+        assert!(lanczos.evaluate(1.0, 0.0).abs() < 1e-5);
+    }
+}
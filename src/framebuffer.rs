@@ -0,0 +1,95 @@
+use super::color;
+use super::filter;
+
+// A floating point image, stored row by row starting from the top-left pixel.
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<color::Color>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![color::Color(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> color::Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, pixel_color: color::Color) {
+        self.pixels[y * self.width + x] = pixel_color;
+    }
+
+    pub fn add(&mut self, x: usize, y: usize, pixel_color: color::Color) {
+        let index = y * self.width + x;
+        self.pixels[index] = self.pixels[index] + pixel_color;
+    }
+
+    pub fn pixels(&self) -> &[color::Color] {
+        &self.pixels
+    }
+}
+
+// Accumulates radiance samples into pixels, weighting each by a reconstruction filter.
+// A sample contributes to every pixel whose center lies within the filter radius.
+pub struct Film {
+    filter: filter::Filter,
+    weighted_sum: Framebuffer,
+    weights: Vec<f32>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: filter::Filter) -> Self {
+        Film {
+            filter,
+            weighted_sum: Framebuffer::new(width, height),
+            weights: vec![0.0; width * height],
+        }
+    }
+
+    // Add a sample at continuous image coordinates (x, y), measured in pixels from the
+    // top-left corner of the image. Pixel (i, j) has its center at (i + 0.5, j + 0.5).
+    pub fn add_sample(&mut self, x: f32, y: f32, sample_color: color::Color) {
+        let width = self.weighted_sum.width;
+        let height = self.weighted_sum.height;
+
+        let x_min = (x - 0.5 - self.filter.radius).ceil().max(0.0) as usize;
+        let x_max = ((x - 0.5 + self.filter.radius).floor() as isize).min(width as isize - 1);
+        let y_min = (y - 0.5 - self.filter.radius).ceil().max(0.0) as usize;
+        let y_max = ((y - 0.5 + self.filter.radius).floor() as isize).min(height as isize - 1);
+
+        for j in y_min as isize..=y_max {
+            for i in x_min as isize..=x_max {
+                let (i, j) = (i as usize, j as usize);
+                let weight = self.filter.evaluate(i as f32 + 0.5 - x, j as f32 + 0.5 - y);
+                if weight == 0.0 {
+                    continue;
+                }
+                self.weighted_sum.add(i, j, sample_color * weight);
+                self.weights[j * width + i] += weight;
+            }
+        }
+    }
+
+    // Normalize the accumulated samples into the final image.
+    pub fn resolve(&self) -> Framebuffer {
+        let width = self.weighted_sum.width;
+        let height = self.weighted_sum.height;
+        let mut image = Framebuffer::new(width, height);
+        for j in 0..height {
+            for i in 0..width {
+                let weight = self.weights[j * width + i];
+                // Negative filter lobes can cancel out, leave those pixels black.
+                if weight.abs() > 1e-6 {
+                    image.set(i, j, self.weighted_sum.get(i, j) / weight);
+                }
+            }
+        }
+        image
+    }
+}
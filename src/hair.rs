@@ -0,0 +1,436 @@
+use super::color;
+use super::hittable;
+use super::material::{Evaluation, Material, Scattering};
+use super::microfacet;
+use super::ray;
+use super::util;
+use super::vec3;
+use std::f32::consts::PI;
+
+// Number of lobes traced through the fiber: R, TT and TRT, followed by one for all
+// the longer paths together.
+const P_MAX: usize = 3;
+
+// Absorption of the two pigments that give hair its color, per unit of concentration.
+static EUMELANIN_SIGMA_A: color::Color = color::Color(0.419, 0.697, 1.37);
+static PHEOMELANIN_SIGMA_A: color::Color = color::Color(0.187, 0.4, 1.05);
+
+// The hair scattering model of Chiang et al. 2016, "A Practical and Controllable Hair
+// and Fur Model for Production Path Tracing", after the version in pbrt. Light either
+// reflects off the fiber or refracts into it and out again after zero or more
+// internal reflections, each lobe split into a longitudinal and an azimuthal part.
+// Needs hits with dpdu along the fiber and v across it, as from flat curves.
+// sigma_a: Absorption coefficient inside the fiber, relative to its diameter.
+// beta_m: Longitudinal roughness, from 0 to 1.
+// beta_n: Azimuthal roughness, from 0 to 1.
+// alpha: Tilt of the cuticle scales, in degrees.
+pub struct Hair {
+    sigma_a: color::Color,
+    eta: f32,
+    beta_m: f32,
+    beta_n: f32,
+    alpha: f32,
+    // Longitudinal variance of each lobe.
+    v: [f32; P_MAX + 1],
+    // Azimuthal logistic scale.
+    s: f32,
+    // Sine and cosine of 2, 4 and 8 times alpha, tilting the lobes.
+    sin_2k_alpha: [f32; 3],
+    cos_2k_alpha: [f32; 3],
+}
+
+impl Hair {
+    pub fn new(sigma_a: color::Color, beta_m: f32, beta_n: f32, alpha: f32) -> Self {
+        let beta_m = beta_m.clamp(0.01, 1.0);
+        let beta_n = beta_n.clamp(0.01, 1.0);
+        let v0 = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let s =
+            (PI / 8.0).sqrt() * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+
+        let mut sin_2k_alpha = [0.0; 3];
+        let mut cos_2k_alpha = [0.0; 3];
+        sin_2k_alpha[0] = util::degrees_to_radians(alpha).sin();
+        cos_2k_alpha[0] = (1.0 - sin_2k_alpha[0].powi(2)).max(0.0).sqrt();
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+
+        Hair {
+            sigma_a,
+            eta: 1.55,
+            beta_m,
+            beta_n,
+            alpha,
+            v: [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0],
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    // Natural hair colors from the concentrations of the two melanins: little
+    // eumelanin gives blonde, around 1.3 brown and 8 black, while pheomelanin reddens.
+    pub fn from_melanin(eumelanin: f32, pheomelanin: f32, beta_m: f32, beta_n: f32) -> Self {
+        let sigma_a = EUMELANIN_SIGMA_A * eumelanin + PHEOMELANIN_SIGMA_A * pheomelanin;
+        Hair::new(sigma_a, beta_m, beta_n, 2.0)
+    }
+
+    // Hair whose multiply scattered color is roughly the given one, as fitted by
+    // Chiang et al.
+    pub fn from_color(color: color::Color, beta_m: f32, beta_n: f32) -> Self {
+        let beta_n = beta_n.clamp(0.01, 1.0);
+        let denominator = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4)
+            + 0.245 * beta_n.powi(5);
+        let sigma_a = |c: f32| (c.clamp(1e-4, 1.0).ln() / denominator).powi(2);
+        Hair::new(
+            color::Color(sigma_a(color.x()), sigma_a(color.y()), sigma_a(color.z())),
+            beta_m,
+            beta_n,
+            2.0,
+        )
+    }
+
+    // The direction along the fiber and across it, with the outward normal, giving
+    // local coordinates with x along the fiber.
+    fn frame(hit_record: &hittable::HitRecord) -> vec3::Frame {
+        let normal = hit_record.outward_normal();
+        let tangent = hit_record.dpdu - normal * normal.dot(hit_record.dpdu);
+        let tangent = if tangent.near_zero() {
+            vec3::orthonormal_basis(&normal).0
+        } else {
+            tangent.unit_vector()
+        };
+        vec3::Frame {
+            tangent,
+            bitangent: normal.cross(tangent),
+            normal,
+        }
+    }
+
+    // Elevation of the outgoing direction tilted by the scales for lobe p.
+    fn tilted(&self, p: usize, sin_theta_o: f32, cos_theta_o: f32) -> (f32, f32) {
+        let (sin_theta, cos_theta) = match p {
+            0 => (
+                sin_theta_o * self.cos_2k_alpha[1] - cos_theta_o * self.sin_2k_alpha[1],
+                cos_theta_o * self.cos_2k_alpha[1] + sin_theta_o * self.sin_2k_alpha[1],
+            ),
+            1 => (
+                sin_theta_o * self.cos_2k_alpha[0] + cos_theta_o * self.sin_2k_alpha[0],
+                cos_theta_o * self.cos_2k_alpha[0] - sin_theta_o * self.sin_2k_alpha[0],
+            ),
+            2 => (
+                sin_theta_o * self.cos_2k_alpha[2] + cos_theta_o * self.sin_2k_alpha[2],
+                cos_theta_o * self.cos_2k_alpha[2] - sin_theta_o * self.sin_2k_alpha[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin_theta, cos_theta.abs())
+    }
+
+    // Attenuation of each lobe, and the angle of the refracted ray around the fiber.
+    fn attenuations(&self, cos_theta_o: f32, h: f32) -> ([color::Color; P_MAX + 1], f32) {
+        let sin_theta_o = safe_sqrt(1.0 - cos_theta_o * cos_theta_o);
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+        // The modified index of refraction for the projection onto the normal plane.
+        let eta_p = (self.eta * self.eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o;
+        let sin_gamma_t = (h / eta_p).clamp(-1.0, 1.0);
+        let cos_gamma_t = safe_sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+        let gamma_t = sin_gamma_t.asin();
+
+        let distance = 2.0 * cos_gamma_t / cos_theta_t;
+        let transmittance = color::Color(
+            (-self.sigma_a.x() * distance).exp(),
+            (-self.sigma_a.y() * distance).exp(),
+            (-self.sigma_a.z() * distance).exp(),
+        );
+        let cos_gamma_o = safe_sqrt(1.0 - h * h);
+        let f = microfacet::fresnel_dielectric(cos_theta_o * cos_gamma_o, self.eta);
+
+        let r = color::WHITE * f;
+        let tt = transmittance * (1.0 - f) * (1.0 - f);
+        let trt = tt * transmittance * f;
+        // A geometric series for the paths with more internal reflections. At grazing
+        // angles nothing gets in, so there is no series to sum.
+        let series = |t: f32| {
+            if t * f < 1.0 {
+                t * f / (1.0 - t * f)
+            } else {
+                0.0
+            }
+        };
+        let rest = trt
+            * color::Color(
+                series(transmittance.x()),
+                series(transmittance.y()),
+                series(transmittance.z()),
+            );
+        ([r, tt, trt, rest], gamma_t)
+    }
+
+    // How likely each lobe is to be sampled, proportional to its attenuation.
+    fn lobe_pdfs(attenuations: &[color::Color; P_MAX + 1]) -> [f32; P_MAX + 1] {
+        let luminances = attenuations.map(color::luminance);
+        let total: f32 = luminances.iter().sum();
+        if total <= 0.0 {
+            return [1.0, 0.0, 0.0, 0.0];
+        }
+        luminances.map(|luminance| luminance / total)
+    }
+
+    // The BSDF times cosine and the sampling density for local directions, with h
+    // the offset across the fiber from -1 to 1.
+    fn evaluate(&self, wo: &vec3::Vec3, wi: &vec3::Vec3, h: f32) -> Evaluation {
+        let sin_theta_o = wo.x().clamp(-1.0, 1.0);
+        let cos_theta_o = safe_sqrt(1.0 - sin_theta_o * sin_theta_o);
+        let phi_o = wo.z().atan2(wo.y());
+        let sin_theta_i = wi.x().clamp(-1.0, 1.0);
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+        let phi_i = wi.z().atan2(wi.y());
+
+        let (attenuations, gamma_t) = self.attenuations(cos_theta_o, h);
+        let lobe_pdfs = Hair::lobe_pdfs(&attenuations);
+        let gamma_o = h.clamp(-1.0, 1.0).asin();
+        let phi = phi_i - phi_o;
+
+        let mut value = color::Color(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+        for p in 0..P_MAX {
+            let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            let lobe = longitudinal(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[p],
+            ) * azimuthal(phi, p, self.s, gamma_o, gamma_t);
+            value = value + attenuations[p] * lobe;
+            pdf += lobe_pdfs[p] * lobe;
+        }
+        // The remaining paths are spread evenly around the fiber.
+        let lobe = longitudinal(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        ) / (2.0 * PI);
+        value = value + attenuations[P_MAX] * lobe;
+        pdf += lobe_pdfs[P_MAX] * lobe;
+        Evaluation { value, pdf }
+    }
+
+    // Pick a lobe, then an elevation from its longitudinal part and an azimuth from
+    // its azimuthal part.
+    fn sample(&self, wo: &vec3::Vec3, h: f32) -> vec3::Vec3 {
+        let sin_theta_o = wo.x().clamp(-1.0, 1.0);
+        let cos_theta_o = safe_sqrt(1.0 - sin_theta_o * sin_theta_o);
+        let phi_o = wo.z().atan2(wo.y());
+        let (attenuations, gamma_t) = self.attenuations(cos_theta_o, h);
+        let lobe_pdfs = Hair::lobe_pdfs(&attenuations);
+
+        let mut u = util::random_float();
+        let mut p = 0;
+        while p < P_MAX && u >= lobe_pdfs[p] {
+            u -= lobe_pdfs[p];
+            p += 1;
+        }
+
+        let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+        let u = util::random_float().max(1e-5);
+        let v = self.v[p];
+        let cos_theta = 1.0 + v * (u + (1.0 - u) * (-2.0 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+        let cos_phi = (2.0 * PI * util::random_float()).cos();
+        let sin_theta_i = -cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op;
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+
+        let gamma_o = h.clamp(-1.0, 1.0).asin();
+        let delta_phi = if p < P_MAX {
+            lobe_angle(p, gamma_o, gamma_t) + sample_trimmed_logistic(util::random_float(), self.s)
+        } else {
+            2.0 * PI * util::random_float()
+        };
+        let phi_i = phi_o + delta_phi;
+        vec3::Vec3(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        )
+    }
+}
+
+fn safe_sqrt(x: f32) -> f32 {
+    x.max(0.0).sqrt()
+}
+
+// Modified Bessel function of the first kind, from the first terms of its series.
+fn bessel_i0(x: f32) -> f32 {
+    let mut value = 0.0;
+    let mut x2i = 1.0;
+    let mut factorial = 1.0;
+    let mut four_i = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as f32;
+        }
+        value += x2i / (four_i * factorial * factorial);
+        x2i *= x * x;
+        four_i *= 4.0;
+    }
+    value
+}
+
+fn log_bessel_i0(x: f32) -> f32 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+// Longitudinal scattering function of d'Eon et al. 2011, with variance v.
+fn longitudinal(
+    cos_theta_i: f32,
+    cos_theta_o: f32,
+    sin_theta_i: f32,
+    sin_theta_o: f32,
+    v: f32,
+) -> f32 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        // In log space, as the terms overflow for low roughness.
+        (log_bessel_i0(a) - b - 1.0 / v + std::f32::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        (-b).exp() * bessel_i0(a) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+// Azimuth around the fiber that lobe p leaves at, for a perfectly smooth fiber.
+fn lobe_angle(p: usize, gamma_o: f32, gamma_t: f32) -> f32 {
+    2.0 * p as f32 * gamma_t - 2.0 * gamma_o + p as f32 * PI
+}
+
+fn logistic(x: f32, s: f32) -> f32 {
+    let x = x.abs();
+    (-x / s).exp() / (s * (1.0 + (-x / s).exp()).powi(2))
+}
+
+fn logistic_cdf(x: f32, s: f32) -> f32 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+// Azimuthal scattering function, a logistic distribution around the lobe's angle
+// trimmed to [-pi, pi].
+fn azimuthal(phi: f32, p: usize, s: f32, gamma_o: f32, gamma_t: f32) -> f32 {
+    let mut delta_phi = phi - lobe_angle(p, gamma_o, gamma_t);
+    delta_phi = (delta_phi + PI).rem_euclid(2.0 * PI) - PI;
+    logistic(delta_phi, s) / (logistic_cdf(PI, s) - logistic_cdf(-PI, s))
+}
+
+fn sample_trimmed_logistic(u: f32, s: f32) -> f32 {
+    let k = logistic_cdf(PI, s) - logistic_cdf(-PI, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(-PI, s)) - 1.0).ln();
+    x.clamp(-PI, PI)
+}
+
+impl Material for Hair {
+    fn scatter(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> Option<Scattering> {
+        let frame = Hair::frame(hit_record);
+        let h = -1.0 + 2.0 * hit_record.v;
+        let wo = frame.to_local(&-ray.direction.unit_vector());
+        let wi = self.sample(&wo, h);
+        let evaluation = self.evaluate(&wo, &wi, h);
+        if evaluation.pdf <= 0.0 {
+            return None;
+        }
+        Some(Scattering {
+            scattered: ray::Ray::new(hit_record.p, frame.to_world(&wi)),
+            attenuation: evaluation.value / evaluation.pdf,
+            pdf: Some(evaluation.pdf),
+        })
+    }
+
+    fn albedo(&self, _hit_record: &hittable::HitRecord) -> color::Color {
+        color::Color(
+            (-self.sigma_a.x()).exp(),
+            (-self.sigma_a.y()).exp(),
+            (-self.sigma_a.z()).exp(),
+        )
+    }
+
+    fn eval(
+        &self,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        direction: &vec3::Vec3,
+    ) -> Option<Evaluation> {
+        let frame = Hair::frame(hit_record);
+        let h = -1.0 + 2.0 * hit_record.v;
+        let wo = frame.to_local(&-ray.direction.unit_vector());
+        let wi = frame.to_local(direction);
+        Some(self.evaluate(&wo, &wi, h))
+    }
+
+    // A fiber has no inside for rays to enter.
+    fn is_surface(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::check_consistent;
+    use std::rc::Rc;
+
+    fn fiber_hit(h: f32, material: Rc<dyn Material>) -> (ray::Ray, hittable::HitRecord) {
+        // A fiber along x seen from +z.
+        let ray = ray::Ray::new(vec3::Point3(0.0, 0.2, 1.0), vec3::Vec3(0.1, -0.2, -1.0));
+        let mut hit_record = hittable::HitRecord::new(
+            1.0,
+            vec3::Point3(0.1, 0.0, 0.0),
+            &ray,
+            vec3::Vec3(0.0, 0.0, 1.0),
+            material,
+        );
+        hit_record.dpdu = vec3::Vec3(1.0, 0.0, 0.0);
+        hit_record.dpdv = vec3::Vec3(0.0, 0.01, 0.0);
+        hit_record.v = 0.5 * (h + 1.0);
+        (ray, hit_record)
+    }
+
+    #[test]
+    fn test_hair_consistent() {
+        check_consistent(Rc::new(Hair::from_melanin(1.3, 0.0, 0.3, 0.3)), true);
+    }
+
+    #[test]
+    fn test_white_furnace() {
+        // Without absorption all light leaves the fiber, whatever the roughness.
+        for &(beta_m, beta_n) in [(0.2, 0.3), (0.5, 0.5), (0.8, 0.9)].iter() {
+            let hair: Rc<dyn Material> =
+                Rc::new(Hair::new(color::Color(0.0, 0.0, 0.0), beta_m, beta_n, 2.0));
+            for &h in [-0.6, 0.0, 0.7].iter() {
+                let (ray, hit_record) = fiber_hit(h, hair.clone());
+                let samples = 20000;
+                let mut total = color::Color(0.0, 0.0, 0.0);
+                for _ in 0..samples {
+                    let scattering = hair.scatter(&ray, &hit_record).unwrap();
+                    total = total + scattering.attenuation;
+                }
+                let average = total / samples as f32;
+                assert!((average.y() - 1.0).abs() < 0.05);
+            }
+        }
+
+        // Eumelanin darkens it.
+        let hair: Rc<dyn Material> = Rc::new(Hair::from_melanin(8.0, 0.0, 0.3, 0.3));
+        let (ray, hit_record) = fiber_hit(0.0, hair.clone());
+        let scattering = hair.scatter(&ray, &hit_record).unwrap();
+        assert!(scattering.attenuation.y() < 1.0);
+    }
+}
@@ -0,0 +1,351 @@
+use super::aabb::Aabb;
+use super::framebuffer::Framebuffer;
+use super::hittable;
+use super::image_reader;
+use super::material;
+use super::ppm;
+use super::ray;
+use super::triangle::intersect_triangle;
+use super::vec3;
+use std::option::Option;
+use std::path::Path;
+use std::rc::Rc;
+
+// Terrain from a grid of heights, each cell split into two triangles. Only the
+// heights are stored, and rays walk the grid cell by cell so memory and time stay
+// small however large the terrain is.
+// width, depth: Number of samples along x and z.
+// heights: Samples row by row, starting from the one at the lowest z.
+// origin: Corner of the terrain at the lowest x and z, and at height zero.
+// size: Extent along x and z, and the height of a sample of one along y.
+// normals: Vertex normals from the slope around each sample, for smooth shading.
+// u, v: From the lowest x at u = 0, and from the highest z at v = 0.
+pub struct Heightfield {
+    width: usize,
+    depth: usize,
+    heights: Vec<f32>,
+    origin: vec3::Point3,
+    size: vec3::Vec3,
+    normals: Vec<vec3::Vec3>,
+    bounds: Aabb,
+    material: Rc<dyn material::Material>,
+}
+
+impl Heightfield {
+    pub fn new(
+        width: usize,
+        depth: usize,
+        heights: Vec<f32>,
+        origin: vec3::Point3,
+        size: vec3::Vec3,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        assert!(width >= 2 && depth >= 2 && heights.len() == width * depth);
+        let lowest = heights.iter().cloned().fold(f32::INFINITY, f32::min);
+        let highest = heights.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let mut heightfield = Heightfield {
+            width,
+            depth,
+            heights,
+            origin,
+            size,
+            normals: vec![],
+            bounds: Aabb::new(
+                origin + vec3::Vec3(0.0, lowest * size.y(), 0.0),
+                origin + vec3::Vec3(size.x(), highest * size.y(), size.z()),
+            ),
+            material,
+        };
+        heightfield.normals = (0..depth)
+            .flat_map(|j| (0..width).map(move |i| (i, j)))
+            .map(|(i, j)| heightfield.vertex_normal(i, j))
+            .collect();
+        heightfield
+    }
+
+    // Heights from the brightness of an image, with its top row at the lowest z. The
+    // image needs at least two pixels each way to make a single cell.
+    pub fn from_image(
+        image: &Framebuffer,
+        origin: vec3::Point3,
+        size: vec3::Vec3,
+        material: Rc<dyn material::Material>,
+    ) -> std::io::Result<Self> {
+        if image.width < 2 || image.height < 2 {
+            return Err(ppm::invalid_data("height map is smaller than 2x2"));
+        }
+        let heights = image
+            .pixels()
+            .iter()
+            .map(|pixel| (pixel.x() + pixel.y() + pixel.z()) / 3.0)
+            .collect();
+        Ok(Heightfield::new(
+            image.width,
+            image.height,
+            heights,
+            origin,
+            size,
+            material,
+        ))
+    }
+
+    // Load a grayscale height map, read as linear data.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        origin: vec3::Point3,
+        size: vec3::Vec3,
+        material: Rc<dyn material::Material>,
+    ) -> std::io::Result<Self> {
+        let image = image_reader::load_image(path, image_reader::ColorSpace::Linear)?;
+        Heightfield::from_image(&image, origin, size, material)
+    }
+
+    fn cell_size(&self) -> (f32, f32) {
+        (
+            self.size.x() / (self.width - 1) as f32,
+            self.size.z() / (self.depth - 1) as f32,
+        )
+    }
+
+    fn height(&self, i: usize, j: usize) -> f32 {
+        self.heights[j * self.width + i]
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> vec3::Point3 {
+        let (cell_x, cell_z) = self.cell_size();
+        self.origin
+            + vec3::Vec3(
+                i as f32 * cell_x,
+                self.height(i, j) * self.size.y(),
+                j as f32 * cell_z,
+            )
+    }
+
+    // Normal from central differences, one sided along the edges.
+    fn vertex_normal(&self, i: usize, j: usize) -> vec3::Vec3 {
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.width - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.depth - 1));
+        let (cell_x, cell_z) = self.cell_size();
+        let slope_x =
+            (self.height(i1, j) - self.height(i0, j)) * self.size.y() / ((i1 - i0) as f32 * cell_x);
+        let slope_z =
+            (self.height(i, j1) - self.height(i, j0)) * self.size.y() / ((j1 - j0) as f32 * cell_z);
+        vec3::Vec3(-slope_x, 1.0, -slope_z).unit_vector()
+    }
+
+    // Intersect the two triangles of the cell with its lowest corner at (i, j).
+    fn hit_cell(
+        &self,
+        i: usize,
+        j: usize,
+        r: &ray::Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<hittable::HitRecord> {
+        let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+        // Wound so the geometric normals face up.
+        let triangles = [[0, 2, 1], [0, 3, 2]];
+        let vertices = |triangle: &[usize; 3]| triangle.map(|corner| corners[corner]);
+        let mut closest = None;
+        for (index, triangle) in triangles.iter().enumerate() {
+            let [p0, p1, p2] = vertices(triangle).map(|(i, j)| self.vertex(i, j));
+            if let Some((t, b1, b2)) = intersect_triangle(r, &p0, &p1, &p2) {
+                let closest_so_far = closest.map_or(t_max, |(t, _, _, _)| t);
+                if t >= t_min && t <= closest_so_far {
+                    closest = Some((t, index, b1, b2));
+                }
+            }
+        }
+        let (t, index, b1, b2) = closest?;
+        let vertices = vertices(&triangles[index]);
+        let b0 = 1.0 - b1 - b2;
+
+        let [p0, p1, p2] = vertices.map(|(i, j)| self.vertex(i, j));
+        let geometric_normal = (p1 - p0).cross(p2 - p0).unit_vector();
+        let [n0, n1, n2] = vertices.map(|(i, j)| self.normals[j * self.width + i]);
+        let shading_normal = (n0 * b0 + n1 * b1 + n2 * b2).unit_vector();
+
+        let mut hit_record =
+            hittable::HitRecord::new(t, r.at(t), r, geometric_normal, self.material.clone());
+        hit_record.normal = if hit_record.front_face {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+        let p = hit_record.p - self.origin;
+        hit_record.u = p.x() / self.size.x();
+        hit_record.v = 1.0 - p.z() / self.size.z();
+        // The triangle is the plane y = slope_x x + slope_z z, with v running to -z.
+        let slope_x = -geometric_normal.x() / geometric_normal.y();
+        let slope_z = -geometric_normal.z() / geometric_normal.y();
+        hit_record.dpdu = vec3::Vec3(1.0, slope_x, 0.0) * self.size.x();
+        hit_record.dpdv = vec3::Vec3(0.0, -slope_z, -1.0) * self.size.z();
+        Some(hit_record)
+    }
+}
+
+impl hittable::Hittable for Heightfield {
+    // Walk the cells under the ray with a 2D DDA, from where it enters the bounds,
+    // skipping cells the ray passes entirely above or below.
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<hittable::HitRecord> {
+        let (t_enter, t_exit) = self.bounds.hit(r, t_min, t_max)?;
+        let (cell_x, cell_z) = self.cell_size();
+        let cells = [self.width - 1, self.depth - 1];
+        let start = r.at(t_enter) - self.origin;
+        let position = [start.x() / cell_x, start.z() / cell_z];
+        let direction = [r.direction.x() / cell_x, r.direction.z() / cell_z];
+
+        let mut cell = [0usize; 2];
+        let mut step = [0isize; 2];
+        let mut t_next = [f32::INFINITY; 2];
+        let mut t_delta = [f32::INFINITY; 2];
+        for axis in 0..2 {
+            cell[axis] = (position[axis].floor().max(0.0) as usize).min(cells[axis] - 1);
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                t_delta[axis] = 1.0 / direction[axis];
+                t_next[axis] = t_enter + ((cell[axis] + 1) as f32 - position[axis]) * t_delta[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                t_delta[axis] = -1.0 / direction[axis];
+                t_next[axis] = t_enter + (position[axis] - cell[axis] as f32) * t_delta[axis];
+            }
+        }
+
+        let mut t_cell = t_enter;
+        loop {
+            let t_leave = t_next[0].min(t_next[1]).min(t_exit);
+            let corners = [
+                self.height(cell[0], cell[1]),
+                self.height(cell[0] + 1, cell[1]),
+                self.height(cell[0], cell[1] + 1),
+                self.height(cell[0] + 1, cell[1] + 1),
+            ];
+            let lowest = corners.iter().cloned().fold(f32::INFINITY, f32::min);
+            let highest = corners.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            let (y0, y1) = (r.at(t_cell).y(), r.at(t_leave).y());
+            let bottom = self.origin.y() + lowest * self.size.y();
+            let top = self.origin.y() + highest * self.size.y();
+            if y0.min(y1) <= top && y0.max(y1) >= bottom {
+                if let Some(hit_record) = self.hit_cell(cell[0], cell[1], r, t_min, t_max) {
+                    return Some(hit_record);
+                }
+            }
+
+            if t_leave >= t_exit {
+                return None;
+            }
+            let axis = if t_next[0] < t_next[1] { 0 } else { 1 };
+            let next = cell[axis] as isize + step[axis];
+            if next < 0 || next >= cells[axis] as isize {
+                return None;
+            }
+            cell[axis] = next as usize;
+            t_cell = t_next[axis];
+            t_next[axis] += t_delta[axis];
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{check_tangents, lambertian};
+    use crate::hittable::Hittable;
+    use crate::triangle::TriangleMesh;
+    use crate::util;
+
+    #[test]
+    fn test_flat_heightfield() {
+        let heightfield = Heightfield::new(
+            3,
+            3,
+            vec![0.5; 9],
+            vec3::Point3(-1.0, 0.0, -1.0),
+            vec3::Vec3(2.0, 2.0, 2.0),
+            lambertian(),
+        );
+        let r = ray::Ray::new(vec3::Point3(0.3, 5.0, 0.2), vec3::Vec3(0.0, -1.0, 0.0));
+        let hit_record = heightfield.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 4.0).abs() < 1e-5);
+        assert!(hit_record.front_face);
+        assert!((hit_record.normal - vec3::Vec3(0.0, 1.0, 0.0)).norm() < 1e-5);
+        assert!((hit_record.u - 0.65).abs() < 1e-5 && (hit_record.v - 0.4).abs() < 1e-5);
+        check_tangents(&hit_record);
+
+        let outside = ray::Ray::new(vec3::Point3(1.5, 5.0, 0.0), vec3::Vec3(0.0, -1.0, 0.0));
+        assert!(heightfield.hit(&outside, 0.001, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_from_image() {
+        let mut image = Framebuffer::new(2, 2);
+        image.set(1, 1, crate::color::Color(0.3, 0.6, 0.9));
+        let origin = vec3::Point3(0.0, 0.0, 0.0);
+        let size = vec3::Vec3(1.0, 1.0, 1.0);
+        let heightfield = Heightfield::from_image(&image, origin, size, lambertian()).unwrap();
+        assert!((heightfield.height(1, 1) - 0.6).abs() < 1e-6);
+
+        // A single row or column has no cells.
+        for (width, height) in [(1, 4), (4, 1), (0, 0)] {
+            let image = Framebuffer::new(width, height);
+            let error = Heightfield::from_image(&image, origin, size, lambertian())
+                .err()
+                .unwrap();
+            assert!(error.kind() == std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_matches_triangle_mesh() {
+        // The same terrain as a mesh gives the same hits, however rays cross the grid.
+        let (width, depth) = (7, 5);
+        let heights: Vec<f32> = (0..width * depth).map(|_| util::random_float()).collect();
+        let heightfield = Heightfield::new(
+            width,
+            depth,
+            heights,
+            vec3::Point3(-3.0, -1.0, -2.0),
+            vec3::Vec3(6.0, 2.0, 4.0),
+            lambertian(),
+        );
+        let positions = (0..depth)
+            .flat_map(|j| (0..width).map(move |i| (i, j)))
+            .map(|(i, j)| heightfield.vertex(i, j))
+            .collect();
+        let mut indices = vec![];
+        for j in 0..depth - 1 {
+            for i in 0..width - 1 {
+                let corner = j * width + i;
+                indices.push([corner, corner + 1, corner + width + 1]);
+                indices.push([corner, corner + width + 1, corner + width]);
+            }
+        }
+        let mesh = TriangleMesh::new(positions, indices, lambertian());
+
+        for _ in 0..500 {
+            let origin = vec3::Point3(
+                util::random_float_bounds(-5.0, 5.0),
+                util::random_float_bounds(-3.0, 3.0),
+                util::random_float_bounds(-5.0, 5.0),
+            );
+            let target = vec3::Point3(
+                util::random_float_bounds(-3.0, 3.0),
+                util::random_float_bounds(-1.0, 1.0),
+                util::random_float_bounds(-2.0, 2.0),
+            );
+            let r = ray::Ray::new(origin, target - origin);
+            let expected = mesh.hit(&r, 0.001, f32::INFINITY).map(|hit| hit.t);
+            let actual = heightfield.hit(&r, 0.001, f32::INFINITY).map(|hit| hit.t);
+            match (expected, actual) {
+                (Some(expected), Some(actual)) => assert!((expected - actual).abs() < 1e-4),
+                (None, None) => {}
+                _ => panic!("heightfield and mesh disagree"),
+            }
+        }
+    }
+}
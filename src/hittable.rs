@@ -1,3 +1,5 @@
+use super::aabb::Aabb;
+use super::color;
 use super::material;
 use super::ray;
 use super::vec3;
@@ -8,12 +10,24 @@ use std::rc::Rc;
 // t: t along the ray where the intersection occurs.
 // p: The point of intersection.
 // normal: The surface normal from the intersection point.
+// u, v: Surface coordinates of the intersection, used to look up textures.
+// dpdu, dpdv: How the surface position changes with u and v. Together with the
+// outward normal they form the tangent frame used by normal and bump maps.
+// object_id: Index of the hit object within the world, set by the HittableList.
+// vertex_color: Interpolated vertex color, on meshes that have them.
+#[derive(Clone)]
 pub struct HitRecord {
     pub t: f32,
     pub p: vec3::Point3,
     pub normal: vec3::Vec3,
     pub front_face: bool,
     pub material: Rc<dyn material::Material>,
+    pub u: f32,
+    pub v: f32,
+    pub dpdu: vec3::Vec3,
+    pub dpdv: vec3::Vec3,
+    pub object_id: usize,
+    pub vertex_color: Option<color::Color>,
 }
 
 impl HitRecord {
@@ -37,10 +51,124 @@ impl HitRecord {
             normal,
             front_face,
             material,
+            u: 0.0,
+            v: 0.0,
+            dpdu: vec3::Vec3(0.0, 0.0, 0.0),
+            dpdv: vec3::Vec3(0.0, 0.0, 0.0),
+            object_id: 0,
+            vertex_color: None,
         }
     }
+
+    // The normal on the outside of the surface, whichever side was hit.
+    pub fn outward_normal(&self) -> vec3::Vec3 {
+        if self.front_face {
+            self.normal
+        } else {
+            -self.normal
+        }
+    }
+}
+
+// A stretch of a ray inside a solid, for constructive solid geometry.
+// t_enter, t_exit: Where the stretch starts and ends along the ray.
+// enter, exit: The hits on the boundary there. enter is None when the ray starts
+// inside at t_min, exit when it is still inside at t_max.
+#[derive(Clone)]
+pub struct Interval {
+    pub t_enter: f32,
+    pub t_exit: f32,
+    pub enter: Option<HitRecord>,
+    pub exit: Option<HitRecord>,
+}
+
+// How far past a boundary to look for the next one when collecting intervals, relative
+// to the distance along the ray so the step doesn't vanish in rounding far away.
+static INTERVAL_EPSILON: f32 = 1e-4;
+
+fn step_past(t: f32) -> f32 {
+    t + (t.abs() * INTERVAL_EPSILON).max(INTERVAL_EPSILON)
 }
 
 pub trait Hittable {
     fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+
+    // A box containing the whole object, None if it is unbounded.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    // The stretches of the ray between t_min and t_max that are inside the object, in
+    // order. By default these come from walking through every hit and using the
+    // face that was hit to tell entries from exits, which suits closed surfaces.
+    fn intervals(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Vec<Interval> {
+        let mut intervals = vec![];
+        let mut enter: Option<HitRecord> = None;
+        let mut t = t_min;
+        while let Some(hit_record) = self.hit(r, t, t_max) {
+            t = step_past(hit_record.t);
+            if hit_record.front_face {
+                if enter.is_none() {
+                    enter = Some(hit_record);
+                }
+                continue;
+            }
+            // Only the first exit can come without an entry, for rays starting inside.
+            if enter.is_none() && !intervals.is_empty() {
+                continue;
+            }
+            intervals.push(Interval {
+                t_enter: enter.as_ref().map_or(t_min, |enter| enter.t),
+                t_exit: hit_record.t,
+                enter: enter.take(),
+                exit: Some(hit_record),
+            });
+        }
+        if let Some(enter) = enter {
+            intervals.push(Interval {
+                t_enter: enter.t,
+                t_exit: t_max,
+                enter: Some(enter),
+                exit: None,
+            });
+        }
+        intervals
+    }
+
+    // Fraction of light getting through along the ray between t_min and t_max, for
+    // shadow rays. Surfaces block all of it, apart from where they are transparent.
+    fn transmittance(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> color::Color {
+        let mut transmittance = 1.0;
+        let mut t = t_min;
+        while let Some(hit_record) = self.hit(r, t, t_max) {
+            transmittance *= hit_record.material.transparency(&hit_record);
+            if transmittance == 0.0 {
+                break;
+            }
+            t = step_past(hit_record.t);
+        }
+        color::WHITE * transmittance
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // A plain gray material for shape tests that don't care how hits look.
+    pub fn lambertian() -> Rc<dyn material::Material> {
+        Rc::new(material::Lambertian::new(vec3::Vec3(0.5, 0.5, 0.5)))
+    }
+
+    // The tangents and the normal must form a right handed frame, or normal and bump
+    // maps come out inverted.
+    pub fn check_tangents(hit_record: &HitRecord) {
+        assert!(
+            hit_record
+                .dpdu
+                .cross(hit_record.dpdv)
+                .dot(hit_record.normal)
+                > 0.0
+        );
+    }
 }
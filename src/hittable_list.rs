@@ -1,3 +1,5 @@
+use super::aabb::Aabb;
+use super::color;
 use super::hittable;
 use super::ray;
 use std::option::Option;
@@ -27,15 +29,31 @@ impl hittable::Hittable for HittableList {
         let mut final_record: Option<hittable::HitRecord> = None;
         let mut closest_so_far = t_max;
 
-        for object in self.objects.iter() {
-            match object.hit(r, t_min, closest_so_far) {
-                Some(hit_record) => {
-                    closest_so_far = hit_record.t;
-                    final_record = Some(hit_record);
-                }
-                None => (),
+        for (object_id, object) in self.objects.iter().enumerate() {
+            if let Some(mut hit_record) = object.hit(r, t_min, closest_so_far) {
+                closest_so_far = hit_record.t;
+                hit_record.object_id = object_id;
+                final_record = Some(hit_record);
             }
         }
         final_record
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|object| object.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |aabb, other| Some(aabb.surrounding(other?)))
+    }
+
+    fn transmittance(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> color::Color {
+        let black = color::Color(0.0, 0.0, 0.0);
+        let mut transmittance = color::WHITE;
+        for object in self.objects.iter() {
+            transmittance = transmittance * object.transmittance(r, t_min, t_max);
+            if transmittance == black {
+                break;
+            }
+        }
+        transmittance
+    }
 }
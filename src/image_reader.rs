@@ -0,0 +1,171 @@
+use super::color;
+use super::framebuffer::Framebuffer;
+use super::pfm;
+use super::ppm;
+use super::tonemap;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read};
+use std::path::Path;
+
+// How the integer samples of an image file map to linear values.
+// Srgb: Color images such as textures and photos.
+// Linear: Data images such as normal and height maps.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    // Convert a normalized [0, 1] sample into a linear value.
+    pub fn decode(&self, x: f32) -> f32 {
+        match self {
+            ColorSpace::Srgb => tonemap::srgb_to_linear(x),
+            ColorSpace::Linear => x,
+        }
+    }
+}
+
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+// Decode a PNG image. Palette and low bit depth images are expanded, alpha is dropped.
+pub fn read_png<R: Read>(mut reader: R, color_space: ColorSpace) -> std::io::Result<Framebuffer> {
+    let mut file = vec![];
+    reader.read_to_end(&mut file)?;
+    let mut decoder = png::Decoder::new(&file[..]);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info()?;
+    let (color_type, bit_depth) = reader.output_color_type();
+
+    // Deflate compresses by at most a factor of 1032, which bounds the size of any
+    // image the file can really hold.
+    ppm::check_size(
+        info.width as usize,
+        info.height as usize,
+        1,
+        MAX_DEFLATE_RATIO * file.len(),
+    )?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut data)?;
+
+    let channels = match color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::RGB => 3,
+        png::ColorType::RGBA => 4,
+        png::ColorType::Indexed => {
+            return Err(Error::new(ErrorKind::InvalidData, "unexpanded PNG palette"))
+        }
+    };
+    let sample = |index: usize| -> f32 {
+        let value = match bit_depth {
+            png::BitDepth::Sixteen => {
+                u16::from_be_bytes([data[2 * index], data[2 * index + 1]]) as f32 / u16::MAX as f32
+            }
+            _ => data[index] as f32 / u8::MAX as f32,
+        };
+        color_space.decode(value)
+    };
+
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let mut image = Framebuffer::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y * width + x) * channels;
+            let pixel_color = if channels < 3 {
+                let gray = sample(index);
+                color::Color(gray, gray, gray)
+            } else {
+                color::Color(sample(index), sample(index + 1), sample(index + 2))
+            };
+            image.set(x, y, pixel_color);
+        }
+    }
+    Ok(image)
+}
+
+// Load a PPM, PNG or PFM image from disk, picking the decoder from the file extension.
+// PFM images hold linear floats, so they ignore the color space.
+pub fn load_image<P: AsRef<Path>>(
+    path: P,
+    color_space: ColorSpace,
+) -> std::io::Result<Framebuffer> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    let file = BufReader::new(File::open(path)?);
+
+    match extension.as_deref() {
+        Some("ppm") => ppm::read_ppm(file, color_space),
+        Some("png") => read_png(file, color_space),
+        Some("pfm") => pfm::read_pfm(file),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("unsupported image format {}", path.display()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_png(width: u32, height: u32, color_type: png::ColorType, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, width, height);
+            encoder.set_color(color_type);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(data).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_read_png() {
+        let bytes = encode_png(2, 1, png::ColorType::RGB, &[255, 0, 0, 0, 0, 255]);
+        let image = read_png(&bytes[..], ColorSpace::Linear).unwrap();
+
+        assert!(image.width == 2 && image.height == 1);
+        assert!(image.get(0, 0) == color::RED);
+        assert!(image.get(1, 0) == color::Color(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_read_png_grayscale_srgb() {
+        let bytes = encode_png(1, 1, png::ColorType::Grayscale, &[128]);
+        let image = read_png(&bytes[..], ColorSpace::Srgb).unwrap();
+
+        let expected = tonemap::srgb_to_linear(128.0 / 255.0);
+        assert!(image.get(0, 0) == color::Color(expected, expected, expected));
+    }
+
+    #[test]
+    fn test_pfm_round_trip() {
+        let path = std::env::temp_dir().join("in_one_weekend_round_trip.pfm");
+        let mut image = Framebuffer::new(2, 2);
+        image.set(1, 0, color::Color(16.0, 0.5, -1.0));
+
+        pfm::write_pfm(path.to_str().unwrap(), &image).unwrap();
+        let loaded = load_image(&path, ColorSpace::Srgb).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(loaded.width == 2 && loaded.height == 2);
+        assert!(loaded.get(1, 0) == color::Color(16.0, 0.5, -1.0));
+        assert!(loaded.get(0, 1) == color::Color(0.0, 0.0, 0.0));
+
+        // Empty maps are rejected rather than breaking environment sampling later.
+        assert!(pfm::read_pfm("PF\n0 2\n-1.0\n".as_bytes()).is_err());
+        assert!(pfm::read_pfm("PF\n4000000 4000000\n-1.0\n\0\0\0\0".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_load_image_errors() {
+        assert!(load_image("missing.png", ColorSpace::Srgb).is_err());
+        assert!(load_image("Cargo.toml", ColorSpace::Srgb).is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use super::aov;
+use super::color;
+use super::hittable::{HitRecord, Hittable};
+use super::medium::MediumStack;
+use super::ray;
+use super::scene::Scene;
+use super::util;
+
+// Offset from surfaces when spawning rays, so they don't hit where they started.
+static T_MIN: f32 = 0.0001;
+
+// Most times a path may scatter inside media. These random walks take far more steps
+// than surface bounces, so they are limited separately from max_depth.
+static MAX_MEDIUM_EVENTS: usize = 1000;
+
+// Multiple importance sampling weight for a strategy with density pdf, when another
+// strategy could have produced the same sample with density other_pdf.
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let pdf2 = pdf * pdf;
+    let other_pdf2 = other_pdf * other_pdf;
+    if pdf2 + other_pdf2 == 0.0 {
+        return 0.0;
+    }
+    pdf2 / (pdf2 + other_pdf2)
+}
+
+// Fraction of light reaching the origin of the shadow ray from t_max along it.
+fn visibility(scene: &Scene, shadow_ray: &ray::Ray, t_max: f32) -> color::Color {
+    scene.world.transmittance(shadow_ray, T_MIN, t_max)
+}
+
+// Light arriving at the hit point directly from the background, through the material.
+fn sample_background(r: &ray::Ray, hit_record: &HitRecord, scene: &Scene) -> color::Color {
+    let black = color::Color(0.0, 0.0, 0.0);
+    let sample = match scene.background.sample() {
+        Some(sample) => sample,
+        None => return black,
+    };
+    let evaluation = match hit_record.material.eval(r, hit_record, &sample.direction) {
+        Some(evaluation) => evaluation,
+        None => return black,
+    };
+    if evaluation.value == black {
+        return black;
+    }
+    let visibility = visibility(
+        scene,
+        &ray::Ray::new(hit_record.p, sample.direction),
+        f32::INFINITY,
+    );
+    if visibility == black {
+        return black;
+    }
+
+    let weight = power_heuristic(sample.pdf, evaluation.pdf);
+    evaluation.value * visibility * sample.radiance * (weight / sample.pdf)
+}
+
+// Light arriving at the hit point from the punctual lights, through the material.
+// These lights can't be hit by rays, so they are only ever found here.
+fn sample_lights(r: &ray::Ray, hit_record: &HitRecord, scene: &Scene) -> color::Color {
+    let mut radiance = color::Color(0.0, 0.0, 0.0);
+    for light in scene.lights.iter() {
+        let sample = match light.sample(&hit_record.p) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let evaluation = match hit_record.material.eval(r, hit_record, &sample.direction) {
+            Some(evaluation) => evaluation,
+            // Specular materials can't see lights without area.
+            None => continue,
+        };
+        let shadow_ray = ray::Ray::new(hit_record.p, sample.direction);
+        let visibility = visibility(scene, &shadow_ray, sample.distance - T_MIN);
+        radiance = radiance + evaluation.value * visibility * sample.irradiance;
+    }
+    radiance
+}
+
+// Given a ray from camera -> pixel in the image, determine the color of that pixel.
+// If aov is given, the first hit along the ray is recorded into it. max_depth limits
+// the number of bounces off surfaces.
+pub fn ray_color(
+    r: &ray::Ray,
+    scene: &Scene,
+    max_depth: usize,
+    mut aov: Option<&mut Option<aov::AovSample>>,
+) -> color::Color {
+    let mut radiance = color::Color(0.0, 0.0, 0.0);
+    let mut throughput = color::WHITE;
+    let mut ray = *r;
+    // Density of the bounce that created the current ray, None for camera rays and
+    // specular bounces, which lights can't be sampled for.
+    let mut scatter_pdf: Option<f32> = None;
+    let mut media = MediumStack::new();
+
+    let mut bounces = 0;
+    let mut medium_events = 0;
+    while bounces < max_depth {
+        let hit = scene.world.hit(&ray, T_MIN, f32::INFINITY);
+
+        // Travel through the medium the ray is in, up to the surface it hits.
+        if let Some(medium) = media.current() {
+            let surface_distance = hit.as_ref().map_or(f32::INFINITY, |hit_record| {
+                hit_record.t * ray.direction.norm()
+            });
+            let (scatter_distance, weight) = medium.sample_distance(surface_distance);
+            throughput = throughput * weight;
+            if let Some(distance) = scatter_distance {
+                // Scattered inside the medium, continue the random walk from there.
+                medium_events += 1;
+                if medium_events > MAX_MEDIUM_EVENTS {
+                    break;
+                }
+                let direction = ray.direction.unit_vector();
+                ray = ray::Ray::new(
+                    ray.origin + direction * distance,
+                    medium.sample_phase(&direction),
+                );
+                ray.wavelength = r.wavelength;
+                scatter_pdf = None;
+                continue;
+            }
+        }
+
+        let hit_record = match hit {
+            Some(hit_record) => hit_record,
+            None => {
+                let direction = ray.direction.unit_vector();
+                let weight = match scatter_pdf {
+                    Some(pdf) => power_heuristic(pdf, scene.background.pdf(&direction)),
+                    None => 1.0,
+                };
+                radiance = radiance + throughput * scene.background.radiance(&direction) * weight;
+                break;
+            }
+        };
+
+        // Light passes straight through holes in the surface, as if it wasn't there.
+        let transparency = hit_record.material.transparency(&hit_record);
+        if transparency > 0.0 && util::random_float() < transparency {
+            ray = ray::Ray::new(hit_record.p, ray.direction);
+            ray.wavelength = r.wavelength;
+            continue;
+        }
+
+        if let Some(aov) = aov.take() {
+            *aov = Some(aov::AovSample::from_hit(&hit_record));
+        }
+
+        radiance = radiance
+            + throughput
+                * (sample_background(&ray, &hit_record, scene)
+                    + sample_lights(&ray, &hit_record, scene));
+
+        match hit_record.material.scatter(&ray, &hit_record) {
+            Some(scattering) => {
+                bounces += 1;
+                throughput = throughput * scattering.attenuation;
+                scatter_pdf = scattering.pdf;
+                ray = scattering.scattered;
+                // The whole path is traced at the wavelength of the camera ray.
+                ray.wavelength = r.wavelength;
+                // Rays continuing through the surface cross into or out of the object.
+                if hit_record.material.is_surface()
+                    && scattering.scattered.direction.dot(hit_record.normal) < 0.0
+                {
+                    if hit_record.front_face {
+                        media.enter(hit_record.material.medium());
+                    } else {
+                        media.exit();
+                    }
+                }
+            }
+            None => break,
+        }
+    }
+    radiance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::ConstantBackground;
+    use crate::combinators::AlphaMask;
+    use crate::hittable_list::HittableList;
+    use crate::light::{Light, LightSample};
+    use crate::material::{Dielectric, Evaluation, Material, Scattering};
+    use crate::medium::Medium;
+    use crate::sphere::Sphere;
+    use crate::subsurface::Subsurface;
+    use crate::texture::SolidColor;
+    use crate::vec3;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_power_heuristic() {
+        assert!(power_heuristic(1.0, 0.0) == 1.0);
+        assert!(power_heuristic(0.0, 1.0) == 0.0);
+        assert!(power_heuristic(0.0, 0.0) == 0.0);
+        assert!((power_heuristic(2.0, 1.0) + power_heuristic(1.0, 2.0) - 1.0).abs() < 1e-6);
+    }
+
+    // Reflects light only towards +x.
+    struct OneSided;
+
+    impl Material for OneSided {
+        fn scatter(&self, _ray: &ray::Ray, _hit_record: &HitRecord) -> Option<Scattering> {
+            None
+        }
+
+        fn eval(
+            &self,
+            _ray: &ray::Ray,
+            _hit_record: &HitRecord,
+            direction: &vec3::Vec3,
+        ) -> Option<Evaluation> {
+            if direction.x() > 0.0 {
+                Some(Evaluation {
+                    value: color::WHITE,
+                    pdf: 1.0,
+                })
+            } else {
+                None
+            }
+        }
+    }
+
+    // Light from a fixed direction, with no falloff.
+    struct FixedLight(vec3::Vec3);
+
+    impl Light for FixedLight {
+        fn sample(&self, _p: &vec3::Point3) -> Option<LightSample> {
+            Some(LightSample {
+                direction: self.0,
+                distance: f32::INFINITY,
+                irradiance: color::WHITE,
+            })
+        }
+    }
+
+    #[test]
+    fn test_sample_lights() {
+        let far_away = Sphere::new(vec3::Point3(0.0, -100.0, 0.0), 1.0, Rc::new(OneSided));
+        let mut scene = Scene::new(
+            HittableList::new(Box::new(far_away)),
+            Box::new(ConstantBackground::new(color::Color(0.0, 0.0, 0.0))),
+        );
+        // The material can't reflect the first light, which mustn't hide the second.
+        scene.add_light(Box::new(FixedLight(vec3::Vec3(-1.0, 0.0, 0.0))));
+        scene.add_light(Box::new(FixedLight(vec3::Vec3(1.0, 0.0, 0.0))));
+
+        let r = ray::Ray::new(vec3::Point3(0.0, 1.0, 0.0), vec3::Vec3(0.0, -1.0, 0.0));
+        let hit_record = HitRecord::new(
+            1.0,
+            vec3::Point3(0.0, 0.0, 0.0),
+            &r,
+            vec3::Vec3(0.0, 1.0, 0.0),
+            Rc::new(OneSided),
+        );
+        assert!(sample_lights(&r, &hit_record, &scene) == color::WHITE);
+    }
+
+    #[test]
+    fn test_absorption() {
+        // Glass which doesn't bend or reflect light, absorbing along the diameter of
+        // the sphere by the Beer-Lambert law.
+        let absorption = color::Color(0.5, 1.0, 2.0);
+        let glass = Rc::new(Dielectric::with_absorption(1.0, Medium::new(absorption)));
+        let sphere = Sphere::new(vec3::Point3(0.0, 0.0, 0.0), 1.0, glass);
+        let scene = Scene::new(
+            HittableList::new(Box::new(sphere)),
+            Box::new(ConstantBackground::new(color::WHITE)),
+        );
+        let r = ray::Ray::new(vec3::Point3(0.0, 0.0, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        let radiance = ray_color(&r, &scene, 50, None);
+        let expected = color::Color((-1.0f32).exp(), (-2.0f32).exp(), (-4.0f32).exp());
+        assert!((radiance - expected).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_alpha_mask_pass_through() {
+        // Rays going through the holes of a cut-out glass sphere never enter its medium.
+        let glass = Rc::new(Dielectric::with_absorption(
+            1.0,
+            Medium::new(color::Color(0.5, 1.0, 2.0)),
+        ));
+        let cut_out = Rc::new(AlphaMask::new(glass, Rc::new(SolidColor::scalar(0.0))));
+        let sphere = Sphere::new(vec3::Point3(0.0, 0.0, 0.0), 1.0, cut_out);
+        let scene = Scene::new(
+            HittableList::new(Box::new(sphere)),
+            Box::new(ConstantBackground::new(color::WHITE)),
+        );
+        let r = ray::Ray::new(vec3::Point3(0.0, 0.0, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        assert!(ray_color(&r, &scene, 50, None) == color::WHITE);
+    }
+
+    #[test]
+    fn test_subsurface_furnace() {
+        // A nearly lossless translucent sphere under uniform light looks uniformly lit.
+        let material = Rc::new(Subsurface::new(color::WHITE, color::WHITE * 0.5, 1.3, 0.3));
+        let sphere = Sphere::new(vec3::Point3(0.0, 0.0, 0.0), 1.0, material);
+        let scene = Scene::new(
+            HittableList::new(Box::new(sphere)),
+            Box::new(ConstantBackground::new(color::WHITE)),
+        );
+        let r = ray::Ray::new(vec3::Point3(0.3, 0.0, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        util::seed_random(42);
+        let samples = 2000;
+        let mut total = color::Color(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            total = total + ray_color(&r, &scene, 50, None);
+        }
+        let average = total.0 / samples as f32;
+        assert!(average > 0.85 && average < 1.1);
+    }
+}
@@ -0,0 +1,227 @@
+use super::color;
+use super::util;
+use super::vec3;
+use std::f32::consts::PI;
+
+// Light arriving at a point from a light source.
+// direction: Unit vector from the point towards the light.
+// distance: Distance to the light, infinite for directional lights.
+// irradiance: Light arriving on a surface facing the light, in W/m^2.
+#[derive(Debug, Copy, Clone)]
+pub struct LightSample {
+    pub direction: vec3::Vec3,
+    pub distance: f32,
+    pub irradiance: color::Color,
+}
+
+// Lights with no area, which can only be reached by shadow rays.
+pub trait Light {
+    // The light arriving at p, None if p receives no light.
+    fn sample(&self, p: &vec3::Point3) -> Option<LightSample>;
+}
+
+// Light emitted equally in all directions from a single point.
+// intensity: Radiant intensity in W/sr.
+pub struct PointLight {
+    position: vec3::Point3,
+    intensity: color::Color,
+}
+
+impl PointLight {
+    pub fn new(position: vec3::Point3, intensity: color::Color) -> Self {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+
+    // A point light emitting the given total power, in watts.
+    pub fn from_power(position: vec3::Point3, power: color::Color) -> Self {
+        PointLight::new(position, power / (4.0 * PI))
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: &vec3::Point3) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance_squared = to_light.norm_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            irradiance: self.intensity / distance_squared,
+        })
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge0 >= edge1 {
+        return if x >= edge1 { 1.0 } else { 0.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// A point light restricted to a cone, fading out between the inner and outer angle.
+// intensity: Radiant intensity in W/sr along the axis of the cone.
+pub struct SpotLight {
+    position: vec3::Point3,
+    direction: vec3::Vec3,
+    intensity: color::Color,
+    cos_inner: f32,
+    cos_outer: f32,
+}
+
+impl SpotLight {
+    // Angles are measured from the axis of the cone, in degrees.
+    pub fn new(
+        position: vec3::Point3,
+        direction: vec3::Vec3,
+        intensity: color::Color,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        SpotLight {
+            position,
+            direction: direction.unit_vector(),
+            intensity,
+            cos_inner: util::degrees_to_radians(inner_angle).cos(),
+            cos_outer: util::degrees_to_radians(outer_angle).cos(),
+        }
+    }
+
+    // A spot light emitting the given total power, in watts.
+    pub fn from_power(
+        position: vec3::Point3,
+        direction: vec3::Vec3,
+        power: color::Color,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        let mut light = SpotLight::new(position, direction, power, inner_angle, outer_angle);
+        // Approximate the solid angle lit, treating the falloff as half covered.
+        let solid_angle = 2.0 * PI * (1.0 - 0.5 * (light.cos_inner + light.cos_outer));
+        light.intensity = power / solid_angle;
+        light
+    }
+
+    fn falloff(&self, direction_from_light: &vec3::Vec3) -> f32 {
+        smoothstep(
+            self.cos_outer,
+            self.cos_inner,
+            direction_from_light.dot(self.direction),
+        )
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: &vec3::Point3) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance_squared = to_light.norm_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+
+        let falloff = self.falloff(&-direction);
+        if falloff == 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            irradiance: self.intensity * (falloff / distance_squared),
+        })
+    }
+}
+
+// Parallel light from infinitely far away, like the sun.
+// irradiance: Light arriving on a surface facing the light, in W/m^2.
+pub struct DirectionalLight {
+    // Unit vector pointing towards the light.
+    direction: vec3::Vec3,
+    irradiance: color::Color,
+}
+
+impl DirectionalLight {
+    // direction: The direction the light travels in.
+    pub fn new(direction: vec3::Vec3, irradiance: color::Color) -> Self {
+        DirectionalLight {
+            direction: -direction.unit_vector(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: &vec3::Point3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: self.direction,
+            distance: f32::INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_light_inverse_square() {
+        let light = PointLight::new(vec3::Point3(0.0, 4.0, 0.0), color::WHITE);
+        let near = light.sample(&vec3::Point3(0.0, 2.0, 0.0)).unwrap();
+        let far = light.sample(&vec3::Point3(0.0, 0.0, 0.0)).unwrap();
+
+        assert!(near.direction == vec3::Vec3(0.0, 1.0, 0.0));
+        assert!(far.distance == 4.0);
+        assert!(near.irradiance == far.irradiance * 4.0);
+
+        let from_power =
+            PointLight::from_power(vec3::Point3(0.0, 1.0, 0.0), color::WHITE * 4.0 * PI);
+        assert!(
+            from_power
+                .sample(&vec3::Point3(0.0, 0.0, 0.0))
+                .unwrap()
+                .irradiance
+                == color::WHITE
+        );
+    }
+
+    #[test]
+    fn test_spot_light_cone() {
+        let light = SpotLight::new(
+            vec3::Point3(0.0, 1.0, 0.0),
+            vec3::Vec3(0.0, -1.0, 0.0),
+            color::WHITE,
+            20.0,
+            30.0,
+        );
+        let inside = light.sample(&vec3::Point3(0.0, 0.0, 0.0)).unwrap();
+        assert!(inside.irradiance == color::WHITE);
+
+        // 25 degrees off axis is in the falloff region.
+        let partial = light.sample(&vec3::Point3(
+            util::degrees_to_radians(25.0).tan(),
+            0.0,
+            0.0,
+        ));
+        let partial = partial.unwrap().irradiance.0;
+        assert!(partial > 0.0 && partial < 1.0);
+
+        assert!(light.sample(&vec3::Point3(1.0, 0.0, 0.0)).is_none());
+        assert!(light.sample(&vec3::Point3(0.0, 2.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_directional_light() {
+        let light = DirectionalLight::new(vec3::Vec3(0.0, -2.0, 0.0), color::WHITE);
+        let sample = light.sample(&vec3::Point3(5.0, 0.0, 5.0)).unwrap();
+        assert!(sample.direction == vec3::Vec3(0.0, 1.0, 0.0));
+        assert!(sample.distance == f32::INFINITY);
+    }
+}
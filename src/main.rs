@@ -1,90 +1,72 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
+mod aabb;
+mod aov;
+mod background;
 mod camera;
 mod color;
+mod combinators;
+mod cone;
+mod csg;
+mod curve;
+mod cylinder;
+mod denoise;
+mod disk;
+mod filter;
+mod framebuffer;
+mod hair;
+mod heightfield;
 mod hittable;
 mod hittable_list;
+mod image_reader;
+mod integrator;
+mod light;
 mod material;
+mod medium;
+mod microfacet;
+mod normal_mapping;
+mod options;
+mod pfm;
+mod ply;
+mod polynomial;
 mod ppm;
+mod principled;
 mod ray;
+mod sampling;
+mod scene;
+mod sdf;
+mod sky;
+mod spectrum;
 mod sphere;
+mod stl;
+mod subdivision;
+mod subsurface;
+mod texture;
+mod thin_film;
+mod tonemap;
+mod torus;
+mod triangle;
 mod util;
 mod vec3;
+mod volume;
 
-use hittable::Hittable;
+use framebuffer::Film;
 use hittable_list::HittableList;
+use integrator::ray_color;
+use options::Options;
 use pixel_canvas::{Canvas, Color, RC};
 use ppm::PpmWriter;
-use std::ops;
 use std::rc::Rc;
 
-// t == 0, returns start, t == 1 returns end.
-fn linear_blend<T>(start: &T, end: &T, t: f32) -> T
-where
-    T: ops::Mul<f32, Output = T> + ops::Add<Output = T> + Copy,
-{
-    *start * (1.0 - t) + (*end * t)
-}
-
-// Given a ray from camera -> pixel in the image, determine the color of that pixel.
-fn ray_color(r: &ray::Ray, world: &HittableList, depth: usize) -> color::Color {
-    if depth <= 0 {
-        return color::Color(0.0, 0.0, 0.0);
-    }
-
-    match world.hit(r, 0.0001, f32::INFINITY) {
-        Some(hit_record) => match hit_record.material.scatter(r, &hit_record) {
-            Some(scattering) => {
-                return ray_color(&scattering.scattered, world, depth - 1) * scattering.attenuation;
-            }
-            None => {
-                return color::Color(0.0, 0.0, 0.0);
-            }
-        },
-        None => {
-            // Gradient white -> vlue background.
-            let unit_direction = r.direction.unit_vector();
-            let t = 0.5 * (unit_direction.y() + 1.0);
-            return linear_blend(&color::WHITE, &color::BLUE, t);
-        }
-    }
-}
-
-// TODO: Argument parsing
-static WRITE_PPM: bool = true;
-
 fn write_pixel(
     pixel_color: &color::Color,
-    samples_per_pixel: i32,
+    display: &tonemap::DisplayTransform,
     pixel: &mut pixel_canvas::Color,
-    ppm_writer: &mut PpmWriter,
 ) {
-    let mut r = pixel_color.0;
-    let mut g = pixel_color.1;
-    let mut b = pixel_color.2;
-
-    let scale = 1.0 / samples_per_pixel as f32;
-    // Gamma correct for gamma=2
-    r = (scale * r).sqrt();
-    g = (scale * g).sqrt();
-    b = (scale * b).sqrt();
-
-    r = 256.0 * r.clamp(0.0, 0.999);
-    g = 256.0 * g.clamp(0.0, 0.999);
-    b = 256.0 * b.clamp(0.0, 0.999);
-
-    *pixel = Color {
-        r: r as u8,
-        g: g as u8,
-        b: b as u8,
-    };
+    let [r, g, b] = display.to_8bit(*pixel_color);
 
-    if WRITE_PPM {
-        ppm_writer
-            .write_color(color::Color(r, g, b))
-            .expect("writing color failed");
-    }
+    *pixel = Color { r, g, b };
 }
 
 fn random_scene() -> HittableList {
@@ -132,26 +114,73 @@ fn random_scene() -> HittableList {
     let material3 = Rc::new(material::Metal::new(color::Color(0.7, 0.6, 0.5), 0.0));
     world.add(Box::new(sphere::Sphere::new(vec3::Point3(4.0, 1.0, 0.0), 1.0, material3.clone())));
 
-
-    return world;
+    world
 }
 
 fn main() -> std::io::Result<()> {
+    let options = Options::from_args();
+
     let aspect_ratio = 3.0 / 2.0;
-    let image_width: usize = 1200;
+    let image_width = options.image_width;
     let image_height: usize = (image_width as f32 / aspect_ratio) as usize;
-    let samples_per_pixel = 500;
-    let max_depth = 50;
+    let samples_per_pixel = options.samples_per_pixel;
+    let max_depth = options.max_depth;
 
     let canvas = Canvas::new(image_width, image_height)
         .title("Tile")
         .render_on_change(true);
-    let mut ppm_writer = match PpmWriter::new(&image_width, &image_height) {
-        Err(why) => panic!("couldn't create writer {}", why),
-        Ok(ppm_writer) => ppm_writer,
+    let mut ppm_writer = if options.write_ppm {
+        Some(PpmWriter::create(
+            &options.output,
+            options.ppm_format,
+            options.ppm_max_value,
+        )?)
+    } else {
+        None
+    };
+    let aov_prefix = options.output_stem();
+    let write_aovs = options.write_aovs;
+    let denoise = options.denoise;
+    let display = options.display;
+    let pixel_filter = options.filter();
+    let spectral_film = if options.spectral {
+        Some(spectrum::SpectralFilm::new())
+    } else {
+        None
+    };
+    let mut aov_buffers = if write_aovs || denoise {
+        Some(aov::AovBuffers::new(image_width, image_height))
+    } else {
+        None
     };
 
-    let world = random_scene();
+    let background: Box<dyn background::Background> = match &options.environment {
+        Some(path) => {
+            let image = image_reader::load_image(path, image_reader::ColorSpace::Srgb)?;
+            Box::new(background::EnvironmentMap::new(
+                image,
+                util::degrees_to_radians(options.environment_rotation),
+                options.environment_intensity,
+            ))
+        }
+        None if options.sky => Box::new(sky::PreethamSky::from_angles(
+            options.sun_elevation,
+            options.sun_azimuth,
+            options.turbidity,
+            options.sky_intensity,
+        )),
+        None => match options.background_color {
+            Some(background_color) => {
+                Box::new(background::ConstantBackground::new(background_color))
+            }
+            // Gradient white -> blue background.
+            None => Box::new(background::GradientBackground::new(
+                color::WHITE,
+                color::BLUE,
+            )),
+        },
+    };
+    let scene = scene::Scene::new(random_scene(), background);
 
     let lookfrom = vec3::Point3(13.0, 2.0, 3.0);
     let lookat = vec3::Point3(0.0, 0.0, 0.0);
@@ -169,23 +198,76 @@ fn main() -> std::io::Result<()> {
     );
 
     canvas.render(move |_state, image| {
+        let mut film = Film::new(image_width, image_height, pixel_filter);
+        let mut aov_samples = Vec::with_capacity(samples_per_pixel);
         for j in (0..image_height).rev() {
             eprint!("\rScanlines remaining: {}    ", j);
+            // Canvas rows start from the bottom, image rows from the top.
+            let y = image_height - 1 - j;
             for i in 0..image_width {
-                let mut pixel_color = color::Color(0.0, 0.0, 0.0);
+                aov_samples.clear();
                 for _ in 0..samples_per_pixel {
-                    let u = ((i as f32) + util::random_float()) / ((image_width - 1) as f32);
-                    let v = ((j as f32) + util::random_float()) / ((image_height - 1) as f32);
+                    let offset_u = util::random_float();
+                    let offset_v = util::random_float();
+                    let u = ((i as f32) + offset_u) / ((image_width - 1) as f32);
+                    let v = ((j as f32) + offset_v) / ((image_height - 1) as f32);
 
                     // Generate ray going from camera origin to the current pixel.
-                    let r = camera.generate_ray(u, v);
-                    pixel_color = pixel_color + ray_color(&r, &world, max_depth);
+                    let mut r = camera.generate_ray(u, v);
+                    if spectral_film.is_some() {
+                        r.wavelength = Some(spectrum::sample_wavelength(util::random_float()));
+                    }
+                    let sample_color = if aov_buffers.is_some() {
+                        let mut aov_sample = None;
+                        let sample_color = ray_color(&r, &scene, max_depth, Some(&mut aov_sample));
+                        aov_samples.push(aov_sample);
+                        sample_color
+                    } else {
+                        ray_color(&r, &scene, max_depth, None)
+                    };
+                    let sample_color = match (&spectral_film, r.wavelength) {
+                        (Some(spectral_film), Some(wavelength)) => {
+                            sample_color * spectral_film.weight(wavelength)
+                        }
+                        _ => sample_color,
+                    };
+                    film.add_sample(
+                        (i as f32) + offset_u,
+                        (y as f32) + 1.0 - offset_v,
+                        sample_color,
+                    );
                 }
-                let pixel: &mut Color = &mut image[RC(j, i)];
-                write_pixel(&pixel_color, samples_per_pixel, pixel, &mut ppm_writer);
+                if let Some(aov_buffers) = aov_buffers.as_mut() {
+                    aov_buffers.write_pixel(i, y, &aov_samples);
+                }
+            }
+        }
+        eprintln!();
+
+        let mut beauty = film.resolve();
+        if let Some(aov_buffers) = aov_buffers.take() {
+            if denoise {
+                beauty = denoise::denoise(&beauty, &aov_buffers, &Default::default());
+            }
+            if write_aovs {
+                if let Err(why) = aov_buffers.write(&aov_prefix) {
+                    eprintln!("couldn't write AOVs: {}", why);
+                }
+            }
+        }
+
+        for y in 0..image_height {
+            for x in 0..image_width {
+                let pixel: &mut Color = &mut image[RC(image_height - 1 - y, x)];
+                write_pixel(&beauty.get(x, y), &display, pixel);
+            }
+        }
+
+        if let Some(mut ppm_writer) = ppm_writer.take() {
+            if let Err(why) = ppm_writer.write_image(&beauty, &display) {
+                eprintln!("couldn't write image: {}", why);
             }
         }
-        eprintln!("");
     });
     Ok(())
 }
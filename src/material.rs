@@ -1,27 +1,103 @@
 use super::color;
 use super::hittable;
+use super::medium;
+use super::microfacet;
+use super::microfacet::Complex;
 use super::ray;
+use super::spectrum;
+use super::thin_film;
 use super::util;
 use super::vec3;
+use std::f32::consts::PI;
 
+// A ray continuing the path after hitting a material.
+// attenuation: Weight of the scattered ray, the BSDF times cosine divided by the pdf.
+// pdf: Solid angle density the direction was sampled with. None for perfectly
+// specular directions, which can't be found by sampling lights.
 #[derive(Debug, Copy, Clone)]
 pub struct Scattering {
     pub scattered: ray::Ray,
     pub attenuation: color::Color,
+    pub pdf: Option<f32>,
+}
+
+// The BSDF times cosine for a given direction, and the density scatter picks it with.
+#[derive(Debug, Copy, Clone)]
+pub struct Evaluation {
+    pub value: color::Color,
+    pub pdf: f32,
 }
 
 pub trait Material {
     fn scatter(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> Option<Scattering>;
+
+    // The surface color at the hit point, independent of lighting.
+    fn albedo(&self, _hit_record: &hittable::HitRecord) -> color::Color {
+        color::WHITE
+    }
+
+    // Evaluate scattering from the incoming ray into the given unit direction,
+    // used to sample lights directly. Materials which only scatter into
+    // specular directions return None.
+    fn eval(
+        &self,
+        _ray: &ray::Ray,
+        _hit_record: &hittable::HitRecord,
+        _direction: &vec3::Vec3,
+    ) -> Option<Evaluation> {
+        None
+    }
+
+    // What fills the inside of objects made of this material, entered by rays
+    // transmitted through the front face. None for empty space.
+    fn medium(&self) -> Option<medium::Medium> {
+        None
+    }
+
+    // Whether hits are on a surface with an inside, which rays continuing through
+    // it enter or leave. Volumes, whose hits are scattering events, return false.
+    fn is_surface(&self) -> bool {
+        true
+    }
+
+    // Probability of light passing straight through the hit point without touching
+    // the material, as through holes cut into the surface. Light that does isn't
+    // entering or leaving the object.
+    fn transparency(&self, _hit_record: &hittable::HitRecord) -> f32 {
+        0.0
+    }
 }
 
+// vertex_colors: Whether to use the vertex colors of meshes that have them instead
+// of the albedo.
 #[derive(Debug, Copy, Clone)]
 pub struct Lambertian {
     albedo: color::Color,
+    vertex_colors: bool,
 }
 
 impl Lambertian {
     pub fn new(albedo: color::Color) -> Self {
-        Lambertian { albedo }
+        Lambertian {
+            albedo,
+            vertex_colors: false,
+        }
+    }
+
+    // Colored by the vertex colors of meshes, such as those of scanned models, and by
+    // albedo on other objects.
+    pub fn vertex_colors(albedo: color::Color) -> Self {
+        Lambertian {
+            vertex_colors: true,
+            ..Lambertian::new(albedo)
+        }
+    }
+
+    fn albedo_at(&self, hit_record: &hittable::HitRecord) -> color::Color {
+        match hit_record.vertex_color {
+            Some(vertex_color) if self.vertex_colors => vertex_color,
+            _ => self.albedo,
+        }
     }
 }
 
@@ -31,28 +107,79 @@ impl Material for Lambertian {
         //let target = hit_record.p + hit_record.normal + vec3::random_unit_vector();
         //let target = hit_record.p + vec3::random_in_hemisphere(&hit_record.normal);
 
-        let mut scatter_direction = hit_record.normal * vec3::random_unit_vector();
+        let mut scatter_direction = hit_record.normal + vec3::random_unit_vector();
 
         if scatter_direction.near_zero() {
             scatter_direction = hit_record.normal;
         }
 
+        // Directions are cosine distributed, which cancels with the BRDF's cosine.
+        let cosine = scatter_direction.unit_vector().dot(hit_record.normal);
         Some(Scattering {
             scattered: ray::Ray::new(hit_record.p, scatter_direction),
-            attenuation: self.albedo,
+            attenuation: self.albedo_at(hit_record),
+            pdf: Some(cosine / PI),
+        })
+    }
+
+    fn albedo(&self, hit_record: &hittable::HitRecord) -> color::Color {
+        self.albedo_at(hit_record)
+    }
+
+    fn eval(
+        &self,
+        _ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        direction: &vec3::Vec3,
+    ) -> Option<Evaluation> {
+        let cosine = direction.dot(hit_record.normal).max(0.0);
+        Some(Evaluation {
+            value: self.albedo_at(hit_record) * (cosine / PI),
+            pdf: cosine / PI,
         })
     }
 }
 
+// thin_film: Optional coating, making the metal iridescent.
 #[derive(Debug, Copy, Clone)]
 pub struct Metal {
     albedo: color::Color,
     fuzziness: f32,
+    thin_film: Option<thin_film::ThinFilm>,
 }
 
 impl Metal {
     pub fn new(albedo: color::Color, fuzziness: f32) -> Self {
-        Metal { albedo, fuzziness }
+        Metal {
+            albedo,
+            fuzziness,
+            thin_film: None,
+        }
+    }
+
+    pub fn with_thin_film(albedo: color::Color, fuzziness: f32, film: thin_film::ThinFilm) -> Self {
+        Metal {
+            thin_film: Some(film),
+            ..Metal::new(albedo, fuzziness)
+        }
+    }
+
+    fn reflectance(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> color::Color {
+        let film = match self.thin_film {
+            Some(film) => film,
+            None => return self.albedo,
+        };
+        let cos_theta = -ray.direction.unit_vector().dot(hit_record.normal);
+        // Coat a conductor matching the metal's color.
+        film.reflectance_color(
+            cos_theta,
+            1.0,
+            |lambda| {
+                let reflectivity = spectrum::color_at(self.albedo, lambda);
+                thin_film::conductor_from_reflectivity(reflectivity, reflectivity)
+            },
+            ray.wavelength,
+        )
     }
 }
 
@@ -66,21 +193,54 @@ impl Material for Metal {
         if scattered.direction.dot(hit_record.normal) > 0.0 {
             return Some(Scattering {
                 scattered,
-                attenuation: self.albedo,
+                attenuation: self.reflectance(ray, hit_record),
+                pdf: None,
             });
         }
         None
     }
+
+    fn albedo(&self, _hit_record: &hittable::HitRecord) -> color::Color {
+        self.albedo
+    }
 }
 
+// ior: Index of refraction, which disperses light in spectral mode if it depends
+// on the wavelength.
+// medium: Absorption inside the dielectric, tinting it more the thicker it is.
+// thin_film: Optional coating on the outside, like the skin of a soap bubble.
 #[derive(Debug, Copy, Clone)]
 pub struct Dielectric {
-    ir: f32,
+    ior: spectrum::RefractiveIndex,
+    medium: Option<medium::Medium>,
+    thin_film: Option<thin_film::ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(ir: f32) -> Self {
-        Dielectric { ir }
+        Dielectric::dispersive(spectrum::RefractiveIndex::Constant(ir))
+    }
+
+    pub fn dispersive(ior: spectrum::RefractiveIndex) -> Self {
+        Dielectric {
+            ior,
+            medium: None,
+            thin_film: None,
+        }
+    }
+
+    pub fn with_thin_film(ir: f32, film: thin_film::ThinFilm) -> Self {
+        Dielectric {
+            thin_film: Some(film),
+            ..Dielectric::new(ir)
+        }
+    }
+
+    pub fn with_absorption(ir: f32, absorption: medium::Medium) -> Self {
+        Dielectric {
+            medium: Some(absorption),
+            ..Dielectric::new(ir)
+        }
     }
 
     pub fn reflectance(cosine: f32, refraction_ratio: f32) -> f32 {
@@ -93,29 +253,467 @@ impl Dielectric {
 
 impl Material for Dielectric {
     fn scatter(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> Option<Scattering> {
-        let refraction_ratio = if hit_record.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
+        let ir = self.ior.at(ray.wavelength);
+        let refraction_ratio = if hit_record.front_face { 1.0 / ir } else { ir };
 
         let unit_direction = ray.direction.unit_vector();
         let cos_theta = (-(unit_direction).dot(hit_record.normal)).min(1.0);
         let sin_theta = (1.0 - (cos_theta * cos_theta)).sqrt();
         let cannot_refract = (refraction_ratio * sin_theta) > 1.0;
 
-        let direction = if cannot_refract
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > util::random_float()
-        {
-            vec3::reflect(&unit_direction, &hit_record.normal)
+        let reflectance = match self.thin_film {
+            Some(film) if hit_record.front_face => film.reflectance_color(
+                cos_theta,
+                1.0,
+                |lambda| Complex::from(self.ior.at(Some(lambda))),
+                ray.wavelength,
+            ),
+            _ => color::WHITE * Dielectric::reflectance(cos_theta, refraction_ratio),
+        };
+        // Pick reflection with the average reflectance, weighing each channel by how
+        // much it differs from that.
+        let reflect_probability = (reflectance.0 + reflectance.1 + reflectance.2) / 3.0;
+
+        let (direction, attenuation) = if cannot_refract {
+            (
+                vec3::reflect(&unit_direction, &hit_record.normal),
+                color::WHITE,
+            )
+        } else if reflect_probability > util::random_float() {
+            (
+                vec3::reflect(&unit_direction, &hit_record.normal),
+                reflectance / reflect_probability,
+            )
         } else {
-            vec3::refract(&unit_direction, &hit_record.normal, refraction_ratio)
+            (
+                vec3::refract(&unit_direction, &hit_record.normal, refraction_ratio),
+                (color::WHITE - reflectance) / (1.0 - reflect_probability),
+            )
         };
 
         let scattered = ray::Ray::new(hit_record.p, direction);
         Some(Scattering {
             scattered,
-            attenuation: color::Color::new(1.0, 1.0, 1.0),
+            attenuation,
+            pdf: None,
+        })
+    }
+
+    fn medium(&self) -> Option<medium::Medium> {
+        self.medium
+    }
+}
+
+// A metal with GGX microfacet roughness and a complex index of refraction.
+// eta, k: Real and imaginary parts of the index of refraction, per color channel.
+// roughness: Perceptual roughness in [0, 1], 0 being a perfect mirror.
+#[derive(Debug, Copy, Clone)]
+pub struct RoughConductor {
+    eta: color::Color,
+    k: color::Color,
+    distribution: microfacet::TrowbridgeReitz,
+}
+
+impl RoughConductor {
+    pub fn new(eta: color::Color, k: color::Color, roughness: f32) -> Self {
+        RoughConductor {
+            eta,
+            k,
+            distribution: microfacet::TrowbridgeReitz::from_roughness(roughness),
+        }
+    }
+
+    pub fn gold(roughness: f32) -> Self {
+        RoughConductor::new(
+            color::Color(0.143, 0.374, 1.442),
+            color::Color(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f32) -> Self {
+        RoughConductor::new(
+            color::Color(0.200, 0.924, 1.102),
+            color::Color(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f32) -> Self {
+        RoughConductor::new(
+            color::Color(1.657, 0.880, 0.521),
+            color::Color(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+}
+
+impl Material for RoughConductor {
+    fn scatter(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> Option<Scattering> {
+        let frame = vec3::Frame::from_normal(&hit_record.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vector());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        if self.distribution.effectively_smooth() {
+            let wi = vec3::Vec3(-wo.x(), -wo.y(), wo.z());
+            return Some(Scattering {
+                scattered: ray::Ray::new(hit_record.p, frame.to_world(&wi)),
+                attenuation: microfacet::fresnel_conductor(wo.z(), self.eta, self.k),
+                pdf: None,
+            });
+        }
+
+        let wm = self.distribution.sample_visible_normal(
+            &wo,
+            util::random_float(),
+            util::random_float(),
+        );
+        let wi = vec3::reflect(&-wo, &wm);
+        // Single scattering, light reflected below the surface is lost.
+        if wi.z() <= 0.0 {
+            return None;
+        }
+
+        let fresnel = microfacet::fresnel_conductor(wo.dot(wm), self.eta, self.k);
+        let pdf = self.distribution.visible_d(&wo, &wm) / (4.0 * wo.dot(wm));
+        Some(Scattering {
+            scattered: ray::Ray::new(hit_record.p, frame.to_world(&wi)),
+            attenuation: fresnel * (self.distribution.g(&wo, &wi) / self.distribution.g1(&wo)),
+            pdf: Some(pdf),
+        })
+    }
+
+    fn albedo(&self, _hit_record: &hittable::HitRecord) -> color::Color {
+        microfacet::fresnel_conductor(1.0, self.eta, self.k)
+    }
+
+    fn eval(
+        &self,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        direction: &vec3::Vec3,
+    ) -> Option<Evaluation> {
+        if self.distribution.effectively_smooth() {
+            return None;
+        }
+        let frame = vec3::Frame::from_normal(&hit_record.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vector());
+        let wi = frame.to_local(direction);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Some(Evaluation {
+                value: color::Color(0.0, 0.0, 0.0),
+                pdf: 0.0,
+            });
+        }
+
+        let wm = (wo + wi).unit_vector();
+        let fresnel = microfacet::fresnel_conductor(wo.dot(wm), self.eta, self.k);
+        let brdf =
+            self.distribution.d(&wm) * self.distribution.g(&wo, &wi) / (4.0 * wo.z() * wi.z());
+        Some(Evaluation {
+            value: fresnel * (brdf * wi.z()),
+            pdf: self.distribution.visible_d(&wo, &wm) / (4.0 * wo.dot(wm)),
+        })
+    }
+}
+
+// Frosted glass, a dielectric with GGX microfacet roughness.
+// ior: Index of refraction, which disperses light in spectral mode if it depends
+// on the wavelength.
+// roughness: Perceptual roughness in [0, 1], 0 being perfectly smooth glass.
+// medium: Absorption inside the dielectric.
+#[derive(Debug, Copy, Clone)]
+pub struct RoughDielectric {
+    ior: spectrum::RefractiveIndex,
+    distribution: microfacet::TrowbridgeReitz,
+    medium: Option<medium::Medium>,
+}
+
+impl RoughDielectric {
+    pub fn new(ir: f32, roughness: f32) -> Self {
+        RoughDielectric::dispersive(spectrum::RefractiveIndex::Constant(ir), roughness)
+    }
+
+    pub fn dispersive(ior: spectrum::RefractiveIndex, roughness: f32) -> Self {
+        RoughDielectric {
+            ior,
+            distribution: microfacet::TrowbridgeReitz::from_roughness(roughness),
+            medium: None,
+        }
+    }
+
+    pub fn with_absorption(ir: f32, roughness: f32, absorption: medium::Medium) -> Self {
+        RoughDielectric {
+            medium: Some(absorption),
+            ..RoughDielectric::new(ir, roughness)
+        }
+    }
+
+    // Ratio of the index of refraction on the far side of the surface to the near side,
+    // at the wavelength of the ray.
+    fn relative_ir(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> f32 {
+        let ir = self.ior.at(ray.wavelength);
+        if hit_record.front_face {
+            ir
+        } else {
+            1.0 / ir
+        }
+    }
+
+    // Weight left after sampling visible normals, G / G1.
+    fn shadowing(&self, wo: &vec3::Vec3, wi: &vec3::Vec3, smooth: bool) -> f32 {
+        if smooth {
+            return 1.0;
+        }
+        self.distribution.g(wo, wi) / self.distribution.g1(wo)
+    }
+}
+
+// Refract wo through a surface with normal n, returning the transmitted direction.
+// Both vectors point away from the surface, eta is the relative index of refraction.
+fn refract_microfacet(wo: &vec3::Vec3, n: &vec3::Vec3, eta: f32) -> Option<vec3::Vec3> {
+    let cos_theta_i = wo.dot(*n);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(-*wo / eta + *n * (cos_theta_i / eta - cos_theta_t))
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> Option<Scattering> {
+        let eta = self.relative_ir(ray, hit_record);
+        let frame = vec3::Frame::from_normal(&hit_record.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vector());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let smooth = self.distribution.effectively_smooth();
+        let wm = if smooth {
+            vec3::Vec3(0.0, 0.0, 1.0)
+        } else {
+            self.distribution
+                .sample_visible_normal(&wo, util::random_float(), util::random_float())
+        };
+        let reflectance = microfacet::fresnel_dielectric(wo.dot(wm), eta);
+        let visible_d = self.distribution.visible_d(&wo, &wm);
+
+        // Pick reflection or transmission in proportion to the Fresnel term, which
+        // then cancels out of the weight.
+        if util::random_float() < reflectance {
+            let wi = vec3::reflect(&-wo, &wm);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            return Some(Scattering {
+                scattered: ray::Ray::new(hit_record.p, frame.to_world(&wi)),
+                attenuation: color::WHITE * self.shadowing(&wo, &wi, smooth),
+                pdf: if smooth {
+                    None
+                } else {
+                    Some(reflectance * visible_d / (4.0 * wo.dot(wm)))
+                },
+            });
+        }
+
+        let wi = refract_microfacet(&wo, &wm, eta)?;
+        if wi.z() >= 0.0 {
+            return None;
+        }
+        let denominator = wi.dot(wm) + wo.dot(wm) / eta;
+        let pdf = (1.0 - reflectance) * visible_d * wi.dot(wm).abs() / (denominator * denominator);
+        Some(Scattering {
+            scattered: ray::Ray::new(hit_record.p, frame.to_world(&wi)),
+            // Radiance is compressed into a smaller solid angle when entering a denser medium.
+            attenuation: color::WHITE * (self.shadowing(&wo, &wi, smooth) / (eta * eta)),
+            pdf: if smooth { None } else { Some(pdf) },
         })
     }
+
+    fn eval(
+        &self,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        direction: &vec3::Vec3,
+    ) -> Option<Evaluation> {
+        if self.distribution.effectively_smooth() {
+            return None;
+        }
+        let eta = self.relative_ir(ray, hit_record);
+        let frame = vec3::Frame::from_normal(&hit_record.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vector());
+        let wi = frame.to_local(direction);
+        let black = Evaluation {
+            value: color::Color(0.0, 0.0, 0.0),
+            pdf: 0.0,
+        };
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return Some(black);
+        }
+
+        let reflect = wi.z() > 0.0;
+        // The generalized half vector, which the surface must have been oriented along.
+        let mut wm = if reflect { wo + wi } else { wi * eta + wo };
+        if wm.near_zero() {
+            return Some(black);
+        }
+        wm = wm.unit_vector();
+        if wm.z() < 0.0 {
+            wm = -wm;
+        }
+        // Discard microfacets seen from behind.
+        if wm.dot(wi) * wi.z() < 0.0 || wm.dot(wo) * wo.z() < 0.0 {
+            return Some(black);
+        }
+
+        let reflectance = microfacet::fresnel_dielectric(wo.dot(wm), eta);
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(&wo, &wi);
+        let visible_d = self.distribution.visible_d(&wo, &wm);
+        if reflect {
+            let brdf = d * g * reflectance / (4.0 * wo.z() * wi.z());
+            return Some(Evaluation {
+                value: color::WHITE * (brdf * wi.z()),
+                pdf: reflectance * visible_d / (4.0 * wo.dot(wm)),
+            });
+        }
+
+        let denominator = wi.dot(wm) + wo.dot(wm) / eta;
+        let btdf = (1.0 - reflectance) * d * g * (wi.dot(wm) * wo.dot(wm)).abs()
+            / (wi.z() * wo.z() * denominator * denominator).abs()
+            / (eta * eta);
+        Some(Evaluation {
+            value: color::WHITE * (btdf * wi.z().abs()),
+            pdf: (1.0 - reflectance) * visible_d * wi.dot(wm).abs() / (denominator * denominator),
+        })
+    }
+
+    fn medium(&self) -> Option<medium::Medium> {
+        self.medium
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    // Scattering a ray must agree with evaluating the direction it scattered into.
+    pub fn check_consistent(material: Rc<dyn Material>, front_face: bool) {
+        let normal = vec3::Vec3(0.0, 1.0, 0.0);
+        let outward_normal = if front_face { normal } else { -normal };
+        let ray = ray::Ray::new(vec3::Point3(-1.0, 1.0, 0.3), vec3::Vec3(1.0, -1.0, -0.3));
+        let hit_record = hittable::HitRecord::new(
+            1.0,
+            vec3::Point3(0.0, 0.0, 0.0),
+            &ray,
+            outward_normal,
+            material.clone(),
+        );
+
+        let mut checked = 0;
+        for _ in 0..64 {
+            let scattering = match material.scatter(&ray, &hit_record) {
+                Some(scattering) => scattering,
+                None => continue,
+            };
+            let pdf = scattering.pdf.unwrap();
+            let direction = scattering.scattered.direction.unit_vector();
+            let evaluation = material.eval(&ray, &hit_record, &direction).unwrap();
+
+            assert!((evaluation.pdf - pdf).abs() <= 1e-3 * pdf);
+            let weight = evaluation.value / evaluation.pdf;
+            assert!((weight - scattering.attenuation).norm() <= 1e-3 * weight.norm().max(1.0));
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn test_lambertian_consistent() {
+        check_consistent(Rc::new(Lambertian::new(color::Color(0.5, 0.2, 0.1))), true);
+    }
+
+    #[test]
+    fn test_rough_conductor_consistent() {
+        check_consistent(Rc::new(RoughConductor::gold(0.4)), true);
+    }
+
+    #[test]
+    fn test_rough_dielectric_consistent() {
+        check_consistent(Rc::new(RoughDielectric::new(1.5, 0.5)), true);
+        check_consistent(Rc::new(RoughDielectric::new(1.5, 0.5)), false);
+    }
+
+    #[test]
+    fn test_rough_dielectric_dispersion() {
+        // Light through a rough prism spreads out differently for each wavelength.
+        let evaluate = |material: &RoughDielectric, wavelength: f32| {
+            let mut ray = ray::Ray::new(vec3::Point3(-1.0, 1.0, 0.0), vec3::Vec3(1.0, -1.0, 0.0));
+            ray.wavelength = Some(wavelength);
+            let hit_record = hittable::HitRecord::new(
+                1.0,
+                vec3::Point3(0.0, 0.0, 0.0),
+                &ray,
+                vec3::Vec3(0.0, 1.0, 0.0),
+                Rc::new(*material),
+            );
+            let direction = vec3::Vec3(0.4, -1.0, 0.0).unit_vector();
+            material.eval(&ray, &hit_record, &direction).unwrap().value
+        };
+        let diamond = RoughDielectric::dispersive(spectrum::RefractiveIndex::diamond(), 0.2);
+        assert!(evaluate(&diamond, 400.0) != evaluate(&diamond, 700.0));
+        let glass = RoughDielectric::new(1.5, 0.2);
+        assert!(evaluate(&glass, 400.0) == evaluate(&glass, 700.0));
+    }
+
+    #[test]
+    fn test_thin_film_conserves_energy() {
+        // A soap bubble reflects some colors and transmits the rest.
+        let bubble: Rc<dyn Material> = Rc::new(Dielectric::with_thin_film(
+            1.0,
+            thin_film::ThinFilm::new(300.0, 1.33),
+        ));
+        let ray = ray::Ray::new(vec3::Point3(0.0, 1.0, 0.0), vec3::Vec3(0.0, -1.0, 0.0));
+        let hit_record = hittable::HitRecord::new(
+            1.0,
+            vec3::Point3(0.0, 0.0, 0.0),
+            &ray,
+            vec3::Vec3(0.0, 1.0, 0.0),
+            bubble.clone(),
+        );
+        util::seed_random(41);
+        let samples = 2000;
+        let mut total = color::Color(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            total = total + bubble.scatter(&ray, &hit_record).unwrap().attenuation;
+        }
+        assert!((total / samples as f32 - color::WHITE).norm() < 0.05);
+
+        let coated = Metal::with_thin_film(
+            color::Color(0.9, 0.9, 0.9),
+            0.0,
+            thin_film::ThinFilm::new(400.0, 1.5),
+        );
+        let attenuation = coated.scatter(&ray, &hit_record).unwrap().attenuation;
+        assert!(attenuation.0 != attenuation.2);
+    }
+
+    #[test]
+    fn test_conductor_presets() {
+        // Gold reflects red more than blue, aluminium is nearly white.
+        let gold = microfacet::fresnel_conductor(
+            1.0,
+            RoughConductor::gold(0.0).eta,
+            RoughConductor::gold(0.0).k,
+        );
+        assert!(gold.0 > gold.2);
+        let aluminium = RoughConductor::aluminium(0.0);
+        let reflectance = microfacet::fresnel_conductor(1.0, aluminium.eta, aluminium.k);
+        assert!(reflectance.2 > 0.9);
+    }
 }
@@ -0,0 +1,259 @@
+use super::color;
+use super::util;
+use super::vec3;
+use std::f32::consts::PI;
+
+// The inside of an object, which light is absorbed and scattered by as it travels
+// through.
+// absorption: Fraction of light absorbed per unit distance, per color channel.
+// scattering: Fraction of light scattered into a new direction per unit distance.
+// anisotropy: Asymmetry of the Henyey-Greenstein phase function.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Medium {
+    pub absorption: color::Color,
+    pub scattering: color::Color,
+    pub anisotropy: f32,
+}
+
+// exp(-coefficient * distance), which is one for a coefficient of zero even over an
+// infinite distance.
+fn attenuate(coefficient: f32, distance: f32) -> f32 {
+    if coefficient == 0.0 {
+        return 1.0;
+    }
+    (-coefficient * distance).exp()
+}
+
+fn channel(c: &color::Color, index: usize) -> f32 {
+    match index {
+        0 => c.0,
+        1 => c.1,
+        _ => c.2,
+    }
+}
+
+impl Medium {
+    pub fn new(absorption: color::Color) -> Self {
+        Medium::scattering(absorption, color::Color(0.0, 0.0, 0.0), 0.0)
+    }
+
+    pub fn scattering(absorption: color::Color, scattering: color::Color, anisotropy: f32) -> Self {
+        Medium {
+            absorption,
+            scattering,
+            anisotropy,
+        }
+    }
+
+    // A medium which light comes out of with the given color after traveling
+    // the given distance through it.
+    pub fn from_color(transmitted: color::Color, distance: f32) -> Self {
+        let coefficient = |c: f32| -c.clamp(1e-6, 1.0).ln() / distance;
+        Medium::new(color::Color(
+            coefficient(transmitted.0),
+            coefficient(transmitted.1),
+            coefficient(transmitted.2),
+        ))
+    }
+
+    pub fn extinction(&self) -> color::Color {
+        self.absorption + self.scattering
+    }
+
+    pub fn is_scattering(&self) -> bool {
+        self.scattering != color::Color(0.0, 0.0, 0.0)
+    }
+
+    // Fraction of light left after traveling the distance, following the Beer-Lambert law.
+    pub fn transmittance(&self, distance: f32) -> color::Color {
+        let extinction = self.extinction();
+        color::Color(
+            attenuate(extinction.0, distance),
+            attenuate(extinction.1, distance),
+            attenuate(extinction.2, distance),
+        )
+    }
+
+    // Follow a ray up to the surface it hits at the given distance. Returns where it
+    // scattered before getting there, if it did, and the weight of the path so far.
+    // Distances are sampled for one randomly chosen channel and weighted by the
+    // average density over all three, so differently colored mean free paths work.
+    pub fn sample_distance(&self, surface_distance: f32) -> (Option<f32>, color::Color) {
+        if !self.is_scattering() {
+            return (None, self.transmittance(surface_distance));
+        }
+
+        let extinction = self.extinction();
+        let chosen = channel(&extinction, (util::random_float() * 3.0) as usize);
+        let distance = if chosen > 0.0 {
+            -(1.0 - util::random_float()).ln() / chosen
+        } else {
+            f32::INFINITY
+        };
+
+        if distance < surface_distance {
+            let transmittance = self.transmittance(distance);
+            let pdf = (extinction.0 * transmittance.0
+                + extinction.1 * transmittance.1
+                + extinction.2 * transmittance.2)
+                / 3.0;
+            if pdf <= 0.0 {
+                return (Some(distance), color::Color(0.0, 0.0, 0.0));
+            }
+            return (Some(distance), self.scattering * transmittance / pdf);
+        }
+
+        let transmittance = self.transmittance(surface_distance);
+        let pdf = (transmittance.0 + transmittance.1 + transmittance.2) / 3.0;
+        if pdf <= 0.0 {
+            return (None, color::Color(0.0, 0.0, 0.0));
+        }
+        (None, transmittance / pdf)
+    }
+
+    // Phase function for light continuing from travelling along direction into
+    // new_direction, both unit vectors.
+    pub fn phase(&self, direction: &vec3::Vec3, new_direction: &vec3::Vec3) -> f32 {
+        henyey_greenstein(direction.dot(*new_direction), self.anisotropy)
+    }
+
+    // Pick a new direction for light travelling along the unit direction, distributed
+    // by the phase function, which then cancels out of the path weight.
+    pub fn sample_phase(&self, direction: &vec3::Vec3) -> vec3::Vec3 {
+        sample_henyey_greenstein(direction, self.anisotropy)
+    }
+}
+
+// Asymmetries this close to -1 or 1 scatter everything straight back or on, where
+// the phase function is infinitely peaked.
+static MAX_ASYMMETRY: f32 = 0.99;
+
+// The Henyey-Greenstein phase function, the density of scattering by an angle with
+// the given cosine. g is the asymmetry, from -1 (backwards) to 1 (forwards), and is
+// kept within MAX_ASYMMETRY of either.
+pub fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let g = g.clamp(-MAX_ASYMMETRY, MAX_ASYMMETRY);
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+// Sample a direction scattered from the unit direction with density henyey_greenstein.
+pub fn sample_henyey_greenstein(direction: &vec3::Vec3, g: f32) -> vec3::Vec3 {
+    let g = g.clamp(-MAX_ASYMMETRY, MAX_ASYMMETRY);
+    let u = util::random_float();
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u
+    } else {
+        let square = (1.0 - g * g) / (1.0 + g - 2.0 * g * u);
+        ((1.0 + g * g - square * square) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * util::random_float();
+    let frame = vec3::Frame::from_normal(direction);
+    frame.to_world(&vec3::Vec3(
+        sin_theta * phi.cos(),
+        sin_theta * phi.sin(),
+        cos_theta,
+    ))
+}
+
+// The media a ray is nested in, innermost last. Objects without a medium push None,
+// so leaving them pops back to whatever they were inside.
+#[derive(Debug, Clone, Default)]
+pub struct MediumStack {
+    entries: Vec<Option<Medium>>,
+}
+
+impl MediumStack {
+    pub fn new() -> Self {
+        MediumStack::default()
+    }
+
+    pub fn enter(&mut self, medium: Option<Medium>) {
+        self.entries.push(medium);
+    }
+
+    // Leaving an object the ray never entered, such as when starting inside it, is ignored.
+    pub fn exit(&mut self) {
+        self.entries.pop();
+    }
+
+    // The medium the ray is currently travelling through, None for empty space.
+    pub fn current(&self) -> Option<&Medium> {
+        self.entries.last().and_then(|medium| medium.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transmittance() {
+        let medium = Medium::from_color(color::Color(0.5, 0.25, 1.0), 2.0);
+        let transmitted = medium.transmittance(2.0);
+        assert!((transmitted - color::Color(0.5, 0.25, 1.0)).norm() < 1e-5);
+        assert!(medium.transmittance(0.0) == color::WHITE);
+        // Twice the distance squares the transmittance.
+        assert!((medium.transmittance(4.0).0 - 0.25).abs() < 1e-5);
+        // Channels without absorption pass everything, however far light travels.
+        assert!(medium.transmittance(f32::INFINITY) == color::Color(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_sample_distance() {
+        // Without absorption, light either scatters or reaches the surface, so the
+        // weights of both average to one in every channel.
+        let medium = Medium::scattering(
+            color::Color(0.0, 0.0, 0.0),
+            color::Color(1.0, 2.0, 4.0),
+            0.0,
+        );
+        let samples = 20000;
+        let mut total = color::Color(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            let (distance, weight) = medium.sample_distance(0.5);
+            assert!(distance.map_or(true, |distance| distance < 0.5));
+            total = total + weight;
+        }
+        let average = total / samples as f32;
+        assert!((average - color::WHITE).norm() < 0.05);
+
+        let absorbing = Medium::new(color::Color(1.0, 0.0, 0.0));
+        let (distance, weight) = absorbing.sample_distance(1.0);
+        assert!(distance.is_none() && weight == absorbing.transmittance(1.0));
+    }
+
+    #[test]
+    fn test_phase_sampling() {
+        let direction = vec3::Vec3(0.0, 0.0, 1.0);
+        for &g in [0.0, 0.7, -0.5].iter() {
+            let medium = Medium::scattering(color::WHITE, color::WHITE, g);
+            let samples = 20000;
+            let mut mean_cosine = 0.0;
+            for _ in 0..samples {
+                let new_direction = medium.sample_phase(&direction);
+                assert!((new_direction.norm() - 1.0).abs() < 1e-4);
+                mean_cosine += new_direction.dot(direction) / samples as f32;
+            }
+            // The asymmetry is the average cosine of the scattering angle.
+            assert!((mean_cosine - g).abs() < 0.03);
+        }
+    }
+
+    #[test]
+    fn test_medium_stack() {
+        let glass = Medium::new(color::Color(1.0, 0.0, 0.0));
+        let mut stack = MediumStack::new();
+        assert!(stack.current().is_none());
+
+        stack.enter(Some(glass));
+        stack.enter(None);
+        assert!(stack.current().is_none());
+        stack.exit();
+        assert!(stack.current() == Some(&glass));
+        stack.exit();
+        stack.exit();
+        assert!(stack.current().is_none());
+    }
+}
@@ -0,0 +1,331 @@
+use super::color;
+use super::vec3;
+use std::f32::consts::PI;
+use std::ops;
+
+// Minimal complex numbers, for the Fresnel equations of conductors.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+impl Complex {
+    pub fn new(re: f32, im: f32) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn norm_squared(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub fn sqrt(self) -> Self {
+        let n = self.norm_squared().sqrt();
+        if n == 0.0 {
+            return Complex::new(0.0, 0.0);
+        }
+        let t1 = (0.5 * (n + self.re.abs())).sqrt();
+        let t2 = 0.5 * self.im / t1;
+        if self.re >= 0.0 {
+            Complex::new(t1, t2)
+        } else {
+            Complex::new(t2.abs(), t1.copysign(self.im))
+        }
+    }
+
+    pub fn exp(self) -> Self {
+        let scale = self.re.exp();
+        Complex::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+}
+
+impl ops::Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl ops::Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl ops::Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl ops::Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let scale = 1.0 / rhs.norm_squared();
+        Complex::new(
+            scale * (self.re * rhs.re + self.im * rhs.im),
+            scale * (self.im * rhs.re - self.re * rhs.im),
+        )
+    }
+}
+
+impl From<f32> for Complex {
+    fn from(re: f32) -> Self {
+        Complex::new(re, 0.0)
+    }
+}
+
+// Fresnel reflectance of a dielectric interface for unpolarized light.
+// cos_theta_i: Cosine of the incident angle, negative when arriving from inside.
+// eta: Index of refraction of the inside divided by that of the outside.
+pub fn fresnel_dielectric(cos_theta_i: f32, eta: f32) -> f32 {
+    let mut cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let mut eta = eta;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // Total internal reflection.
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+// Fresnel reflectance of an interface with a complex index of refraction eta + ik.
+pub fn fresnel_complex(cos_theta_i: f32, eta: Complex) -> f32 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = Complex::from(sin2_theta_i) / (eta * eta);
+    let cos_theta_t = (Complex::from(1.0) - sin2_theta_t).sqrt();
+    let cos_i = Complex::from(cos_theta_i);
+
+    let r_parallel = (eta * cos_i - cos_theta_t) / (eta * cos_i + cos_theta_t);
+    let r_perpendicular = (cos_i - eta * cos_theta_t) / (cos_i + eta * cos_theta_t);
+    0.5 * (r_parallel.norm_squared() + r_perpendicular.norm_squared())
+}
+
+// Fresnel reflectance of a conductor for each color channel.
+pub fn fresnel_conductor(cos_theta_i: f32, eta: color::Color, k: color::Color) -> color::Color {
+    color::Color(
+        fresnel_complex(cos_theta_i, Complex::new(eta.0, k.0)),
+        fresnel_complex(cos_theta_i, Complex::new(eta.1, k.1)),
+        fresnel_complex(cos_theta_i, Complex::new(eta.2, k.2)),
+    )
+}
+
+// The Trowbridge-Reitz (GGX) microfacet distribution.
+// All directions are unit vectors in the local shading frame, with the normal along z.
+// alpha: Width of the distribution, the square of the perceptual roughness.
+#[derive(Debug, Copy, Clone)]
+pub struct TrowbridgeReitz {
+    pub alpha: f32,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha: f32) -> Self {
+        TrowbridgeReitz { alpha }
+    }
+
+    // Distribution with an alpha of roughness squared, which varies more evenly.
+    pub fn from_roughness(roughness: f32) -> Self {
+        TrowbridgeReitz::new(roughness.clamp(0.0, 1.0).powi(2))
+    }
+
+    // Below this alpha surfaces are treated as perfectly smooth.
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha < 1e-3
+    }
+
+    // Density of microfacet normals wm, per unit projected area.
+    pub fn d(&self, wm: &vec3::Vec3) -> f32 {
+        let cos2_theta = wm.z() * wm.z();
+        if cos2_theta <= 0.0 {
+            return 0.0;
+        }
+        let tan2_theta = (1.0 - cos2_theta) / cos2_theta;
+        let alpha2 = self.alpha * self.alpha;
+        let e = 1.0 + tan2_theta / alpha2;
+        1.0 / (PI * alpha2 * cos2_theta * cos2_theta * e * e)
+    }
+
+    // Smith's auxiliary function, the ratio of hidden to visible microfacet area.
+    pub fn lambda(&self, w: &vec3::Vec3) -> f32 {
+        let cos2_theta = w.z() * w.z();
+        if cos2_theta <= 0.0 {
+            return f32::INFINITY;
+        }
+        let tan2_theta = (1.0 - cos2_theta) / cos2_theta;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2_theta).sqrt() - 1.0)
+    }
+
+    // Fraction of microfacets visible from w.
+    pub fn g1(&self, w: &vec3::Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Fraction of microfacets visible from both wo and wi.
+    pub fn g(&self, wo: &vec3::Vec3, wi: &vec3::Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of normals visible from wo.
+    pub fn visible_d(&self, wo: &vec3::Vec3, wm: &vec3::Vec3) -> f32 {
+        if wo.z() == 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) / wo.z().abs() * self.d(wm) * wo.dot(*wm).max(0.0)
+    }
+
+    // Sample a microfacet normal visible from wo, see Heitz 2018, "Sampling the GGX
+    // Distribution of Visible Normals". The density is visible_d.
+    pub fn sample_visible_normal(&self, wo: &vec3::Vec3, u1: f32, u2: f32) -> vec3::Vec3 {
+        // Stretch the view direction so the distribution becomes a hemisphere.
+        let mut wh = vec3::Vec3(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).unit_vector();
+        if wh.z() < 0.0 {
+            wh = -wh;
+        }
+
+        let length_squared = wh.x() * wh.x() + wh.y() * wh.y();
+        let t1 = if length_squared > 0.0 {
+            vec3::Vec3(-wh.y(), wh.x(), 0.0) / length_squared.sqrt()
+        } else {
+            vec3::Vec3(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(t1);
+
+        // Sample the projected half disk.
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let mut p2 = r * phi.sin();
+        let s = 0.5 * (1.0 + wh.z());
+        p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * p2;
+
+        let nh = t1 * p1 + t2 * p2 + wh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        // Unstretch back to the ellipsoid.
+        vec3::Vec3(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(1e-6)).unit_vector()
+    }
+}
+
+// The generalized Trowbridge-Reitz distribution with gamma 1 (Berry), which has
+// longer tails than GGX. Used for the clearcoat layer of the principled material.
+#[derive(Debug, Copy, Clone)]
+pub struct Gtr1 {
+    pub alpha: f32,
+}
+
+impl Gtr1 {
+    pub fn new(alpha: f32) -> Self {
+        Gtr1 { alpha }
+    }
+
+    // Density of microfacet normals wm, per unit projected area.
+    pub fn d(&self, wm: &vec3::Vec3) -> f32 {
+        if wm.z() <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        (alpha2 - 1.0) / (PI * alpha2.ln() * (1.0 + (alpha2 - 1.0) * wm.z() * wm.z()))
+    }
+
+    // Sample a microfacet normal with density d(wm) * cos(theta_m).
+    pub fn sample_normal(&self, u1: f32, u2: f32) -> vec3::Vec3 {
+        let alpha2 = self.alpha * self.alpha;
+        let cos2_theta = ((1.0 - alpha2.powf(1.0 - u1)) / (1.0 - alpha2)).clamp(0.0, 1.0);
+        let cos_theta = cos2_theta.sqrt();
+        let sin_theta = (1.0 - cos2_theta).sqrt();
+        let phi = 2.0 * PI * u2;
+        vec3::Vec3(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complex() {
+        let a = Complex::new(1.0, 2.0);
+        let b = Complex::new(3.0, -1.0);
+        assert!(a * b == Complex::new(5.0, 5.0));
+        assert!(((a * b) / b - a).norm_squared() < 1e-10);
+        let root = Complex::new(-4.0, 0.0).sqrt();
+        assert!((root - Complex::new(0.0, 2.0)).norm_squared() < 1e-10);
+        let unit = Complex::new(0.0, std::f32::consts::FRAC_PI_2).exp();
+        assert!((unit - Complex::new(0.0, 1.0)).norm_squared() < 1e-10);
+    }
+
+    #[test]
+    fn test_fresnel() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-6);
+        assert!(fresnel_dielectric(-0.1, 1.5) == 1.0);
+        assert!(fresnel_dielectric(0.0, 1.5) > 0.99);
+
+        // At normal incidence conductors reflect ((n - 1)^2 + k^2) / ((n + 1)^2 + k^2).
+        let (n, k) = (0.2, 3.9);
+        let expected = ((n - 1.0) * (n - 1.0) + k * k) / ((n + 1.0) * (n + 1.0) + k * k);
+        assert!((fresnel_complex(1.0, Complex::new(n, k)) - expected).abs() < 1e-5);
+        // Without absorption it matches the dielectric equations.
+        assert!(
+            (fresnel_complex(0.5, Complex::from(1.5)) - fresnel_dielectric(0.5, 1.5)).abs() < 1e-5
+        );
+    }
+
+    #[test]
+    fn test_distribution_normalized() {
+        // The projected area of all microfacets equals the macro surface area.
+        for &alpha in [0.1, 0.5, 1.0].iter() {
+            let distribution = TrowbridgeReitz::new(alpha);
+            let steps = 2000;
+            let mut integral = 0.0;
+            for i in 0..steps {
+                let theta = (i as f32 + 0.5) / steps as f32 * PI / 2.0;
+                let wm = vec3::Vec3(theta.sin(), 0.0, theta.cos());
+                integral +=
+                    distribution.d(&wm) * theta.cos() * theta.sin() * (PI / 2.0 / steps as f32);
+            }
+            assert!((2.0 * PI * integral - 1.0).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_visible_normals() {
+        let distribution = TrowbridgeReitz::new(0.3);
+        let wo = vec3::Vec3(0.6, 0.0, 0.8);
+        for i in 0..16 {
+            let wm = distribution.sample_visible_normal(&wo, i as f32 / 16.0, 0.37);
+            assert!((wm.norm() - 1.0).abs() < 1e-5);
+            assert!(wm.z() > 0.0);
+            assert!(wo.dot(wm) >= -1e-6);
+        }
+    }
+
+    #[test]
+    fn test_gtr1_normalized() {
+        let distribution = Gtr1::new(0.1);
+        let steps = 4000;
+        let mut integral = 0.0;
+        for i in 0..steps {
+            let theta = (i as f32 + 0.5) / steps as f32 * PI / 2.0;
+            let wm = vec3::Vec3(theta.sin(), 0.0, theta.cos());
+            integral += distribution.d(&wm) * theta.cos() * theta.sin() * (PI / 2.0 / steps as f32);
+        }
+        assert!((2.0 * PI * integral - 1.0).abs() < 0.01);
+    }
+}
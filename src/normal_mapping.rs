@@ -0,0 +1,251 @@
+use super::color;
+use super::hittable;
+use super::material::{Evaluation, Material, Scattering};
+use super::medium;
+use super::ray;
+use super::texture::Texture;
+use super::vec3;
+use std::rc::Rc;
+
+// Step in texture coordinates used to estimate the slope of bump maps.
+static BUMP_DELTA: f32 = 1.0 / 1024.0;
+
+// Replace the shading normal of a hit, given in outward orientation, keeping it on
+// the side of the surface the ray arrived from.
+fn with_normal(hit_record: &hittable::HitRecord, outward: vec3::Vec3) -> hittable::HitRecord {
+    let mut shaded = hit_record.clone();
+    if outward.near_zero() {
+        return shaded;
+    }
+    let outward = outward.unit_vector();
+    shaded.normal = if hit_record.front_face {
+        outward
+    } else {
+        -outward
+    };
+    shaded
+}
+
+// Perturbs the shading normal of a material with a tangent space normal map, as
+// painted by most tools: red along dpdu, green along dpdv and blue along the normal.
+// Surfaces without tangents (dpdu of zero) are left unchanged.
+pub struct NormalMap {
+    material: Rc<dyn Material>,
+    map: Rc<dyn Texture>,
+}
+
+impl NormalMap {
+    pub fn new(material: Rc<dyn Material>, map: Rc<dyn Texture>) -> Self {
+        NormalMap { material, map }
+    }
+
+    fn shade(&self, hit_record: &hittable::HitRecord) -> hittable::HitRecord {
+        let normal = hit_record.outward_normal();
+        // Gram-Schmidt, as the interpolated normal need not be orthogonal to dpdu.
+        let tangent = hit_record.dpdu - normal * normal.dot(hit_record.dpdu);
+        if tangent.near_zero() {
+            return hit_record.clone();
+        }
+        let tangent = tangent.unit_vector();
+        let mut bitangent = normal.cross(tangent);
+        if bitangent.dot(hit_record.dpdv) < 0.0 {
+            bitangent = -bitangent;
+        }
+
+        let texel = self.map.value(hit_record.u, hit_record.v, &hit_record.p);
+        let local = texel * 2.0 - color::WHITE;
+        with_normal(
+            hit_record,
+            tangent * local.x() + bitangent * local.y() + normal * local.z(),
+        )
+    }
+}
+
+// Perturbs the shading normal of a material as if the surface was displaced along
+// its normal by a height map.
+// scale: Displacement in world units for a height of one.
+pub struct BumpMap {
+    material: Rc<dyn Material>,
+    height: Rc<dyn Texture>,
+    scale: f32,
+}
+
+impl BumpMap {
+    pub fn new(material: Rc<dyn Material>, height: Rc<dyn Texture>, scale: f32) -> Self {
+        BumpMap {
+            material,
+            height,
+            scale,
+        }
+    }
+
+    fn shade(&self, hit_record: &hittable::HitRecord) -> hittable::HitRecord {
+        let (u, v, p) = (hit_record.u, hit_record.v, hit_record.p);
+        let (dpdu, dpdv) = (hit_record.dpdu, hit_record.dpdv);
+        if dpdu.near_zero() || dpdv.near_zero() {
+            return hit_record.clone();
+        }
+        let normal = hit_record.outward_normal();
+
+        // Forward differences of the displacement, moving the lookup point along too
+        // for textures that depend on position.
+        let height = self.height.value_scalar(u, v, &p);
+        let du = BUMP_DELTA;
+        let dv = BUMP_DELTA;
+        let height_u = self.height.value_scalar(u + du, v, &(p + dpdu * du));
+        let height_v = self.height.value_scalar(u, v + dv, &(p + dpdv * dv));
+        let dhdu = self.scale * (height_u - height) / du;
+        let dhdv = self.scale * (height_v - height) / dv;
+
+        // Tangents of the displaced surface, ignoring the change of the normal itself
+        // as it's small for small displacements.
+        let displaced_dpdu = dpdu + normal * dhdu;
+        let displaced_dpdv = dpdv + normal * dhdv;
+        let mut bumped = displaced_dpdu.cross(displaced_dpdv);
+        if bumped.dot(normal) < 0.0 {
+            bumped = -bumped;
+        }
+        with_normal(hit_record, bumped)
+    }
+}
+
+// Both maps forward everything to the wrapped material with the shading normal replaced.
+macro_rules! forward_shaded {
+    ($name:ident) => {
+        impl Material for $name {
+            fn scatter(
+                &self,
+                ray: &ray::Ray,
+                hit_record: &hittable::HitRecord,
+            ) -> Option<Scattering> {
+                self.material.scatter(ray, &self.shade(hit_record))
+            }
+
+            fn albedo(&self, hit_record: &hittable::HitRecord) -> color::Color {
+                self.material.albedo(hit_record)
+            }
+
+            fn eval(
+                &self,
+                ray: &ray::Ray,
+                hit_record: &hittable::HitRecord,
+                direction: &vec3::Vec3,
+            ) -> Option<Evaluation> {
+                self.material.eval(ray, &self.shade(hit_record), direction)
+            }
+
+            fn medium(&self) -> Option<medium::Medium> {
+                self.material.medium()
+            }
+
+            fn is_surface(&self) -> bool {
+                self.material.is_surface()
+            }
+
+            // Holes follow the geometry, not the shading normal.
+            fn transparency(&self, hit_record: &hittable::HitRecord) -> f32 {
+                self.material.transparency(hit_record)
+            }
+        }
+    };
+}
+
+forward_shaded!(NormalMap);
+forward_shaded!(BumpMap);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combinators::AlphaMask;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+    use crate::volume::HenyeyGreenstein;
+
+    // A hit on the xy plane facing +z, with u along x and v along y.
+    fn plane_hit(u: f32, v: f32) -> hittable::HitRecord {
+        let r = ray::Ray::new(vec3::Point3(u, v, 1.0), vec3::Vec3(0.0, 0.0, -1.0));
+        let mut hit_record = hittable::HitRecord::new(
+            1.0,
+            vec3::Point3(u, v, 0.0),
+            &r,
+            vec3::Vec3(0.0, 0.0, 1.0),
+            Rc::new(Lambertian::new(color::WHITE)),
+        );
+        hit_record.u = u;
+        hit_record.v = v;
+        hit_record.dpdu = vec3::Vec3(1.0, 0.0, 0.0);
+        hit_record.dpdv = vec3::Vec3(0.0, 1.0, 0.0);
+        hit_record
+    }
+
+    // Height rising along u.
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f32, _v: f32, _p: &vec3::Point3) -> color::Color {
+            color::WHITE * u
+        }
+    }
+
+    #[test]
+    fn test_normal_map() {
+        let material = Rc::new(Lambertian::new(color::WHITE));
+        // The flat normal map color leaves the normal alone.
+        let flat = NormalMap::new(
+            material.clone(),
+            Rc::new(SolidColor::new(color::Color(0.5, 0.5, 1.0))),
+        );
+        let hit_record = plane_hit(0.5, 0.5);
+        assert!((flat.shade(&hit_record).normal - hit_record.normal).norm() < 1e-6);
+
+        // Tilt towards +u.
+        let tilted = NormalMap::new(
+            material,
+            Rc::new(SolidColor::new(color::Color(1.0, 0.5, 1.0))),
+        );
+        let normal = tilted.shade(&hit_record).normal;
+        assert!((normal - vec3::Vec3(1.0, 0.0, 1.0).unit_vector()).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_bump_map() {
+        let material = Rc::new(Lambertian::new(color::WHITE));
+        let bump = BumpMap::new(material, Rc::new(Ramp), 1.0);
+        let hit_record = plane_hit(0.5, 0.5);
+        // A slope of one rising along +x tilts the normal back towards -x.
+        let normal = bump.shade(&hit_record).normal;
+        assert!((normal - vec3::Vec3(-1.0, 0.0, 1.0).unit_vector()).norm() < 1e-3);
+
+        // Seen from behind, the bumped normal still faces the ray.
+        let r = ray::Ray::new(vec3::Point3(0.5, 0.5, -1.0), vec3::Vec3(0.0, 0.0, 1.0));
+        let back = hittable::HitRecord {
+            normal: vec3::Vec3(0.0, 0.0, -1.0),
+            front_face: false,
+            ..plane_hit(0.5, 0.5)
+        };
+        assert!(r.direction.dot(bump.shade(&back).normal) < 0.0);
+    }
+
+    #[test]
+    fn test_forwarding() {
+        // A cut-out leaf keeps its holes under a normal map.
+        let cut_out = Rc::new(AlphaMask::new(
+            Rc::new(Lambertian::new(color::WHITE)),
+            Rc::new(SolidColor::scalar(0.25)),
+        ));
+        let leaf = NormalMap::new(
+            cut_out,
+            Rc::new(SolidColor::new(color::Color(0.5, 0.5, 1.0))),
+        );
+        assert!(leaf.transparency(&plane_hit(0.5, 0.5)) == 0.75);
+        assert!(leaf.is_surface());
+
+        // Bumped volumes still scatter rather than cross a surface.
+        let fog = BumpMap::new(
+            Rc::new(HenyeyGreenstein::new(color::WHITE, 0.0)),
+            Rc::new(Ramp),
+            1.0,
+        );
+        assert!(!fog.is_surface());
+    }
+}
@@ -0,0 +1,270 @@
+use super::color;
+use super::filter;
+use super::ppm;
+use super::tonemap;
+use std::env;
+use std::path::Path;
+
+// Render settings, configurable from the command line.
+pub struct Options {
+    pub image_width: usize,
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+    pub write_ppm: bool,
+    pub output: String,
+    pub ppm_format: ppm::PpmFormat,
+    pub ppm_max_value: u16,
+    pub write_aovs: bool,
+    pub denoise: bool,
+    pub display: tonemap::DisplayTransform,
+    pub filter: filter::FilterKind,
+    pub filter_radius: Option<f32>,
+    pub environment: Option<String>,
+    pub environment_rotation: f32,
+    pub environment_intensity: f32,
+    pub background_color: Option<color::Color>,
+    pub sky: bool,
+    pub sun_elevation: f32,
+    pub sun_azimuth: f32,
+    pub turbidity: f32,
+    pub sky_intensity: f32,
+    pub spectral: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            image_width: 1200,
+            samples_per_pixel: 500,
+            max_depth: 50,
+            write_ppm: true,
+            output: String::from("image.ppm"),
+            ppm_format: ppm::PpmFormat::Ascii,
+            ppm_max_value: 255,
+            write_aovs: false,
+            denoise: false,
+            display: tonemap::DisplayTransform::default(),
+            filter: filter::FilterKind::Box,
+            filter_radius: None,
+            environment: None,
+            environment_rotation: 0.0,
+            environment_intensity: 1.0,
+            background_color: None,
+            sky: false,
+            sun_elevation: 30.0,
+            sun_azimuth: 45.0,
+            turbidity: 3.0,
+            sky_intensity: 0.1,
+            spectral: false,
+        }
+    }
+}
+
+static USAGE: &str = "Usage: in_one_weekend [options]
+    --width <pixels>      Image width (default 1200)
+    --spp <samples>       Samples per pixel (default 500)
+    --max-depth <bounces> Maximum path depth (default 50)
+    --no-ppm              Don't write the rendered image to disk
+    -o, --output <path>   Path of the rendered image (default image.ppm)
+    --binary              Write binary (P6) instead of plain text (P3) PPM
+    --16bit               Write 16 bits per channel
+    --aovs                Write albedo, normal, depth, position and id images
+    --denoise             Denoise the image using the first hit AOVs
+    --exposure <stops>    Exposure adjustment in EV (default 0)
+    --white-balance <r,g,b>
+                          Per channel white balance gains (default 1,1,1)
+    --tonemap <curve>     One of clamp, reinhard or aces (default clamp)
+    --filter <shape>      Pixel filter, one of box, tent, gaussian, mitchell or lanczos
+                          (default box)
+    --filter-radius <pixels>
+                          Filter radius (default depends on the filter)
+    --environment <path>  Light the scene with an equirectangular PPM, PNG or PFM map
+    --environment-rotation <degrees>
+                          Rotate the environment map around the vertical axis
+    --environment-intensity <scale>
+                          Scale the environment map's brightness (default 1)
+    --background-color <r,g,b>
+                          Use a constant background instead of the sky gradient
+    --sky                 Use a physical daylight sky and sun as the background
+    --sun-elevation <degrees>
+                          Angle of the sun above the horizon (default 30)
+    --sun-azimuth <degrees>
+                          Angle of the sun around the vertical axis, from +x towards +z
+                          (default 45)
+    --turbidity <haze>    Haziness of the sky, from 2 (clear) to 10 (hazy) (default 3)
+    --sky-intensity <scale>
+                          Scale from sky luminance in kcd/m^2 to radiance (default 0.1)
+    --spectral            Trace each path at a single wavelength, so glass disperses light";
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+fn parse_color(flag: &str, value: Option<String>) -> Result<color::Color, String> {
+    let value: String = parse_value(flag, value)?;
+    let channels = value
+        .split(',')
+        .map(|channel| channel.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>();
+    match channels.as_deref() {
+        Ok([r, g, b]) => Ok(color::Color(*r, *g, *b)),
+        _ => Err(format!("invalid color for {}: {}", flag, value)),
+    }
+}
+
+impl Options {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--width" => options.image_width = parse_value(&arg, args.next())?,
+                "--spp" => options.samples_per_pixel = parse_value(&arg, args.next())?,
+                "--max-depth" => options.max_depth = parse_value(&arg, args.next())?,
+                "--no-ppm" => options.write_ppm = false,
+                "-o" | "--output" => options.output = parse_value(&arg, args.next())?,
+                "--binary" => options.ppm_format = ppm::PpmFormat::Binary,
+                "--16bit" => options.ppm_max_value = u16::MAX,
+                "--aovs" => options.write_aovs = true,
+                "--denoise" => options.denoise = true,
+                "--exposure" => options.display.exposure = parse_value(&arg, args.next())?,
+                "--white-balance" => {
+                    options.display.white_balance = parse_color(&arg, args.next())?
+                }
+                "--tonemap" => options.display.tone_mapper = parse_value(&arg, args.next())?,
+                "--filter" => options.filter = parse_value(&arg, args.next())?,
+                "--environment" => options.environment = Some(parse_value(&arg, args.next())?),
+                "--environment-rotation" => {
+                    options.environment_rotation = parse_value(&arg, args.next())?
+                }
+                "--environment-intensity" => {
+                    options.environment_intensity = parse_value(&arg, args.next())?
+                }
+                "--background-color" => {
+                    options.background_color = Some(parse_color(&arg, args.next())?)
+                }
+                "--sky" => options.sky = true,
+                "--sun-elevation" => options.sun_elevation = parse_value(&arg, args.next())?,
+                "--sun-azimuth" => options.sun_azimuth = parse_value(&arg, args.next())?,
+                "--turbidity" => options.turbidity = parse_value(&arg, args.next())?,
+                "--sky-intensity" => options.sky_intensity = parse_value(&arg, args.next())?,
+                "--filter-radius" => options.filter_radius = Some(parse_value(&arg, args.next())?),
+                "--spectral" => options.spectral = true,
+                _ => return Err(format!("unknown argument {}\n{}", arg, USAGE)),
+            }
+        }
+        Ok(options)
+    }
+
+    // Output path without its extension, used to name the AOV images.
+    pub fn output_stem(&self) -> String {
+        let path = Path::new(&self.output);
+        path.with_extension("").to_string_lossy().into_owned()
+    }
+
+    // The reconstruction filter, falling back to the default radius of its shape.
+    pub fn filter(&self) -> filter::Filter {
+        let radius = self
+            .filter_radius
+            .unwrap_or_else(|| self.filter.default_radius());
+        filter::Filter::new(self.filter, radius)
+    }
+
+    // Parse the arguments of the current process, exiting with usage on error.
+    pub fn from_args() -> Options {
+        match Options::parse(env::args().skip(1)) {
+            Ok(options) => options,
+            Err(why) => {
+                eprintln!("{}", why);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let options = parse(&["--spp", "16", "--aovs", "--no-ppm", "--denoise"]).unwrap();
+
+        assert!(options.samples_per_pixel == 16);
+        assert!(options.write_aovs);
+        assert!(options.denoise);
+        assert!(!options.write_ppm);
+        assert!(options.image_width == 1200);
+        assert!(!options.spectral);
+        assert!(parse(&["--spectral"]).unwrap().spectral);
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let options = parse(&["-o", "renders/scene.ppm", "--binary", "--16bit"]).unwrap();
+
+        assert!(options.output == "renders/scene.ppm");
+        assert!(options.output_stem() == "renders/scene");
+        assert!(options.ppm_format == ppm::PpmFormat::Binary);
+        assert!(options.ppm_max_value == 65535);
+    }
+
+    #[test]
+    fn test_parse_display() {
+        let options = parse(&[
+            "--exposure",
+            "-1.5",
+            "--tonemap",
+            "aces",
+            "--white-balance",
+            "1,0.9,0.8",
+        ])
+        .unwrap();
+
+        assert!(options.display.exposure == -1.5);
+        assert!(options.display.tone_mapper == tonemap::ToneMapper::AcesFilmic);
+        assert!(options.display.white_balance == color::Color(1.0, 0.9, 0.8));
+        assert!(parse(&["--white-balance", "1,1"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_environment() {
+        let options = parse(&["--environment", "sky.pfm", "--environment-rotation", "90"]).unwrap();
+
+        assert!(options.environment.as_deref() == Some("sky.pfm"));
+        assert!(options.environment_rotation == 90.0);
+        assert!(options.environment_intensity == 1.0);
+        assert!(options.background_color.is_none());
+
+        let options = parse(&["--sky", "--sun-elevation", "5", "--turbidity", "8"]).unwrap();
+        assert!(options.sky);
+        assert!(options.sun_elevation == 5.0);
+        assert!(options.turbidity == 8.0);
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        let options = parse(&["--filter", "mitchell"]).unwrap();
+        assert!(options.filter().kind == filter::FilterKind::Mitchell);
+        assert!(options.filter().radius == 2.0);
+
+        let options = parse(&["--filter-radius", "1.25", "--filter", "gaussian"]).unwrap();
+        assert!(options.filter().kind == filter::FilterKind::Gaussian);
+        assert!(options.filter().radius == 1.25);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(&["--spp"]).is_err());
+        assert!(parse(&["--spp", "many"]).is_err());
+        assert!(parse(&["--unknown"]).is_err());
+        assert!(parse(&["--tonemap", "linear"]).is_err());
+    }
+}
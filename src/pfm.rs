@@ -0,0 +1,73 @@
+use super::color;
+use super::framebuffer::Framebuffer;
+use super::ppm;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Read, Write};
+
+// Write a framebuffer as a Portable Float Map (PFM).
+// PFM stores raw little endian floats with rows ordered bottom to top.
+pub fn write_pfm(path: &str, image: &Framebuffer) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+
+    writeln!(file, "PF")?;
+    writeln!(file, "{} {}", image.width, image.height)?;
+    // A negative scale marks the data as little endian.
+    writeln!(file, "-1.0")?;
+
+    for y in (0..image.height).rev() {
+        for x in 0..image.width {
+            let pixel = image.get(x, y);
+            file.write_all(&pixel.0.to_le_bytes())?;
+            file.write_all(&pixel.1.to_le_bytes())?;
+            file.write_all(&pixel.2.to_le_bytes())?;
+        }
+    }
+    file.flush()
+}
+
+// Read a Portable Float Map. Both color (PF) and grayscale (Pf) maps are supported,
+// values are returned as stored, so they are already linear.
+pub fn read_pfm<R: BufRead>(mut reader: R) -> std::io::Result<Framebuffer> {
+    let channels = match ppm::read_token(&mut reader)?.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(ppm::invalid_data("not a PFM")),
+    };
+    let parse = |token: String| -> std::io::Result<f32> {
+        token
+            .parse()
+            .map_err(|_| ppm::invalid_data("invalid number in PFM"))
+    };
+    let width = parse(ppm::read_token(&mut reader)?)? as usize;
+    let height = parse(ppm::read_token(&mut reader)?)? as usize;
+    let little_endian = parse(ppm::read_token(&mut reader)?)? < 0.0;
+
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    ppm::check_size(width, height, 4 * channels, data.len())?;
+    let mut reader = &data[..];
+
+    let mut read_value = || -> std::io::Result<f32> {
+        let mut bytes = [0u8; 4];
+        reader.read_exact(&mut bytes)?;
+        Ok(if little_endian {
+            f32::from_le_bytes(bytes)
+        } else {
+            f32::from_be_bytes(bytes)
+        })
+    };
+
+    let mut image = Framebuffer::new(width, height);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let pixel_color = if channels == 3 {
+                color::Color(read_value()?, read_value()?, read_value()?)
+            } else {
+                let value = read_value()?;
+                color::Color(value, value, value)
+            };
+            image.set(x, y, pixel_color);
+        }
+    }
+    Ok(image)
+}
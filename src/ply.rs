@@ -0,0 +1,448 @@
+use super::color;
+use super::material;
+use super::tonemap;
+use super::triangle::TriangleMesh;
+use super::vec3;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind};
+use std::path::Path;
+use std::rc::Rc;
+
+// The Stanford polygon format, as written by scanners and most modelling tools.
+// Vertices may carry normals, texture coordinates and colors, and polygons are
+// split into triangle fans. Other elements and properties are skipped.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ScalarType {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> std::io::Result<Self> {
+        match name {
+            "char" | "int8" => Ok(ScalarType::Int8),
+            "uchar" | "uint8" => Ok(ScalarType::Uint8),
+            "short" | "int16" => Ok(ScalarType::Int16),
+            "ushort" | "uint16" => Ok(ScalarType::Uint16),
+            "int" | "int32" => Ok(ScalarType::Int32),
+            "uint" | "uint32" => Ok(ScalarType::Uint32),
+            "float" | "float32" => Ok(ScalarType::Float32),
+            "double" | "float64" => Ok(ScalarType::Float64),
+            _ => Err(invalid(format!("unknown PLY type {}", name))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::Uint8 => 1,
+            ScalarType::Int16 | ScalarType::Uint16 => 2,
+            ScalarType::Int32 | ScalarType::Uint32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    // The largest value of integer types, which colors are stored as fractions of.
+    fn range(self) -> f64 {
+        match self {
+            ScalarType::Int8 => i8::MAX as f64,
+            ScalarType::Uint8 => u8::MAX as f64,
+            ScalarType::Int16 => i16::MAX as f64,
+            ScalarType::Uint16 => u16::MAX as f64,
+            ScalarType::Int32 => i32::MAX as f64,
+            ScalarType::Uint32 => u32::MAX as f64,
+            ScalarType::Float32 | ScalarType::Float64 => 1.0,
+        }
+    }
+}
+
+// count: The type of the length of list properties, such as the vertex indices of
+// faces, which has None for single values.
+#[derive(Debug, Clone)]
+struct Property {
+    name: String,
+    count: Option<ScalarType>,
+    value: ScalarType,
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// The data after the header, read one value at a time.
+struct Body {
+    data: Vec<u8>,
+    offset: usize,
+    format: Format,
+}
+
+impl Body {
+    fn next(&mut self, value_type: ScalarType) -> std::io::Result<f64> {
+        if self.format == Format::Ascii {
+            let data = &self.data;
+            let start = (self.offset..data.len())
+                .find(|&i| !data[i].is_ascii_whitespace())
+                .ok_or_else(|| invalid("PLY file ends early".to_string()))?;
+            let end = (start..data.len())
+                .find(|&i| data[i].is_ascii_whitespace())
+                .unwrap_or(data.len());
+            self.offset = end;
+            let token = std::str::from_utf8(&data[start..end]).unwrap_or("");
+            return token
+                .parse()
+                .map_err(|_| invalid(format!("invalid PLY value {}", token)));
+        }
+
+        let size = value_type.size();
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + size)
+            .ok_or_else(|| invalid("PLY file ends early".to_string()))?;
+        self.offset += size;
+        let mut buffer = [0; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if self.format == Format::BinaryBigEndian {
+            buffer[..size].reverse();
+        }
+        let value = match value_type {
+            ScalarType::Int8 => buffer[0] as i8 as f64,
+            ScalarType::Uint8 => buffer[0] as f64,
+            ScalarType::Int16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            ScalarType::Uint16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            ScalarType::Int32 => {
+                i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            ScalarType::Uint32 => {
+                u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            ScalarType::Float32 => {
+                f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            ScalarType::Float64 => f64::from_le_bytes(buffer),
+        };
+        Ok(value)
+    }
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> std::io::Result<(Format, Vec<Element>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim() != "ply" {
+        return Err(invalid("not a PLY file".to_string()));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("PLY header has no end".to_string()));
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid(format!("unknown PLY format {}", name))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid PLY element count {}", count)))?,
+                properties: vec![],
+            }),
+            ["property", "list", count, value, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    count: Some(ScalarType::parse(count)?),
+                    value: ScalarType::parse(value)?,
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("PLY property outside an element".to_string()))?
+                    .properties
+                    .push(property);
+            }
+            ["property", value, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    count: None,
+                    value: ScalarType::parse(value)?,
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("PLY property outside an element".to_string()))?
+                    .properties
+                    .push(property);
+            }
+            ["end_header"] => break,
+            // Comments, object information and blank lines.
+            _ => {}
+        }
+    }
+    let format = format.ok_or_else(|| invalid("PLY header has no format".to_string()))?;
+    Ok((format, elements))
+}
+
+// Read a PLY mesh. Vertex colors are taken to be sRGB, like those of images, and
+// converted to linear albedos for materials made with Lambertian::vertex_colors.
+pub fn read_ply<R: BufRead>(
+    mut reader: R,
+    material: Rc<dyn material::Material>,
+) -> std::io::Result<TriangleMesh> {
+    let (format, elements) = read_header(&mut reader)?;
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    let mut body = Body {
+        data,
+        offset: 0,
+        format,
+    };
+
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut colors = vec![];
+    let mut indices = vec![];
+    for element in &elements {
+        let find = |names: &[&str]| {
+            element
+                .properties
+                .iter()
+                .position(|property| names.contains(&property.name.as_str()))
+        };
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let uv = [
+            find(&["u", "s", "texture_u", "texture_s"]),
+            find(&["v", "t", "texture_v", "texture_t"]),
+        ];
+        let color = [
+            find(&["red", "r", "diffuse_red"]),
+            find(&["green", "g", "diffuse_green"]),
+            find(&["blue", "b", "diffuse_blue"]),
+        ];
+        let face = find(&["vertex_indices", "vertex_index"]);
+
+        for _ in 0..element.count {
+            let mut values = Vec::with_capacity(element.properties.len());
+            let mut list = vec![];
+            for (index, property) in element.properties.iter().enumerate() {
+                match property.count {
+                    None => values.push(body.next(property.value)?),
+                    Some(count_type) => {
+                        let count = body.next(count_type)? as usize;
+                        let items = (0..count)
+                            .map(|_| body.next(property.value))
+                            .collect::<std::io::Result<Vec<f64>>>()?;
+                        if Some(index) == face {
+                            list = items;
+                        }
+                        values.push(0.0);
+                    }
+                }
+            }
+
+            let value = |index: usize| values[index] as f32;
+            if element.name == "vertex" {
+                if let [Some(x), Some(y), Some(z)] = position {
+                    positions.push(vec3::Point3(value(x), value(y), value(z)));
+                }
+                if let [Some(x), Some(y), Some(z)] = normal {
+                    normals.push(vec3::Vec3(value(x), value(y), value(z)));
+                }
+                if let [Some(u), Some(v)] = uv {
+                    uvs.push((value(u), value(v)));
+                }
+                if let [Some(r), Some(g), Some(b)] = color {
+                    let channel = |index: usize| {
+                        let range = element.properties[index].value.range();
+                        tonemap::srgb_to_linear((values[index] / range) as f32)
+                    };
+                    colors.push(color::Color(channel(r), channel(g), channel(b)));
+                }
+            } else if element.name == "face" && list.len() >= 3 {
+                if list
+                    .iter()
+                    .any(|&index| index < 0.0 || index.fract() != 0.0)
+                {
+                    return Err(invalid("PLY face has an invalid vertex index".to_string()));
+                }
+                let vertices: Vec<usize> = list.iter().map(|&index| index as usize).collect();
+                for i in 1..vertices.len() - 1 {
+                    indices.push([vertices[0], vertices[i], vertices[i + 1]]);
+                }
+            }
+        }
+    }
+
+    if indices
+        .iter()
+        .any(|triangle| triangle.iter().any(|&index| index >= positions.len()))
+    {
+        return Err(invalid("PLY face refers to a missing vertex".to_string()));
+    }
+    // Each vertex needs all or none of an attribute.
+    let vertex_count = positions.len();
+    if [normals.len(), uvs.len(), colors.len()]
+        .iter()
+        .any(|&count| count != 0 && count != vertex_count)
+    {
+        return Err(invalid(
+            "PLY vertex attributes don't match the positions".to_string(),
+        ));
+    }
+    let mut mesh = TriangleMesh::new(positions, indices, material);
+    if !normals.is_empty() {
+        mesh = mesh.with_normals(normals);
+    }
+    if !uvs.is_empty() {
+        mesh = mesh.with_uvs(uvs);
+    }
+    if !colors.is_empty() {
+        mesh = mesh.with_colors(colors);
+    }
+    Ok(mesh)
+}
+
+pub fn load_ply<P: AsRef<Path>>(
+    path: P,
+    material: Rc<dyn material::Material>,
+) -> std::io::Result<TriangleMesh> {
+    read_ply(BufReader::new(File::open(path)?), material)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::lambertian;
+    use crate::hittable::Hittable;
+    use crate::ray;
+
+    #[test]
+    fn test_ascii_ply() {
+        let file = "ply
+format ascii 1.0
+comment a unit square facing +z
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 1 0 0 0 255
+0 1 0 0 0 255
+4 0 1 2 3
+";
+        let material = Rc::new(material::Lambertian::vertex_colors(color::WHITE));
+        let mesh = read_ply(file.as_bytes(), material).unwrap();
+        assert!(mesh.len() == 2);
+        assert!(mesh.indices() == [[0, 1, 2], [0, 2, 3]]);
+        assert!((mesh.colors().unwrap()[0] - color::Color(1.0, 0.0, 0.0)).norm() < 1e-5);
+
+        // Halfway up, the colors are mixed, and the material picks them up.
+        let r = ray::Ray::new(vec3::Point3(0.5, 0.5, 1.0), vec3::Vec3(0.0, 0.0, -1.0));
+        let hit_record = mesh.hit(&r, 0.001, f32::INFINITY).unwrap();
+        let albedo = hit_record.material.albedo(&hit_record);
+        assert!((albedo - color::Color(0.5, 0.0, 0.5)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_binary_ply() {
+        // The same triangle in both byte orders, with an extra element to skip.
+        for big_endian in [false, true] {
+            let format = if big_endian {
+                "binary_big_endian"
+            } else {
+                "binary_little_endian"
+            };
+            let mut file = format!(
+                "ply\nformat {} 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+                 property float z\nproperty double confidence\nelement face 1\n\
+                 property list uchar uint vertex_indices\nelement edge 1\nproperty int vertex1\n\
+                 end_header\n",
+                format
+            )
+            .into_bytes();
+            let mut push = |bytes: &[u8]| {
+                if big_endian {
+                    file.extend(bytes.iter().rev());
+                } else {
+                    file.extend(bytes);
+                }
+            };
+            for (x, y) in [(0.0f32, 0.0f32), (1.0, 0.0), (0.0, 1.0)] {
+                push(&x.to_le_bytes());
+                push(&y.to_le_bytes());
+                push(&2.0f32.to_le_bytes());
+                push(&1.0f64.to_le_bytes());
+            }
+            push(&[3]);
+            for index in [0u32, 1, 2] {
+                push(&index.to_le_bytes());
+            }
+            push(&7i32.to_le_bytes());
+
+            let mesh = read_ply(file.as_slice(), lambertian()).unwrap();
+            assert!(mesh.len() == 1 && mesh.indices()[0] == [0, 1, 2]);
+            assert!(mesh.positions()[1] == vec3::Point3(1.0, 0.0, 2.0));
+            assert!(mesh.normals().is_none());
+            assert!(mesh.colors().is_none());
+        }
+
+        let truncated = "ply\nformat binary_little_endian 1.0\nelement vertex 1\n\
+                         property float x\nend_header\n\0\0";
+        assert!(read_ply(truncated.as_bytes(), lambertian()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_ply() {
+        let triangle = |face: &str| {
+            format!(
+                "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+                 property float z\nelement face 1\nproperty list uchar float vertex_indices\n\
+                 end_header\n0 0 0\n1 0 0\n0 1 0\n{}\n",
+                face
+            )
+        };
+        assert!(read_ply(triangle("3 0 1 2").as_bytes(), lambertian()).is_ok());
+        assert!(read_ply(triangle("3 0 1 -2").as_bytes(), lambertian()).is_err());
+        assert!(read_ply(triangle("3 0 1 1.5").as_bytes(), lambertian()).is_err());
+
+        // Normals on vertices without a position.
+        let unmatched = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n\
+                         property float nx\nproperty float ny\nproperty float nz\n\
+                         end_header\n0 0 0 1\n";
+        assert!(read_ply(unmatched.as_bytes(), lambertian()).is_err());
+    }
+}
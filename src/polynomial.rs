@@ -0,0 +1,159 @@
+// Real roots of low degree polynomials, in ascending order. These work in double
+// precision as the quartics coming from tori lose too much in single precision.
+
+static EPSILON: f64 = 1e-12;
+
+// Roots of a x^2 + b x + c.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < EPSILON {
+        if b.abs() < EPSILON {
+            return vec![];
+        }
+        return vec![-c / b];
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+    // Avoid the cancellation between b and the square root.
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    let (x0, x1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    if x0 <= x1 {
+        vec![x0, x1]
+    } else {
+        vec![x1, x0]
+    }
+}
+
+// Roots of a x^3 + b x^2 + c x + d.
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if a.abs() < EPSILON {
+        return solve_quadratic(b, c, d);
+    }
+    // Depressed cubic y^3 + p y + q with x = y - b / 3a.
+    let (b, c, d) = (b / a, c / a, d / a);
+    let shift = -b / 3.0;
+    let p = c - b * b / 3.0;
+    let q = 2.0 * b * b * b / 27.0 - b * c / 3.0 + d;
+
+    let discriminant = q * q / 4.0 + p * p * p / 27.0;
+    let mut roots = if discriminant > EPSILON {
+        // One real root, from Cardano's formula.
+        let sqrt_discriminant = discriminant.sqrt();
+        vec![(-q / 2.0 + sqrt_discriminant).cbrt() + (-q / 2.0 - sqrt_discriminant).cbrt()]
+    } else if p.abs() < EPSILON {
+        vec![(-q).cbrt()]
+    } else {
+        // Three real roots, from the trigonometric solution.
+        let m = 2.0 * (-p / 3.0).sqrt();
+        let angle = (3.0 * q / (p * m)).clamp(-1.0, 1.0).acos() / 3.0;
+        (0..3)
+            .map(|k| m * (angle - 2.0 * std::f64::consts::PI * k as f64 / 3.0).cos())
+            .collect()
+    };
+    for root in roots.iter_mut() {
+        *root += shift;
+    }
+    roots.sort_by(|x, y| x.partial_cmp(y).unwrap());
+    roots
+}
+
+// Roots of a x^4 + b x^3 + c x^2 + d x + e, using Ferrari's method.
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if a.abs() < EPSILON {
+        return solve_cubic(b, c, d, e);
+    }
+    // Depressed quartic y^4 + p y^2 + q y + r with x = y - b / 4a.
+    let (b, c, d, e) = (b / a, c / a, d / a, e / a);
+    let shift = -b / 4.0;
+    let b2 = b * b;
+    let p = c - 3.0 * b2 / 8.0;
+    let q = b2 * b / 8.0 - b * c / 2.0 + d;
+    let r = -3.0 * b2 * b2 / 256.0 + b2 * c / 16.0 - b * d / 4.0 + e;
+
+    let mut roots = if q.abs() < EPSILON {
+        // Biquadratic, a quadratic in y^2.
+        solve_quadratic(1.0, p, r)
+            .into_iter()
+            .filter(|&y2| y2 >= 0.0)
+            .flat_map(|y2| vec![-y2.sqrt(), y2.sqrt()])
+            .collect::<Vec<f64>>()
+    } else {
+        // Completing the square needs a positive root m of the resolvent cubic, which
+        // exists as q isn't zero. The quartic then splits into two quadratics.
+        let m = solve_cubic(8.0, 8.0 * p, 2.0 * p * p - 8.0 * r, -q * q)
+            .into_iter()
+            .fold(0.0, f64::max);
+        if m <= 0.0 {
+            return vec![];
+        }
+        let s = (2.0 * m).sqrt();
+        let mut roots = solve_quadratic(1.0, -s, p / 2.0 + m + q / (2.0 * s));
+        roots.extend(solve_quadratic(1.0, s, p / 2.0 + m - q / (2.0 * s)));
+        roots
+    };
+
+    // Polish against the original polynomial, as the resolvent loses precision.
+    for root in roots.iter_mut() {
+        let mut x = *root + shift;
+        for _ in 0..2 {
+            let value = (((x + b) * x + c) * x + d) * x + e;
+            let derivative = ((4.0 * x + 3.0 * b) * x + 2.0 * c) * x + d;
+            if derivative.abs() < EPSILON {
+                break;
+            }
+            x -= value / derivative;
+        }
+        *root = x;
+    }
+    roots.sort_by(|x, y| x.partial_cmp(y).unwrap());
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(roots: &[f64], expected: &[f64]) -> bool {
+        roots.len() == expected.len()
+            && roots
+                .iter()
+                .zip(expected.iter())
+                .all(|(x, y)| (x - y).abs() < 1e-6)
+    }
+
+    #[test]
+    fn test_solve_quadratic() {
+        assert!(close(&solve_quadratic(1.0, -3.0, 2.0), &[1.0, 2.0]));
+        assert!(close(&solve_quadratic(0.0, 2.0, -1.0), &[0.5]));
+        assert!(solve_quadratic(1.0, 0.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_solve_cubic() {
+        // (x - 1)(x - 2)(x + 3)
+        assert!(close(&solve_cubic(1.0, 0.0, -7.0, 6.0), &[-3.0, 1.0, 2.0]));
+        // (x - 2)(x^2 + 1)
+        assert!(close(&solve_cubic(2.0, -4.0, 2.0, -4.0), &[2.0]));
+    }
+
+    #[test]
+    fn test_solve_quartic() {
+        // (x - 1)(x - 2)(x - 3)(x + 4)
+        assert!(close(
+            &solve_quartic(1.0, -2.0, -13.0, 38.0, -24.0),
+            &[-4.0, 1.0, 2.0, 3.0]
+        ));
+        // (x^2 - 1)(x^2 - 4), biquadratic.
+        assert!(close(
+            &solve_quartic(1.0, 0.0, -5.0, 0.0, 4.0),
+            &[-2.0, -1.0, 1.0, 2.0]
+        ));
+        // (x - 0.5)(x - 1.5)(x^2 + 1)
+        assert!(close(
+            &solve_quartic(2.0, -4.0, 3.5, -4.0, 1.5),
+            &[0.5, 1.5]
+        ));
+        assert!(solve_quartic(1.0, 0.0, 0.0, 0.0, 1.0).is_empty());
+    }
+}
@@ -1,34 +1,300 @@
 use super::color;
+use super::framebuffer::Framebuffer;
+use super::image_reader::ColorSpace;
+use super::tonemap;
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufRead, BufWriter, Error, ErrorKind, Read, Write};
 use std::path::Path;
 
-pub struct PpmWriter {
-    file: File,
+// PPM flavours. Ascii is the plain text P3 format, Binary the raw P6 format.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PpmFormat {
+    Ascii,
+    Binary,
 }
 
-impl PpmWriter {
-    pub fn new(image_width: &usize, image_height: &usize) -> std::io::Result<PpmWriter> {
-        let path = Path::new("image.ppm");
+// Writes images as PPM, to a file or any other Write implementation.
+// max_value: The largest sample value, up to 65535. Binary images with a max_value
+// above 255 use two bytes per sample.
+pub struct PpmWriter<W: Write> {
+    writer: W,
+    format: PpmFormat,
+    max_value: u16,
+}
+
+impl PpmWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        format: PpmFormat,
+        max_value: u16,
+    ) -> std::io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(PpmWriter::new(BufWriter::new(file), format, max_value))
+    }
+}
 
-        let mut file = match File::create(&path) {
-            Err(why) => panic!("couldn't create {}", why),
-            Ok(file) => file,
+impl<W: Write> PpmWriter<W> {
+    pub fn new(writer: W, format: PpmFormat, max_value: u16) -> Self {
+        // A max_value of 0 isn't valid PPM.
+        let max_value = max_value.max(1);
+        PpmWriter {
+            writer,
+            format,
+            max_value,
+        }
+    }
+
+    // Write the image through the display transform, top row first.
+    pub fn write_image(
+        &mut self,
+        image: &Framebuffer,
+        display: &tonemap::DisplayTransform,
+    ) -> std::io::Result<()> {
+        let magic = match self.format {
+            PpmFormat::Ascii => "P3",
+            PpmFormat::Binary => "P6",
         };
+        writeln!(self.writer, "{}", magic)?;
+        writeln!(self.writer, "{} {}", image.width, image.height)?;
+        writeln!(self.writer, "{}", self.max_value)?;
+
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let pixel = display.quantize(image.get(x, y), self.max_value);
+                self.write_pixel(pixel)?;
+            }
+        }
+        self.writer.flush()
+    }
+
+    fn write_pixel(&mut self, pixel: [u16; 3]) -> std::io::Result<()> {
+        match self.format {
+            PpmFormat::Ascii => writeln!(self.writer, "{} {} {}", pixel[0], pixel[1], pixel[2]),
+            PpmFormat::Binary => {
+                for sample in pixel.iter() {
+                    if self.max_value > u8::MAX as u16 {
+                        self.writer.write_all(&sample.to_be_bytes())?;
+                    } else {
+                        self.writer.write_all(&[*sample as u8])?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+pub fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// Read the next whitespace separated header token, skipping # comments.
+pub fn read_token<R: BufRead>(reader: &mut R) -> std::io::Result<String> {
+    let mut token = String::new();
+    let mut in_comment = false;
+    loop {
+        let mut byte = [0u8];
+        if reader.read(&mut byte)? == 0 {
+            break;
+        }
+        let c = byte[0] as char;
+        if in_comment {
+            in_comment = c != '\n';
+        } else if c == '#' {
+            in_comment = true;
+        } else if c.is_ascii_whitespace() {
+            // The single whitespace after the last header token is consumed here,
+            // which is what P6 requires before the raster starts.
+            if !token.is_empty() {
+                break;
+            }
+        } else {
+            token.push(c);
+        }
+    }
+    if token.is_empty() {
+        return Err(invalid_data("unexpected end of PPM"));
+    }
+    Ok(token)
+}
+
+// Check an image size read from a header before allocating the image, against the
+// data left to fill it, so that corrupt files can't ask for huge amounts of memory.
+// bytes_per_pixel: The least amount of data each pixel can be stored in.
+pub fn check_size(
+    width: usize,
+    height: usize,
+    bytes_per_pixel: usize,
+    available: usize,
+) -> std::io::Result<()> {
+    if width == 0 || height == 0 {
+        return Err(invalid_data("empty image"));
+    }
+    match width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(bytes_per_pixel))
+    {
+        Some(needed) if needed <= available => Ok(()),
+        _ => Err(invalid_data("image size larger than its data")),
+    }
+}
+
+fn read_number<R: BufRead>(reader: &mut R) -> std::io::Result<usize> {
+    read_token(reader)?
+        .parse()
+        .map_err(|_| invalid_data("invalid number in PPM"))
+}
+
+// Read a P3 or P6 image. Samples are normalized to [0, 1] and decoded to linear
+// from the given color space.
+pub fn read_ppm<R: BufRead>(
+    mut reader: R,
+    color_space: ColorSpace,
+) -> std::io::Result<Framebuffer> {
+    let format = match read_token(&mut reader)?.as_str() {
+        "P3" => PpmFormat::Ascii,
+        "P6" => PpmFormat::Binary,
+        _ => return Err(invalid_data("not a P3 or P6 PPM")),
+    };
+    let width = read_number(&mut reader)?;
+    let height = read_number(&mut reader)?;
+    let max_value = read_number(&mut reader)?;
+    if max_value == 0 || max_value > u16::MAX as usize {
+        return Err(invalid_data("invalid PPM max value"));
+    }
+
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    let bytes_per_pixel = match format {
+        // Text samples take at least a digit each.
+        PpmFormat::Ascii => 3,
+        PpmFormat::Binary if max_value > u8::MAX as usize => 6,
+        PpmFormat::Binary => 3,
+    };
+    check_size(width, height, bytes_per_pixel, data.len())?;
+    let mut reader = &data[..];
+
+    let mut image = Framebuffer::new(width, height);
+    let mut read_sample = || -> std::io::Result<f32> {
+        let sample = match format {
+            PpmFormat::Ascii => read_number(&mut reader)?,
+            PpmFormat::Binary if max_value > u8::MAX as usize => {
+                let mut bytes = [0u8; 2];
+                reader.read_exact(&mut bytes)?;
+                u16::from_be_bytes(bytes) as usize
+            }
+            PpmFormat::Binary => {
+                let mut bytes = [0u8; 1];
+                reader.read_exact(&mut bytes)?;
+                bytes[0] as usize
+            }
+        };
+        Ok(color_space.decode(sample.min(max_value) as f32 / max_value as f32))
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let r = read_sample()?;
+            let g = read_sample()?;
+            let b = read_sample()?;
+            image.set(x, y, color::Color(r, g, b));
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image() -> Framebuffer {
+        // Top row is white, bottom row is black.
+        let mut image = Framebuffer::new(2, 2);
+        image.set(0, 0, color::WHITE);
+        image.set(1, 0, color::WHITE);
+        image
+    }
+
+    #[test]
+    fn test_ascii() {
+        let mut writer = PpmWriter::new(Vec::new(), PpmFormat::Ascii, 255);
+        writer
+            .write_image(&test_image(), &Default::default())
+            .unwrap();
+
+        let text = String::from_utf8(writer.into_inner()).unwrap();
+        assert!(text == "P3\n2 2\n255\n255 255 255\n255 255 255\n0 0 0\n0 0 0\n");
+    }
+
+    #[test]
+    fn test_binary() {
+        let mut writer = PpmWriter::new(Vec::new(), PpmFormat::Binary, 255);
+        writer
+            .write_image(&test_image(), &Default::default())
+            .unwrap();
+
+        let bytes = writer.into_inner();
+        let header = b"P6\n2 2\n255\n";
+        assert!(bytes[..header.len()] == header[..]);
+        assert!(bytes[header.len()..] == [255, 255, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_binary_16bit() {
+        let mut writer = PpmWriter::new(Vec::new(), PpmFormat::Binary, 65535);
+        writer
+            .write_image(&test_image(), &Default::default())
+            .unwrap();
+
+        let bytes = writer.into_inner();
+        let header = b"P6\n2 2\n65535\n";
+        assert!(bytes.len() == header.len() + 2 * 2 * 3 * 2);
+        assert!(bytes[header.len()..header.len() + 2] == [0xff, 0xff]);
+        assert!(bytes[bytes.len() - 2..] == [0, 0]);
+    }
+
+    #[test]
+    fn test_read_round_trip() {
+        for (format, max_value) in [
+            (PpmFormat::Ascii, 255),
+            (PpmFormat::Binary, 255),
+            (PpmFormat::Binary, 65535),
+        ] {
+            let mut writer = PpmWriter::new(Vec::new(), format, max_value);
+            writer
+                .write_image(&test_image(), &Default::default())
+                .unwrap();
+
+            let image = read_ppm(&writer.into_inner()[..], ColorSpace::Linear).unwrap();
+            assert!(image.width == 2 && image.height == 2);
+            assert!(image.get(1, 0) == color::WHITE);
+            assert!(image.get(0, 1) == color::Color(0.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn test_read_comments_and_errors() {
+        let text = "P3\n# a comment\n1 1 # trailing\n10\n10 5 0\n";
+        let image = read_ppm(text.as_bytes(), ColorSpace::Linear).unwrap();
+        assert!(image.get(0, 0) == color::Color(1.0, 0.5, 0.0));
 
-        writeln!(file, "P3")?;
-        writeln!(file, "{} {}", image_width, image_height)?;
-        writeln!(file, "255")?;
+        assert!(read_ppm("P5\n1 1\n255\n".as_bytes(), ColorSpace::Linear).is_err());
+        assert!(read_ppm("P3\n1 1\n255\n10 10".as_bytes(), ColorSpace::Linear).is_err());
 
-        Ok(PpmWriter { file })
+        // Sizes which are empty, overflow or don't match the data are rejected up front.
+        assert!(read_ppm("P6\n0 4\n255\n".as_bytes(), ColorSpace::Linear).is_err());
+        let huge = format!("P6\n{} {}\n255\n", usize::MAX, 2);
+        assert!(read_ppm(huge.as_bytes(), ColorSpace::Linear).is_err());
+        let short = "P6\n1000000 1000000\n65535\n\0\0\0\0\0\0";
+        assert!(read_ppm(short.as_bytes(), ColorSpace::Linear).is_err());
     }
 
-    pub fn write_color(&mut self, pixel_color: color::Color) -> std::io::Result<()> {
-        writeln!(
-            self.file,
-            "{} {} {}",
-            pixel_color.0 as i32, pixel_color.1 as i32, pixel_color.2 as i32
-        )?;
-        Ok(())
+    #[test]
+    fn test_create_error() {
+        assert!(PpmWriter::create("/nonexistent/image.ppm", PpmFormat::Ascii, 255).is_err());
     }
 }
@@ -0,0 +1,354 @@
+use super::color;
+use super::hittable;
+use super::material::{Evaluation, Material, RoughDielectric, Scattering};
+use super::microfacet;
+use super::ray;
+use super::texture::{SolidColor, Texture};
+use super::util;
+use super::vec3;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+// Lower bound on roughness, keeping every lobe glossy so it can be evaluated.
+static MIN_ROUGHNESS: f32 = 0.05;
+
+// Width of the clearcoat layer's highlight.
+static CLEARCOAT_ALPHA: f32 = 0.05;
+
+// A layered uber-material after Burley 2012, "Physically Based Shading at Disney",
+// with the transmission extension from Burley 2015.
+// base_color: Diffuse color for dielectrics, reflectance for metals, tint of transmission.
+// metallic: Blend from dielectric (0) to metal (1).
+// roughness: Perceptual roughness of the specular and transmission lobes.
+// specular: Reflectance of dielectrics at normal incidence, 0.5 being 4%.
+// clearcoat: Strength of a second, glossy and uncolored specular layer.
+// sheen: Strength of the extra grazing angle reflection seen on cloth.
+// transmission: Blend from an opaque to a fully transmissive dielectric.
+// ior: Index of refraction of the transmissive part.
+// All parameters are textures, scalar ones are read from the red channel.
+pub struct Principled {
+    pub base_color: Rc<dyn Texture>,
+    pub metallic: Rc<dyn Texture>,
+    pub roughness: Rc<dyn Texture>,
+    pub specular: Rc<dyn Texture>,
+    pub clearcoat: Rc<dyn Texture>,
+    pub sheen: Rc<dyn Texture>,
+    pub transmission: Rc<dyn Texture>,
+    pub ior: Rc<dyn Texture>,
+}
+
+fn constant(value: f32) -> Rc<dyn Texture> {
+    Rc::new(SolidColor::scalar(value))
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Principled {
+            base_color: constant(0.8),
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            clearcoat: constant(0.0),
+            sheen: constant(0.0),
+            transmission: constant(0.0),
+            ior: constant(1.5),
+        }
+    }
+}
+
+// The parameters looked up at a hit point, and the lobes they turn into.
+struct Parameters {
+    base_color: color::Color,
+    roughness: f32,
+    specular_color: color::Color,
+    sheen_color: color::Color,
+    diffuse_weight: f32,
+    specular_weight: f32,
+    clearcoat_weight: f32,
+    transmission_weight: f32,
+    specular: microfacet::TrowbridgeReitz,
+    dielectric: RoughDielectric,
+}
+
+// Probabilities of sampling each lobe, summing to one.
+struct LobeProbabilities {
+    diffuse: f32,
+    specular: f32,
+    clearcoat: f32,
+    transmission: f32,
+}
+
+fn schlick_weight(cosine: f32) -> f32 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+impl Parameters {
+    fn probabilities(&self, metallic: f32) -> Option<LobeProbabilities> {
+        // Dielectric highlights reflect a few percent, so sample them less often.
+        let diffuse = self.diffuse_weight;
+        let specular = self.specular_weight * (0.25 + 0.75 * metallic);
+        let clearcoat = self.clearcoat_weight;
+        let transmission = self.transmission_weight;
+        let total = diffuse + specular + clearcoat + transmission;
+        if total <= 0.0 {
+            return None;
+        }
+        Some(LobeProbabilities {
+            diffuse: diffuse / total,
+            specular: specular / total,
+            clearcoat: clearcoat / total,
+            transmission: transmission / total,
+        })
+    }
+}
+
+impl Principled {
+    pub fn new() -> Self {
+        Principled::default()
+    }
+
+    // A material with a constant base color and the default parameters.
+    pub fn from_color(base_color: color::Color) -> Self {
+        Principled {
+            base_color: Rc::new(SolidColor::new(base_color)),
+            ..Principled::default()
+        }
+    }
+
+    fn parameters(&self, hit_record: &hittable::HitRecord) -> (Parameters, f32) {
+        let (u, v, p) = (hit_record.u, hit_record.v, &hit_record.p);
+        let scalar = |texture: &Rc<dyn Texture>| texture.value_scalar(u, v, p).clamp(0.0, 1.0);
+
+        let base_color = self.base_color.value(u, v, p);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness).max(MIN_ROUGHNESS);
+        let specular = scalar(&self.specular);
+        let clearcoat = scalar(&self.clearcoat);
+        let sheen = scalar(&self.sheen);
+        let transmission = scalar(&self.transmission);
+        let ior = self.ior.value_scalar(u, v, p).max(1.0);
+
+        // Sheen is tinted halfway towards the hue of the base color.
+        let base_luminance = color::luminance(base_color);
+        let tint = if base_luminance > 0.0 {
+            base_color / base_luminance
+        } else {
+            color::WHITE
+        };
+        let sheen_color = (color::WHITE * 0.5 + tint * 0.5) * sheen;
+
+        let specular_color =
+            color::WHITE * (0.08 * specular * (1.0 - metallic)) + base_color * metallic;
+
+        let parameters = Parameters {
+            base_color,
+            roughness,
+            specular_color,
+            sheen_color,
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            specular_weight: 1.0 - (1.0 - metallic) * transmission,
+            clearcoat_weight: 0.25 * clearcoat,
+            transmission_weight: (1.0 - metallic) * transmission,
+            specular: microfacet::TrowbridgeReitz::from_roughness(roughness),
+            dielectric: RoughDielectric::new(ior, roughness),
+        };
+        (parameters, metallic)
+    }
+
+    // Sum of all lobes for scattering into the unit direction, and the combined
+    // density of sampling it.
+    fn evaluate(
+        &self,
+        parameters: &Parameters,
+        probabilities: &LobeProbabilities,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        direction: &vec3::Vec3,
+    ) -> Evaluation {
+        let frame = vec3::Frame::from_normal(&hit_record.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vector());
+        let wi = frame.to_local(direction);
+        let mut value = color::Color(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+
+        if parameters.transmission_weight > 0.0 {
+            if let Some(evaluation) = parameters.dielectric.eval(ray, hit_record, direction) {
+                let mut transmitted = evaluation.value * parameters.transmission_weight;
+                if wi.z() < 0.0 {
+                    // Tint by the square root so two interfaces give the base color.
+                    let tint = parameters.base_color;
+                    transmitted =
+                        transmitted * color::Color(tint.0.sqrt(), tint.1.sqrt(), tint.2.sqrt());
+                }
+                value = value + transmitted;
+                pdf += probabilities.transmission * evaluation.pdf;
+            }
+        }
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Evaluation { value, pdf };
+        }
+
+        let wm = (wo + wi).unit_vector();
+        let cos_d = wi.dot(wm);
+
+        // Burley's diffuse with retro-reflection at grazing angles, plus sheen.
+        let fd90 = 0.5 + 2.0 * parameters.roughness * cos_d * cos_d;
+        let diffuse = parameters.base_color
+            * ((1.0 + (fd90 - 1.0) * schlick_weight(wi.z()))
+                * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()))
+                / PI);
+        let sheen = parameters.sheen_color * schlick_weight(cos_d);
+        value = value + (diffuse + sheen) * (parameters.diffuse_weight * wi.z());
+        pdf += probabilities.diffuse * wi.z() / PI;
+
+        let specular = &parameters.specular;
+        let fresnel = parameters.specular_color
+            + (color::WHITE - parameters.specular_color) * schlick_weight(wo.dot(wm));
+        let brdf = specular.d(&wm) * specular.g(&wo, &wi) / (4.0 * wo.z());
+        value = value + fresnel * (brdf * parameters.specular_weight);
+        pdf += probabilities.specular * specular.visible_d(&wo, &wm) / (4.0 * wo.dot(wm));
+
+        if parameters.clearcoat_weight > 0.0 {
+            let clearcoat = microfacet::Gtr1::new(CLEARCOAT_ALPHA);
+            let fresnel = 0.04 + 0.96 * schlick_weight(wo.dot(wm));
+            let shadowing = microfacet::TrowbridgeReitz::new(0.25).g(&wo, &wi);
+            let d = clearcoat.d(&wm);
+            value = value
+                + color::WHITE
+                    * (parameters.clearcoat_weight * d * shadowing * fresnel / (4.0 * wo.z()));
+            pdf += probabilities.clearcoat * d * wm.z() / (4.0 * wo.dot(wm));
+        }
+
+        Evaluation { value, pdf }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> Option<Scattering> {
+        let (parameters, metallic) = self.parameters(hit_record);
+        let probabilities = parameters.probabilities(metallic)?;
+        let frame = vec3::Frame::from_normal(&hit_record.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vector());
+
+        // Pick one lobe to sample, then weigh the direction by all of them.
+        let choice = util::random_float();
+        let specular_end = probabilities.diffuse + probabilities.specular;
+        let direction = if choice < probabilities.diffuse {
+            let mut direction = hit_record.normal + vec3::random_unit_vector();
+            if direction.near_zero() {
+                direction = hit_record.normal;
+            }
+            direction
+        } else if choice < specular_end {
+            let wm = parameters.specular.sample_visible_normal(
+                &wo,
+                util::random_float(),
+                util::random_float(),
+            );
+            frame.to_world(&vec3::reflect(&-wo, &wm))
+        } else if choice < specular_end + probabilities.clearcoat {
+            let wm = microfacet::Gtr1::new(CLEARCOAT_ALPHA)
+                .sample_normal(util::random_float(), util::random_float());
+            frame.to_world(&vec3::reflect(&-wo, &wm))
+        } else {
+            parameters
+                .dielectric
+                .scatter(ray, hit_record)?
+                .scattered
+                .direction
+        };
+
+        let direction = direction.unit_vector();
+        let evaluation = self.evaluate(&parameters, &probabilities, ray, hit_record, &direction);
+        if evaluation.pdf <= 0.0 {
+            return None;
+        }
+        Some(Scattering {
+            scattered: ray::Ray::new(hit_record.p, direction),
+            attenuation: evaluation.value / evaluation.pdf,
+            pdf: Some(evaluation.pdf),
+        })
+    }
+
+    fn albedo(&self, hit_record: &hittable::HitRecord) -> color::Color {
+        self.base_color
+            .value(hit_record.u, hit_record.v, &hit_record.p)
+    }
+
+    fn eval(
+        &self,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        direction: &vec3::Vec3,
+    ) -> Option<Evaluation> {
+        let (parameters, metallic) = self.parameters(hit_record);
+        let probabilities = match parameters.probabilities(metallic) {
+            Some(probabilities) => probabilities,
+            None => {
+                return Some(Evaluation {
+                    value: color::Color(0.0, 0.0, 0.0),
+                    pdf: 0.0,
+                })
+            }
+        };
+        Some(self.evaluate(&parameters, &probabilities, ray, hit_record, direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::check_consistent;
+
+    #[test]
+    fn test_principled_consistent() {
+        check_consistent(
+            Rc::new(Principled::from_color(color::Color(0.8, 0.3, 0.2))),
+            true,
+        );
+
+        let mut coated_metal = Principled::from_color(color::Color(0.9, 0.6, 0.3));
+        coated_metal.metallic = constant(1.0);
+        coated_metal.roughness = constant(0.3);
+        coated_metal.clearcoat = constant(1.0);
+        check_consistent(Rc::new(coated_metal), true);
+
+        let mut cloth = Principled::from_color(color::Color(0.2, 0.3, 0.8));
+        cloth.roughness = constant(1.0);
+        cloth.sheen = constant(1.0);
+        check_consistent(Rc::new(cloth), true);
+
+        let mut glass = Principled::from_color(color::Color(0.9, 1.0, 0.9));
+        glass.transmission = constant(1.0);
+        glass.roughness = constant(0.2);
+        check_consistent(Rc::new(glass), true);
+
+        let mut glass = Principled::from_color(color::WHITE);
+        glass.transmission = constant(0.5);
+        check_consistent(Rc::new(glass), false);
+    }
+
+    #[test]
+    fn test_principled_energy() {
+        // A white diffuse surface lit from above shouldn't reflect more than it receives.
+        let material = Principled::from_color(color::WHITE);
+        let normal = vec3::Vec3(0.0, 1.0, 0.0);
+        let ray = ray::Ray::new(vec3::Point3(0.0, 1.0, 0.0), -normal);
+        let hit_record = hittable::HitRecord::new(
+            1.0,
+            vec3::Point3(0.0, 0.0, 0.0),
+            &ray,
+            normal,
+            Rc::new(Principled::new()),
+        );
+        let samples = 4000;
+        let mut reflected = color::Color(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            if let Some(scattering) = material.scatter(&ray, &hit_record) {
+                reflected = reflected + scattering.attenuation;
+            }
+        }
+        let albedo = reflected.0 / samples as f32;
+        assert!(albedo > 0.5 && albedo < 1.1);
+    }
+}
@@ -4,20 +4,36 @@ use super::vec3;
 // P is a position along a 3D line
 // A is the origin of the ray
 // B is the direction of the ray
+// wavelength: Wavelength in nanometers carried by the ray in spectral mode.
 #[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub origin: vec3::Point3,
     pub direction: vec3::Vec3,
+    pub wavelength: Option<f32>,
 }
 
 impl Ray {
     pub fn new(origin: vec3::Vec3, direction: vec3::Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            wavelength: None,
+        }
     }
 
     pub fn at(&self, t: f32) -> vec3::Point3 {
         self.origin + (self.direction * t)
     }
+
+    // The same ray expressed in the orthonormal frame placed at origin. Distances
+    // are preserved, so t values carry over between the two.
+    pub fn to_local(self, origin: &vec3::Point3, frame: &vec3::Frame) -> Ray {
+        Ray {
+            origin: frame.to_local(&(self.origin - *origin)),
+            direction: frame.to_local(&self.direction),
+            ..self
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,149 @@
+// Piecewise constant distributions, used to importance sample tabulated functions.
+
+// A piecewise constant 1D distribution over [0, 1].
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    pub fn new(func: &[f32]) -> Self {
+        assert!(!func.is_empty(), "empty distribution");
+        let n = func.len();
+        let func: Vec<f32> = func.iter().map(|f| f.max(0.0)).collect();
+
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as f32;
+        }
+        let integral = cdf[n];
+        for (i, value) in cdf.iter_mut().enumerate() {
+            // An all zero function falls back to uniform sampling.
+            *value = if integral > 0.0 {
+                *value / integral
+            } else {
+                i as f32 / n as f32
+            };
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    // Map a uniform random number in [0, 1) to a sample in [0, 1).
+    // Returns the sample, its density and the index of the segment it landed in.
+    pub fn sample_continuous(&self, u: f32) -> (f32, f32, usize) {
+        // Last cdf entry that is <= u, found by binary search as the cdf is sorted.
+        let offset = self
+            .cdf
+            .partition_point(|&value| value <= u)
+            .saturating_sub(1)
+            .min(self.count() - 1);
+
+        let mut du = u - self.cdf[offset];
+        let segment = self.cdf[offset + 1] - self.cdf[offset];
+        if segment > 0.0 {
+            du /= segment;
+        }
+
+        let x = ((offset as f32 + du) / self.count() as f32).min(1.0 - f32::EPSILON);
+        (x, self.pdf(offset), offset)
+    }
+
+    // Density of sampling anywhere within the given segment.
+    pub fn pdf(&self, offset: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.func[offset] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+// A piecewise constant 2D distribution over [0, 1]^2, from a row major table of values.
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f32], width: usize, height: usize) -> Self {
+        let conditional: Vec<Distribution1D> = (0..height)
+            .map(|y| Distribution1D::new(&func[y * width..(y + 1) * width]))
+            .collect();
+        let row_integrals: Vec<f32> = conditional.iter().map(|row| row.integral()).collect();
+        Distribution2D {
+            marginal: Distribution1D::new(&row_integrals),
+            conditional,
+        }
+    }
+
+    // Returns the sampled point (u, v) and its density.
+    pub fn sample_continuous(&self, u0: f32, u1: f32) -> ((f32, f32), f32) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f32, v: f32) -> f32 {
+        let width = self.conditional[0].count();
+        let height = self.marginal.count();
+        let x = ((u * width as f32) as usize).min(width - 1);
+        let y = ((v * height as f32) as usize).min(height - 1);
+        self.marginal.pdf(y) * self.conditional[y].pdf(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribution_1d() {
+        let distribution = Distribution1D::new(&[1.0, 3.0]);
+
+        assert!(distribution.integral() == 2.0);
+        assert!(distribution.pdf(0) == 0.5);
+        assert!(distribution.pdf(1) == 1.5);
+
+        let (x, pdf, offset) = distribution.sample_continuous(0.125);
+        assert!(offset == 0 && pdf == 0.5 && (x - 0.25).abs() < 1e-6);
+        let (x, pdf, offset) = distribution.sample_continuous(0.625);
+        assert!(offset == 1 && pdf == 1.5 && (x - 0.75).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_distribution_zero() {
+        let distribution = Distribution1D::new(&[0.0, 0.0, 0.0, 0.0]);
+        let (x, pdf, _) = distribution.sample_continuous(0.6);
+        assert!((x - 0.6).abs() < 1e-6 && pdf == 1.0);
+
+        // Samples skip over segments with no weight.
+        let distribution = Distribution1D::new(&[1.0, 0.0, 0.0, 1.0]);
+        let (x, _, offset) = distribution.sample_continuous(0.5);
+        assert!(offset == 3 && x == 0.75);
+    }
+
+    #[test]
+    fn test_distribution_2d() {
+        // Only the bottom right cell has any weight.
+        let distribution = Distribution2D::new(&[0.0, 0.0, 0.0, 1.0], 2, 2);
+
+        let ((u, v), pdf) = distribution.sample_continuous(0.3, 0.7);
+        assert!(u >= 0.5 && v >= 0.5);
+        assert!((pdf - 4.0).abs() < 1e-6);
+        assert!(distribution.pdf(0.25, 0.25) == 0.0);
+        assert!((distribution.pdf(u, v) - 4.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,25 @@
+use super::background;
+use super::hittable_list::HittableList;
+use super::light;
+
+// Everything needed to render an image: the objects, the light surrounding them,
+// and any punctual lights.
+pub struct Scene {
+    pub world: HittableList,
+    pub background: Box<dyn background::Background>,
+    pub lights: Vec<Box<dyn light::Light>>,
+}
+
+impl Scene {
+    pub fn new(world: HittableList, background: Box<dyn background::Background>) -> Self {
+        Scene {
+            world,
+            background,
+            lights: Vec::new(),
+        }
+    }
+
+    pub fn add_light(&mut self, light: Box<dyn light::Light>) {
+        self.lights.push(light);
+    }
+}
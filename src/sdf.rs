@@ -0,0 +1,512 @@
+use super::aabb::Aabb;
+use super::hittable;
+use super::material;
+use super::ray;
+use super::vec3;
+use std::option::Option;
+use std::rc::Rc;
+
+// Sphere tracing stops once this close to the surface.
+static HIT_EPSILON: f32 = 1e-4;
+// Offset used to estimate normals from the gradient of the distance.
+static NORMAL_EPSILON: f32 = 1e-4;
+static MAX_STEPS: usize = 512;
+// How far rays are marched through fields that have no bounds.
+static MAX_DISTANCE: f32 = 1000.0;
+
+// A signed distance function, negative inside the shape. The value must never be
+// more than the distance to the surface, or marching may step through it.
+pub trait Sdf {
+    fn distance(&self, p: &vec3::Point3) -> f32;
+
+    // A box containing the shape, None if it is unbounded.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+// Any function of the position works as a distance field.
+impl<F> Sdf for F
+where
+    F: Fn(&vec3::Point3) -> f32,
+{
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        self(p)
+    }
+}
+
+fn cube(center: vec3::Point3, half_size: f32) -> Aabb {
+    let extent = vec3::Vec3(half_size, half_size, half_size);
+    Aabb::new(center - extent, center + extent)
+}
+
+fn grow(aabb: Aabb, amount: f32) -> Aabb {
+    let extent = vec3::Vec3(amount, amount, amount);
+    Aabb::new(aabb.min - extent, aabb.max + extent)
+}
+
+pub struct Sphere {
+    pub center: vec3::Point3,
+    pub radius: f32,
+}
+
+impl Sdf for Sphere {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        (*p - self.center).norm() - self.radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(cube(self.center, self.radius))
+    }
+}
+
+// An axis aligned box with its edges rounded off.
+// half_size: Distance from the center to the faces, before rounding.
+// rounding: Radius of the rounded edges, which grow the box by that much.
+pub struct Cuboid {
+    pub center: vec3::Point3,
+    pub half_size: vec3::Vec3,
+    pub rounding: f32,
+}
+
+impl Sdf for Cuboid {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        let q = *p - self.center;
+        let q = vec3::Vec3(
+            q.x().abs() - self.half_size.x(),
+            q.y().abs() - self.half_size.y(),
+            q.z().abs() - self.half_size.z(),
+        );
+        let outside = vec3::Vec3(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0)).norm();
+        let inside = q.x().max(q.y()).max(q.z()).min(0.0);
+        outside + inside - self.rounding
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let aabb = Aabb::new(self.center - self.half_size, self.center + self.half_size);
+        Some(grow(aabb, self.rounding))
+    }
+}
+
+// A ring around the y axis.
+pub struct Torus {
+    pub center: vec3::Point3,
+    pub major_radius: f32,
+    pub minor_radius: f32,
+}
+
+impl Sdf for Torus {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        let q = *p - self.center;
+        let ring = (q.x() * q.x() + q.z() * q.z()).sqrt() - self.major_radius;
+        (ring * ring + q.y() * q.y()).sqrt() - self.minor_radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = self.major_radius + self.minor_radius;
+        Some(Aabb::new(
+            self.center - vec3::Vec3(extent, self.minor_radius, extent),
+            self.center + vec3::Vec3(extent, self.minor_radius, extent),
+        ))
+    }
+}
+
+// All points within radius of the segment from a to b.
+pub struct Capsule {
+    pub a: vec3::Point3,
+    pub b: vec3::Point3,
+    pub radius: f32,
+}
+
+impl Sdf for Capsule {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        let pa = *p - self.a;
+        let ba = self.b - self.a;
+        let h = (pa.dot(ba) / ba.norm_squared()).clamp(0.0, 1.0);
+        (pa - ba * h).norm() - self.radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let aabb = Aabb::from_points(vec![self.a, self.b])?;
+        Some(grow(aabb, self.radius))
+    }
+}
+
+// Everything below the plane through point facing along normal.
+pub struct Plane {
+    pub point: vec3::Point3,
+    pub normal: vec3::Vec3,
+}
+
+impl Sdf for Plane {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        (*p - self.point).dot(self.normal.unit_vector())
+    }
+}
+
+// The power 8 Mandelbulb is the classic one. The distance is an estimate from the
+// running derivative of the iteration.
+// power: Exponent of the iterated function.
+// iterations: More give finer detail at the cost of speed.
+pub struct Mandelbulb {
+    pub power: f32,
+    pub iterations: usize,
+}
+
+impl Sdf for Mandelbulb {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        let mut z = *p;
+        let mut derivative = 1.0;
+        let mut radius = z.norm();
+        for _ in 0..self.iterations {
+            if radius > 2.0 {
+                break;
+            }
+            let theta = (z.z() / radius).clamp(-1.0, 1.0).acos() * self.power;
+            let phi = z.y().atan2(z.x()) * self.power;
+            derivative = radius.powf(self.power - 1.0) * self.power * derivative + 1.0;
+            let scaled = radius.powf(self.power);
+            z = vec3::Vec3(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            ) * scaled
+                + *p;
+            radius = z.norm();
+        }
+        if radius == 0.0 {
+            return 0.0;
+        }
+        0.5 * radius.ln() * radius / derivative
+    }
+
+    // Points beyond the escape radius diverge, so the set lies within it.
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(cube(vec3::Point3(0.0, 0.0, 0.0), 2.0))
+    }
+}
+
+pub struct Translate {
+    pub sdf: Box<dyn Sdf>,
+    pub offset: vec3::Vec3,
+}
+
+impl Sdf for Translate {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        self.sdf.distance(&(*p - self.offset))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let aabb = self.sdf.bounding_box()?;
+        Some(Aabb::new(aabb.min + self.offset, aabb.max + self.offset))
+    }
+}
+
+// Uniform scaling about the origin, which keeps distances exact.
+pub struct Scale {
+    pub sdf: Box<dyn Sdf>,
+    pub factor: f32,
+}
+
+impl Sdf for Scale {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        self.sdf.distance(&(*p / self.factor)) * self.factor
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let aabb = self.sdf.bounding_box()?;
+        Some(Aabb::new(aabb.min * self.factor, aabb.max * self.factor))
+    }
+}
+
+pub struct Union {
+    pub a: Box<dyn Sdf>,
+    pub b: Box<dyn Sdf>,
+}
+
+impl Sdf for Union {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        self.a.distance(p).min(self.b.distance(p))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.a.bounding_box()?.surrounding(self.b.bounding_box()?))
+    }
+}
+
+pub struct Intersection {
+    pub a: Box<dyn Sdf>,
+    pub b: Box<dyn Sdf>,
+}
+
+impl Sdf for Intersection {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        self.a.distance(p).max(self.b.distance(p))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match (self.a.bounding_box(), self.b.bounding_box()) {
+            (Some(a), Some(b)) => Some(a.overlap(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+// a with b carved out of it.
+pub struct Difference {
+    pub a: Box<dyn Sdf>,
+    pub b: Box<dyn Sdf>,
+}
+
+impl Sdf for Difference {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        self.a.distance(p).max(-self.b.distance(p))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.a.bounding_box()
+    }
+}
+
+// A union that fills in the creases where the shapes meet, using the polynomial
+// smooth minimum.
+// smoothness: Size of the fillet, zero gives a plain union.
+pub struct SmoothUnion {
+    pub a: Box<dyn Sdf>,
+    pub b: Box<dyn Sdf>,
+    pub smoothness: f32,
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        if self.smoothness <= 0.0 {
+            return a.min(b);
+        }
+        let h = (0.5 + 0.5 * (b - a) / self.smoothness).clamp(0.0, 1.0);
+        b + (a - b) * h - self.smoothness * h * (1.0 - h)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // The fillet pushes the surface out by at most a quarter of the smoothness.
+        let aabb = self.a.bounding_box()?.surrounding(self.b.bounding_box()?);
+        Some(grow(aabb, 0.25 * self.smoothness))
+    }
+}
+
+// Morphs between two shapes.
+// amount: Zero gives a, one gives b.
+pub struct Blend {
+    pub a: Box<dyn Sdf>,
+    pub b: Box<dyn Sdf>,
+    pub amount: f32,
+}
+
+impl Sdf for Blend {
+    fn distance(&self, p: &vec3::Point3) -> f32 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        a + (b - a) * self.amount
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.a.bounding_box()?.surrounding(self.b.bounding_box()?))
+    }
+}
+
+// Renders a distance field by sphere tracing: stepping along the ray by the
+// distance to the nearest surface, which can't overshoot it.
+pub struct SdfShape {
+    sdf: Box<dyn Sdf>,
+    material: Rc<dyn material::Material>,
+}
+
+impl SdfShape {
+    pub fn new(sdf: Box<dyn Sdf>, material: Rc<dyn material::Material>) -> Self {
+        SdfShape { sdf, material }
+    }
+
+    // Gradient of the distance from four samples on a tetrahedron.
+    fn normal(&self, p: &vec3::Point3) -> vec3::Vec3 {
+        let offsets = [
+            vec3::Vec3(1.0, -1.0, -1.0),
+            vec3::Vec3(-1.0, -1.0, 1.0),
+            vec3::Vec3(-1.0, 1.0, -1.0),
+            vec3::Vec3(1.0, 1.0, 1.0),
+        ];
+        offsets
+            .iter()
+            .fold(vec3::Vec3(0.0, 0.0, 0.0), |gradient, offset| {
+                gradient + *offset * self.sdf.distance(&(*p + *offset * NORMAL_EPSILON))
+            })
+            .unit_vector()
+    }
+}
+
+impl hittable::Hittable for SdfShape {
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<hittable::HitRecord> {
+        let (t_start, t_end) = match self.sdf.bounding_box() {
+            Some(aabb) => aabb.hit(r, t_min, t_max)?,
+            None => (t_min, t_max.min(MAX_DISTANCE)),
+        };
+
+        // Rays starting inside march the same way, on the magnitude of the distance.
+        // Rays spawned on the surface start within HIT_EPSILON of it, so hits only count
+        // once the march has got further away than that, and it steps at least as far
+        // until then. Marches starting where the ray enters the bounds can hit at once.
+        let length = r.direction.norm();
+        let mut t = t_start;
+        let mut steps = 0;
+        let mut left_surface = t_start > t_min;
+        loop {
+            let distance = self.sdf.distance(&r.at(t)).abs();
+            if distance >= HIT_EPSILON {
+                left_surface = true;
+            } else if left_surface {
+                break;
+            }
+            t += distance.max(HIT_EPSILON) / length;
+            steps += 1;
+            if t > t_end || steps == MAX_STEPS {
+                return None;
+            }
+        }
+
+        let p = r.at(t);
+        let outward_normal = self.normal(&p);
+        let mut hit_record =
+            hittable::HitRecord::new(t, p, r, outward_normal, self.material.clone());
+        let (dpdu, dpdv) = vec3::orthonormal_basis(&outward_normal);
+        hit_record.dpdu = dpdu;
+        hit_record.dpdv = dpdv;
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.sdf.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::lambertian;
+    use crate::hittable::Hittable;
+    use crate::hittable_list::HittableList;
+
+    #[test]
+    fn test_sphere_tracing() {
+        let shape = SdfShape::new(
+            Box::new(Sphere {
+                center: vec3::Point3(0.0, 0.0, 0.0),
+                radius: 1.0,
+            }),
+            lambertian(),
+        );
+        let r = ray::Ray::new(vec3::Point3(0.0, 0.6, 5.0), vec3::Vec3(0.0, 0.0, -2.0));
+        let hit_record = shape.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 2.1).abs() < 1e-3);
+        assert!((hit_record.normal - vec3::Vec3(0.0, 0.6, 0.8)).norm() < 1e-3);
+
+        // From inside, the normal still faces the ray.
+        let inside = ray::Ray::new(vec3::Point3(0.0, 0.0, 0.0), vec3::Vec3(1.0, 0.0, 0.0));
+        let hit_record = shape.hit(&inside, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 1.0).abs() < 1e-3 && !hit_record.front_face);
+
+        let miss = ray::Ray::new(vec3::Point3(0.0, 1.5, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        assert!(shape.hit(&miss, 0.001, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_spawned_rays() {
+        // Rays leaving a hit point, offset only by the integrator's t_min, must not
+        // find the surface they start on again.
+        let t_min = 0.0001;
+        let shape = SdfShape::new(
+            Box::new(Sphere {
+                center: vec3::Point3(0.0, 0.0, 0.0),
+                radius: 1.0,
+            }),
+            lambertian(),
+        );
+        for &offset in [0.0, 0.5, 0.9, 0.99].iter() {
+            let r = ray::Ray::new(vec3::Point3(0.0, offset, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+            let hit_record = shape.hit(&r, t_min, f32::INFINITY).unwrap();
+            let reflected = vec3::reflect(&r.direction, &hit_record.normal);
+            assert!(shape
+                .hit(
+                    &ray::Ray::new(hit_record.p, reflected),
+                    t_min,
+                    f32::INFINITY
+                )
+                .is_none());
+
+            let refracted = vec3::refract(&r.direction, &hit_record.normal, 1.0 / 1.5);
+            let inside = ray::Ray::new(hit_record.p, refracted);
+            let exit = shape.hit(&inside, t_min, f32::INFINITY).unwrap();
+            // Across the sphere rather than back where the ray started.
+            assert!(!exit.front_face && exit.t > 1.0);
+        }
+    }
+
+    #[test]
+    fn test_operators() {
+        let p = vec3::Point3(0.0, 0.0, 0.0);
+        let ball = |x: f32| -> Box<dyn Sdf> {
+            Box::new(Sphere {
+                center: vec3::Point3(x, 0.0, 0.0),
+                radius: 0.9,
+            })
+        };
+        let union = Union {
+            a: ball(-1.0),
+            b: ball(1.0),
+        };
+        let smooth = SmoothUnion {
+            a: ball(-1.0),
+            b: ball(1.0),
+            smoothness: 0.5,
+        };
+        // The fillet closes the gap between the two spheres.
+        assert!(union.distance(&p) > 0.0 && smooth.distance(&p) < 0.0);
+        let blend = Blend {
+            a: ball(-1.0),
+            b: ball(1.0),
+            amount: 0.5,
+        };
+        assert!(
+            (blend.distance(&vec3::Point3(0.0, 1.0, 0.0)) - (2.0_f32.sqrt() - 0.9)).abs() < 1e-6
+        );
+
+        // Closures work too, and mix with analytic spheres in one list.
+        let slab = |p: &vec3::Point3| p.y().abs() - 0.5;
+        let mut world = HittableList::new(Box::new(SdfShape::new(Box::new(slab), lambertian())));
+        world.add(Box::new(crate::sphere::Sphere::new(
+            vec3::Point3(0.0, 2.0, 0.0),
+            1.0,
+            lambertian(),
+        )));
+        let down = ray::Ray::new(vec3::Point3(0.0, 5.0, 0.0), vec3::Vec3(0.0, -1.0, 0.0));
+        let hit_record = world.hit(&down, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 2.0).abs() < 1e-4 && hit_record.object_id == 1);
+        // Starting on the top of the slab finds the bottom, not where the ray started.
+        let hit_record = world.hit(&down, 4.5, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 5.5).abs() < 1e-3 && hit_record.object_id == 0);
+    }
+
+    #[test]
+    fn test_mandelbulb() {
+        let bulb = SdfShape::new(
+            Box::new(Mandelbulb {
+                power: 8.0,
+                iterations: 12,
+            }),
+            lambertian(),
+        );
+        let r = ray::Ray::new(vec3::Point3(0.0, 0.0, 3.0), vec3::Vec3(0.0, 0.0, -1.0));
+        let hit_record = bulb.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!(hit_record.t > 1.8 && hit_record.t < 3.0);
+        assert!(hit_record.normal.z() > 0.0);
+        let miss = ray::Ray::new(vec3::Point3(0.0, 1.5, 3.0), vec3::Vec3(0.0, 0.0, -1.0));
+        assert!(bulb.hit(&miss, 0.001, f32::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,283 @@
+use super::background::{Background, BackgroundSample};
+use super::color;
+use super::util;
+use super::vec3;
+use std::f32::consts::PI;
+
+// Angular radius of the sun as seen from earth, in radians.
+static SUN_ANGULAR_RADIUS: f32 = 0.004_65;
+
+// Luminance of the sun before passing through the atmosphere, in kcd/m^2.
+static SUN_LUMINANCE: f32 = 1.6e6;
+
+// Coefficients of the Perez sky luminance distribution.
+#[derive(Debug, Copy, Clone)]
+struct Perez {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+}
+
+impl Perez {
+    // theta: Angle between the view direction and the zenith.
+    // gamma: Angle between the view direction and the sun.
+    fn evaluate(&self, cos_theta: f32, gamma: f32) -> f32 {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / cos_theta).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+// Analytic daylight model from Preetham et al. 1999, "A Practical Analytic Model
+// for Daylight", together with a sampled sun disk.
+// sun_direction: Unit vector pointing towards the sun.
+// turbidity: Haziness of the atmosphere, from 2 for clear skies to 10 for hazy ones.
+// intensity: Scale from luminance in kcd/m^2 to scene radiance.
+pub struct PreethamSky {
+    sun_direction: vec3::Vec3,
+    intensity: f32,
+    perez_luminance: Perez,
+    perez_x: Perez,
+    perez_y: Perez,
+    // Sky values in xyY at the zenith, divided by the Perez function there.
+    zenith: vec3::Vec3,
+    sun_radiance: color::Color,
+    cos_sun_radius: f32,
+}
+
+fn chromaticity(coefficients: [[f32; 4]; 3], turbidity: f32, theta_sun: f32) -> f32 {
+    let t = [turbidity * turbidity, turbidity, 1.0];
+    let theta = [theta_sun.powi(3), theta_sun * theta_sun, theta_sun, 1.0];
+    let mut value = 0.0;
+    for (row, t) in coefficients.iter().zip(t.iter()) {
+        for (coefficient, theta) in row.iter().zip(theta.iter()) {
+            value += t * coefficient * theta;
+        }
+    }
+    value
+}
+
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> color::Color {
+    if y <= 0.0 {
+        return color::Color(0.0, 0.0, 0.0);
+    }
+    let xyz = vec3::Vec3(x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+    color::xyz_to_rgb(xyz)
+}
+
+// Color of sunlight after passing through the atmosphere, following the Rayleigh
+// and aerosol terms of Preetham's sun model at representative RGB wavelengths.
+fn sun_transmittance(turbidity: f32, theta_sun: f32) -> color::Color {
+    // Relative optical mass of the atmosphere the light passes through.
+    let theta_degrees = theta_sun.to_degrees();
+    let mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+    let beta = 0.046_08 * turbidity - 0.045_86;
+    let alpha = 1.3;
+
+    // Wavelengths in micrometers.
+    let transmittance = |lambda: f32| -> f32 {
+        let rayleigh = (-0.008_735 * lambda.powf(-4.08) * mass).exp();
+        let aerosol = (-beta * lambda.powf(-alpha) * mass).exp();
+        rayleigh * aerosol
+    };
+    color::Color(
+        transmittance(0.65),
+        transmittance(0.55),
+        transmittance(0.45),
+    )
+}
+
+impl PreethamSky {
+    pub fn new(sun_direction: vec3::Vec3, turbidity: f32, intensity: f32) -> Self {
+        let sun_direction = sun_direction.unit_vector();
+        let t = turbidity;
+        // Keep the sun just above the horizon, the model breaks down below it.
+        let theta_sun = sun_direction.y().clamp(0.01, 1.0).acos();
+
+        let perez_luminance = Perez {
+            a: 0.1787 * t - 1.4630,
+            b: -0.3554 * t + 0.4275,
+            c: -0.0227 * t + 5.3251,
+            d: 0.1206 * t - 2.5771,
+            e: -0.0670 * t + 0.3703,
+        };
+        let perez_x = Perez {
+            a: -0.0193 * t - 0.2592,
+            b: -0.0665 * t + 0.0008,
+            c: -0.0004 * t + 0.2125,
+            d: -0.0641 * t - 0.8989,
+            e: -0.0033 * t + 0.0452,
+        };
+        let perez_y = Perez {
+            a: -0.0167 * t - 0.2608,
+            b: -0.0950 * t + 0.0092,
+            c: -0.0079 * t + 0.2102,
+            d: -0.0441 * t - 1.6537,
+            e: -0.0109 * t + 0.0529,
+        };
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = chromaticity(
+            [
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ],
+            t,
+            theta_sun,
+        );
+        let zenith_y = chromaticity(
+            [
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ],
+            t,
+            theta_sun,
+        );
+
+        let zenith = vec3::Vec3(
+            zenith_x / perez_x.evaluate(1.0, theta_sun),
+            zenith_y / perez_y.evaluate(1.0, theta_sun),
+            zenith_luminance / perez_luminance.evaluate(1.0, theta_sun),
+        );
+
+        let sun_radiance = sun_transmittance(t, theta_sun) * (SUN_LUMINANCE * intensity);
+
+        PreethamSky {
+            sun_direction,
+            intensity,
+            perez_luminance,
+            perez_x,
+            perez_y,
+            zenith,
+            sun_radiance,
+            cos_sun_radius: SUN_ANGULAR_RADIUS.cos(),
+        }
+    }
+
+    // Build a sky with the sun at the given elevation and azimuth, in degrees.
+    // An azimuth of 0 puts the sun towards +x, 90 towards +z.
+    pub fn from_angles(elevation: f32, azimuth: f32, turbidity: f32, intensity: f32) -> Self {
+        let elevation = util::degrees_to_radians(elevation);
+        let azimuth = util::degrees_to_radians(azimuth);
+        let sun_direction = vec3::Vec3(
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        );
+        PreethamSky::new(sun_direction, turbidity, intensity)
+    }
+
+    // The sky without the sun disk.
+    pub fn sky_radiance(&self, direction: &vec3::Vec3) -> color::Color {
+        // Mirror the sky at the horizon rather than evaluating the model below it.
+        let cos_theta = direction.y().abs().max(0.01);
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let x = self.zenith.0 * self.perez_x.evaluate(cos_theta, gamma);
+        let y = self.zenith.1 * self.perez_y.evaluate(cos_theta, gamma);
+        let luminance = self.zenith.2 * self.perez_luminance.evaluate(cos_theta, gamma);
+        xyy_to_rgb(x, y, luminance) * self.intensity
+    }
+
+    fn in_sun_disk(&self, direction: &vec3::Vec3) -> bool {
+        self.sun_direction.y() > 0.0 && direction.dot(self.sun_direction) >= self.cos_sun_radius
+    }
+
+    fn sun_pdf(&self) -> f32 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+    }
+}
+
+impl Background for PreethamSky {
+    fn radiance(&self, direction: &vec3::Vec3) -> color::Color {
+        if self.in_sun_disk(direction) {
+            return self.sun_radiance;
+        }
+        self.sky_radiance(direction)
+    }
+
+    // Only the sun is sampled, the sky is smooth enough to be found by bounces.
+    fn sample(&self) -> Option<BackgroundSample> {
+        if self.sun_direction.y() <= 0.0 {
+            return None;
+        }
+
+        // Uniformly sample the cone of directions covered by the sun.
+        let cos_theta = 1.0 - util::random_float() * (1.0 - self.cos_sun_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * util::random_float();
+        let (tangent, bitangent) = vec3::orthonormal_basis(&self.sun_direction);
+        let direction = (tangent * (sin_theta * phi.cos())
+            + bitangent * (sin_theta * phi.sin())
+            + self.sun_direction * cos_theta)
+            .unit_vector();
+
+        Some(BackgroundSample {
+            direction,
+            radiance: self.sun_radiance,
+            pdf: self.sun_pdf(),
+        })
+    }
+
+    fn pdf(&self, direction: &vec3::Vec3) -> f32 {
+        if self.in_sun_disk(direction) {
+            self.sun_pdf()
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sky_brightness() {
+        let sky = PreethamSky::from_angles(30.0, 0.0, 3.0, 1.0);
+
+        let zenith = color::luminance(sky.sky_radiance(&vec3::Vec3(0.0, 1.0, 0.0)));
+        let near_sun = color::luminance(sky.sky_radiance(&vec3::Vec3(1.0, 0.7, 0.0).unit_vector()));
+        let away_from_sun =
+            color::luminance(sky.sky_radiance(&vec3::Vec3(-1.0, 0.7, 0.0).unit_vector()));
+        assert!(zenith > 0.0);
+        assert!(near_sun > away_from_sun);
+
+        // A clear sky is blue overhead.
+        let overhead = sky.sky_radiance(&vec3::Vec3(0.0, 1.0, 0.0));
+        assert!(overhead.2 > overhead.0);
+
+        // The sun outshines the sky by orders of magnitude.
+        let sun = color::luminance(sky.radiance(&sky.sun_direction));
+        assert!(sun > 1000.0 * near_sun);
+    }
+
+    #[test]
+    fn test_sun_sampling() {
+        let sky = PreethamSky::from_angles(45.0, 90.0, 2.5, 1.0);
+        for _ in 0..16 {
+            let sample = sky.sample().unwrap();
+            // Allow for rounding at the very edge of the disk.
+            assert!(sample.direction.dot(sky.sun_direction) > sky.cos_sun_radius - 1e-6);
+            assert!(sample.pdf == sky.sun_pdf());
+        }
+        assert!(sky.pdf(&sky.sun_direction) == sky.sun_pdf());
+        assert!(sky.pdf(&vec3::Vec3(0.0, 1.0, 0.0)) == 0.0);
+
+        let night = PreethamSky::from_angles(-10.0, 0.0, 2.5, 1.0);
+        assert!(night.sample().is_none());
+    }
+
+    #[test]
+    fn test_sunset_is_redder() {
+        let noon = PreethamSky::from_angles(80.0, 0.0, 3.0, 1.0);
+        let sunset = PreethamSky::from_angles(3.0, 0.0, 3.0, 1.0);
+        let ratio = |c: color::Color| c.0 / c.2;
+        assert!(ratio(sunset.sun_radiance) > ratio(noon.sun_radiance));
+    }
+}
@@ -0,0 +1,193 @@
+use super::color;
+use super::vec3;
+
+// Range of visible wavelengths sampled in spectral mode, in nanometers.
+pub static LAMBDA_MIN: f32 = 380.0;
+pub static LAMBDA_MAX: f32 = 720.0;
+
+// Representative wavelengths of the red, green and blue channels, for evaluating
+// wavelength dependent effects in RGB mode.
+pub static RGB_WAVELENGTHS: [f32; 3] = [650.0, 550.0, 450.0];
+
+// A value varying smoothly with wavelength, interpolated linearly from its values at
+// the channels' representative wavelengths.
+pub fn color_at(c: color::Color, lambda: f32) -> f32 {
+    let [red, green, blue] = RGB_WAVELENGTHS;
+    if lambda >= red {
+        c.0
+    } else if lambda >= green {
+        c.1 + (c.0 - c.1) * (lambda - green) / (red - green)
+    } else if lambda >= blue {
+        c.2 + (c.1 - c.2) * (lambda - blue) / (green - blue)
+    } else {
+        c.2
+    }
+}
+
+// Wavelength of the helium d-line, where indices of refraction are usually quoted.
+static LAMBDA_D: f32 = 587.6;
+
+// Piecewise Gaussian with different widths either side of the mean.
+fn lobe(lambda: f32, mean: f32, sigma_below: f32, sigma_above: f32) -> f32 {
+    let sigma = if lambda < mean {
+        sigma_below
+    } else {
+        sigma_above
+    };
+    let t = (lambda - mean) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// The CIE 1931 2 degree color matching functions at a wavelength in nanometers, using
+// the multi-lobe fit from Wyman et al. 2013, "Simple Analytic Approximations to the
+// CIE XYZ Color Matching Functions".
+pub fn cie_xyz(lambda: f32) -> vec3::Vec3 {
+    vec3::Vec3(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+// Pick a wavelength uniformly over the visible range from a number in [0, 1).
+pub fn sample_wavelength(u: f32) -> f32 {
+    LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+// Converts paths traced at a single wavelength into RGB at the film.
+// Each path's color is weighted by the sRGB response to its wavelength, scaled so
+// an equal energy spectrum averages back to white. Scenes without wavelength
+// dependent materials then render the same as in RGB mode.
+#[derive(Debug, Copy, Clone)]
+pub struct SpectralFilm {
+    // Integral of the sRGB response over the visible range.
+    normalization: color::Color,
+}
+
+impl SpectralFilm {
+    pub fn new() -> Self {
+        let steps = 1000;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f32;
+        let mut normalization = color::Color(0.0, 0.0, 0.0);
+        for i in 0..steps {
+            let lambda = LAMBDA_MIN + (i as f32 + 0.5) * step;
+            normalization = normalization + color::xyz_to_rgb(cie_xyz(lambda)) * step;
+        }
+        SpectralFilm { normalization }
+    }
+
+    // Weight for a path traced at the wavelength sampled by sample_wavelength.
+    pub fn weight(&self, lambda: f32) -> color::Color {
+        let response = color::xyz_to_rgb(cie_xyz(lambda)) * (LAMBDA_MAX - LAMBDA_MIN);
+        color::Color(
+            response.0 / self.normalization.0,
+            response.1 / self.normalization.1,
+            response.2 / self.normalization.2,
+        )
+    }
+}
+
+impl Default for SpectralFilm {
+    fn default() -> Self {
+        SpectralFilm::new()
+    }
+}
+
+// An index of refraction, optionally varying with wavelength.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RefractiveIndex {
+    Constant(f32),
+    // Cauchy's equation n = a + b / lambda^2, with lambda in micrometers.
+    Cauchy { a: f32, b: f32 },
+    // The Sellmeier equation n^2 = 1 + sum(b_i lambda^2 / (lambda^2 - c_i)), with
+    // lambda in micrometers.
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl RefractiveIndex {
+    // Schott N-BK7 crown glass.
+    pub fn bk7() -> Self {
+        RefractiveIndex::Sellmeier {
+            b: [1.039_612, 0.231_792_34, 1.010_469_5],
+            c: [0.006_000_699, 0.020_017_914, 103.560_65],
+        }
+    }
+
+    // Diamond, which disperses light strongly.
+    pub fn diamond() -> Self {
+        RefractiveIndex::Sellmeier {
+            b: [0.330_6, 4.335_6, 0.0],
+            c: [0.030_625, 0.011_236, 0.0],
+        }
+    }
+
+    // The index at a wavelength in nanometers, or at the d-line when rendering in RGB.
+    pub fn at(&self, wavelength: Option<f32>) -> f32 {
+        let micrometers = wavelength.unwrap_or(LAMBDA_D) / 1000.0;
+        let lambda2 = micrometers * micrometers;
+        match *self {
+            RefractiveIndex::Constant(n) => n,
+            RefractiveIndex::Cauchy { a, b } => a + b / lambda2,
+            RefractiveIndex::Sellmeier { b, c } => {
+                let mut n2 = 1.0;
+                for i in 0..3 {
+                    n2 += b[i] * lambda2 / (lambda2 - c[i]);
+                }
+                n2.sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cie_xyz() {
+        // Luminous efficiency peaks near 555nm.
+        assert!((cie_xyz(555.0).1 - 1.0).abs() < 0.02);
+        assert!(cie_xyz(555.0).1 > cie_xyz(500.0).1);
+        assert!(cie_xyz(555.0).1 > cie_xyz(610.0).1);
+        assert!(cie_xyz(450.0).2 > cie_xyz(450.0).0);
+    }
+
+    #[test]
+    fn test_spectral_film_white() {
+        // Averaging the weights over the whole range gives back white.
+        let film = SpectralFilm::new();
+        let steps = 500;
+        let mut total = color::Color(0.0, 0.0, 0.0);
+        for i in 0..steps {
+            total = total + film.weight(sample_wavelength((i as f32 + 0.5) / steps as f32));
+        }
+        let average = total / steps as f32;
+        assert!((average - color::WHITE).norm() < 1e-3);
+        // Red light mostly lands in the red channel.
+        let red = film.weight(650.0);
+        assert!(red.0 > red.1 && red.0 > red.2);
+    }
+
+    #[test]
+    fn test_color_at() {
+        let c = color::Color(1.0, 0.5, 0.0);
+        assert!(color_at(c, 700.0) == 1.0);
+        assert!(color_at(c, 550.0) == 0.5);
+        assert!((color_at(c, 500.0) - 0.25).abs() < 1e-6);
+        assert!(color_at(c, 400.0) == 0.0);
+    }
+
+    #[test]
+    fn test_refractive_index() {
+        assert!(RefractiveIndex::Constant(1.5).at(Some(400.0)) == 1.5);
+        assert!((RefractiveIndex::bk7().at(None) - 1.5168).abs() < 1e-3);
+        assert!((RefractiveIndex::diamond().at(None) - 2.417).abs() < 5e-3);
+
+        // Shorter wavelengths bend more.
+        let cauchy = RefractiveIndex::Cauchy { a: 1.5, b: 0.004 };
+        assert!(cauchy.at(Some(450.0)) > cauchy.at(Some(650.0)));
+        let diamond = RefractiveIndex::diamond();
+        assert!(diamond.at(Some(450.0)) > diamond.at(Some(650.0)));
+    }
+}
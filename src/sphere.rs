@@ -1,7 +1,9 @@
+use super::aabb::Aabb;
 use super::hittable;
 use super::material;
 use super::ray;
 use super::vec3;
+use std::f32::consts::PI;
 use std::option::Option;
 use std::rc::Rc;
 
@@ -21,6 +23,35 @@ impl Sphere {
     }
 }
 
+// Texture coordinates of a point on the unit sphere.
+// u: Angle around the y axis, starting from -x.
+// v: Angle from -y up to +y.
+pub fn sphere_uv(p: &vec3::Point3) -> (f32, f32) {
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+// Partial derivatives of the position on a sphere of the given radius with respect
+// to the coordinates from sphere_uv, at the point p on the unit sphere.
+pub fn sphere_tangents(p: &vec3::Point3, radius: f32) -> (vec3::Vec3, vec3::Vec3) {
+    let dpdu = vec3::Vec3(p.z(), 0.0, -p.x()) * (2.0 * PI * radius);
+    let sin_theta = (p.x() * p.x() + p.z() * p.z()).sqrt();
+    if sin_theta == 0.0 {
+        // At the poles u has no effect, pick tangents that still face outwards.
+        return (
+            vec3::Vec3(0.0, 0.0, -2.0 * PI * radius),
+            vec3::Vec3(-p.y() * PI * radius, 0.0, 0.0),
+        );
+    }
+    let dpdv = vec3::Vec3(
+        -p.x() * p.y() / sin_theta,
+        sin_theta,
+        -p.y() * p.z() / sin_theta,
+    ) * (PI * radius);
+    (dpdu, dpdv)
+}
+
 impl hittable::Hittable for Sphere {
     // Check if the given sphere is hit by the ray.
     // If so, returns the hit record for the intersection.
@@ -50,12 +81,54 @@ impl hittable::Hittable for Sphere {
         let hit_point = r.at(root);
         let outward_normal = (hit_point - self.center) / self.radius;
 
-        Some(hittable::HitRecord::new(
-            root,
-            hit_point,
-            r,
-            outward_normal,
-            self.material.clone(),
-        ))
+        let mut hit_record =
+            hittable::HitRecord::new(root, hit_point, r, outward_normal, self.material.clone());
+        let (u, v) = sphere_uv(&outward_normal);
+        hit_record.u = u;
+        hit_record.v = v;
+        let (dpdu, dpdv) = sphere_tangents(&outward_normal, self.radius);
+        hit_record.dpdu = dpdu;
+        hit_record.dpdv = dpdv;
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = vec3::Vec3(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sphere_tangents() {
+        let points = [
+            vec3::Point3(1.0, 0.0, 0.0),
+            vec3::Point3(0.0, 0.6, 0.8),
+            vec3::Point3(0.0, 1.0, 0.0),
+            vec3::Point3(0.0, -1.0, 0.0),
+        ];
+        for p in points.iter() {
+            let (dpdu, dpdv) = sphere_tangents(p, 2.0);
+            assert!(dpdu.dot(*p).abs() < 1e-5 && dpdv.dot(*p).abs() < 1e-5);
+            // The frame is right handed around the outward normal.
+            assert!(dpdu.cross(dpdv).dot(*p) > 0.0);
+        }
+
+        // Moving a little in v moves up the sphere by about dpdv.
+        let p = vec3::Point3(0.0, 0.6, 0.8);
+        let (u, v) = sphere_uv(&p);
+        let (_, dpdv) = sphere_tangents(&p, 1.0);
+        let dv = 1e-3;
+        let theta = (v + dv) * PI;
+        let phi = u * 2.0 * PI;
+        let moved = vec3::Point3(
+            -theta.sin() * phi.cos(),
+            -theta.cos(),
+            theta.sin() * phi.sin(),
+        );
+        assert!(((moved - p) / dv - dpdv).norm() < 0.01 * dpdv.norm());
     }
 }
@@ -0,0 +1,191 @@
+use super::material;
+use super::triangle::TriangleMesh;
+use super::vec3;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read};
+use std::path::Path;
+use std::rc::Rc;
+
+// The stereolithography format written by CAD tools, as text or binary. It only holds
+// separate triangles, so vertices at exactly the same place are merged to connect
+// them again, which lets the mesh be subdivided. Its facet normals are often wrong
+// and are ignored, leaving the faceted look of CAD models.
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// Gathers the corners of triangles into shared vertices.
+#[derive(Default)]
+struct Builder {
+    positions: Vec<vec3::Point3>,
+    indices: Vec<[usize; 3]>,
+    vertices: HashMap<[u32; 3], usize>,
+}
+
+impl Builder {
+    fn vertex(&mut self, p: vec3::Point3) -> usize {
+        // Adding zero turns -0 into 0, so the two are merged too.
+        let key = [p.x(), p.y(), p.z()].map(|x| (x + 0.0).to_bits());
+        let positions = &mut self.positions;
+        *self.vertices.entry(key).or_insert_with(|| {
+            positions.push(p);
+            positions.len() - 1
+        })
+    }
+
+    fn triangle(&mut self, corners: [vec3::Point3; 3]) {
+        let triangle = corners.map(|corner| self.vertex(corner));
+        self.indices.push(triangle);
+    }
+
+    fn build(self, material: Rc<dyn material::Material>) -> TriangleMesh {
+        TriangleMesh::new(self.positions, self.indices, material)
+    }
+}
+
+fn read_binary(data: &[u8], count: usize, builder: &mut Builder) {
+    let float = |offset: usize| {
+        f32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    };
+    let point = |offset: usize| vec3::Point3(float(offset), float(offset + 4), float(offset + 8));
+    for index in 0..count {
+        // Skip the normal before the corners, and the attributes after.
+        let offset = HEADER_SIZE + 4 + index * TRIANGLE_SIZE;
+        builder.triangle([point(offset + 12), point(offset + 24), point(offset + 36)]);
+    }
+}
+
+fn read_ascii(text: &str, builder: &mut Builder) -> std::io::Result<()> {
+    let mut words = text.split_whitespace();
+    let mut corners = vec![];
+    let mut ended = false;
+    while let Some(word) = words.next() {
+        if word == "endsolid" {
+            ended = true;
+        }
+        if word != "vertex" {
+            continue;
+        }
+        let mut coordinate = || -> std::io::Result<f32> {
+            words
+                .next()
+                .and_then(|word| word.parse().ok())
+                .ok_or_else(|| invalid("invalid STL vertex"))
+        };
+        corners.push(vec3::Point3(coordinate()?, coordinate()?, coordinate()?));
+        if corners.len() == 3 {
+            builder.triangle([corners[0], corners[1], corners[2]]);
+            corners.clear();
+        }
+    }
+    if !corners.is_empty() {
+        return Err(invalid("STL facet with missing vertices"));
+    }
+    if !ended {
+        return Err(invalid("STL text has no end"));
+    }
+    Ok(())
+}
+
+// Read an STL mesh. Text files start with "solid", but so do the headers of some
+// binary ones, so the binary layout is trusted whenever the size matches it.
+pub fn read_stl<R: Read>(
+    mut reader: R,
+    material: Rc<dyn material::Material>,
+) -> std::io::Result<TriangleMesh> {
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+
+    let mut builder = Builder::default();
+    let binary_count = data
+        .get(HEADER_SIZE..HEADER_SIZE + 4)
+        .map(|count| u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize);
+    match binary_count {
+        Some(count) if data.len() == HEADER_SIZE + 4 + count * TRIANGLE_SIZE => {
+            read_binary(&data, count, &mut builder)
+        }
+        _ if data.starts_with(b"solid") => {
+            let text = std::str::from_utf8(&data).map_err(|_| invalid("invalid STL text"))?;
+            read_ascii(text, &mut builder)?;
+        }
+        _ => return Err(invalid("not an STL file")),
+    }
+    Ok(builder.build(material))
+}
+
+pub fn load_stl<P: AsRef<Path>>(
+    path: P,
+    material: Rc<dyn material::Material>,
+) -> std::io::Result<TriangleMesh> {
+    read_stl(BufReader::new(File::open(path)?), material)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::lambertian;
+
+    // A unit square in the xy plane as two triangles.
+    fn square() -> [[f32; 9]; 2] {
+        [
+            [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+        ]
+    }
+
+    #[test]
+    fn test_ascii_stl() {
+        let mut file = String::from("solid square\n");
+        for triangle in square() {
+            file += "  facet normal 0 0 1\n    outer loop\n";
+            for corner in triangle.chunks(3) {
+                file += &format!("      vertex {} {} {}\n", corner[0], corner[1], corner[2]);
+            }
+            file += "    endloop\n  endfacet\n";
+        }
+        file += "endsolid square\n";
+
+        let mesh = read_stl(file.as_bytes(), lambertian()).unwrap();
+        // The shared corners are merged.
+        assert!(mesh.positions().len() == 4);
+        assert!(mesh.indices() == [[0, 1, 2], [0, 2, 3]]);
+        assert!(mesh.positions()[2] == vec3::Point3(1.0, 1.0, 0.0));
+
+        let truncated = "solid broken\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0";
+        assert!(read_stl(truncated.as_bytes(), lambertian()).is_err());
+    }
+
+    #[test]
+    fn test_binary_stl() {
+        // A header starting like a text file, which must not fool the reader.
+        let mut file = b"solid but binary".to_vec();
+        file.resize(HEADER_SIZE, 0);
+        file.extend(2u32.to_le_bytes());
+        for triangle in square() {
+            file.extend([0.0f32, 0.0, 1.0].iter().flat_map(|x| x.to_le_bytes()));
+            file.extend(triangle.iter().flat_map(|x| x.to_le_bytes()));
+            file.extend([0, 0]);
+        }
+
+        let mesh = read_stl(file.as_slice(), lambertian()).unwrap();
+        assert!(mesh.len() == 2 && mesh.positions().len() == 4);
+        assert!(mesh.indices() == [[0, 1, 2], [0, 2, 3]]);
+
+        // Placed in the scene at twice the size.
+        let mesh = mesh.transform(2.0, vec3::Vec3(0.0, 0.0, -1.0));
+        assert!(mesh.positions()[2] == vec3::Point3(2.0, 2.0, -1.0));
+
+        file.pop();
+        assert!(read_stl(file.as_slice(), lambertian()).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use super::color;
+use super::texture::Texture;
+use super::triangle::TriangleMesh;
+use super::vec3;
+use std::collections::{BTreeMap, HashMap};
+
+// Loop subdivision and displacement of triangle meshes, to turn coarse imported
+// models into smooth or finely detailed ones. Both produce a new mesh with smooth
+// vertex normals, ready to be used like any other.
+
+// The parts of a mesh changed by subdivision.
+struct Surface {
+    positions: Vec<vec3::Point3>,
+    uvs: Option<Vec<(f32, f32)>>,
+    colors: Option<Vec<color::Color>>,
+    indices: Vec<[usize; 3]>,
+}
+
+// Edges are keyed by their vertices in increasing order.
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+// Area weighted vertex normals, from the counter clockwise winding of the triangles.
+pub fn vertex_normals(positions: &[vec3::Point3], indices: &[[usize; 3]]) -> Vec<vec3::Vec3> {
+    let mut normals = vec![vec3::Vec3(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices {
+        let [p0, p1, p2] = triangle.map(|index| positions[index]);
+        // The cross product is twice the area of the triangle long.
+        let normal = (p1 - p0).cross(p2 - p0);
+        for &index in triangle {
+            normals[index] = normals[index] + normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.near_zero() {
+                vec3::Vec3(0.0, 0.0, 1.0)
+            } else {
+                normal.unit_vector()
+            }
+        })
+        .collect()
+}
+
+// One level of Loop subdivision, splitting every triangle into four. Edges with a
+// single triangle are boundaries, which are smoothed along themselves only, so open
+// meshes keep their outline and corners stay in place. Texture coordinates and
+// vertex colors are interpolated linearly.
+fn subdivide_once(surface: &Surface) -> Surface {
+    let (positions, indices) = (&surface.positions, &surface.indices);
+    let uvs = surface.uvs.as_deref();
+    let colors = surface.colors.as_deref();
+    // The vertices opposite each edge, in the triangles sharing it. Kept in order so
+    // new vertices are numbered the same way every time.
+    let mut edges: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+    for &[a, b, c] in indices {
+        for (from, to, opposite) in [(a, b, c), (b, c, a), (c, a, b)] {
+            edges.entry(edge_key(from, to)).or_default().push(opposite);
+        }
+    }
+
+    let mut neighbours = vec![vec![]; positions.len()];
+    let mut boundary_neighbours = vec![vec![]; positions.len()];
+    for (&(a, b), opposite) in &edges {
+        neighbours[a].push(b);
+        neighbours[b].push(a);
+        if opposite.len() != 2 {
+            boundary_neighbours[a].push(b);
+            boundary_neighbours[b].push(a);
+        }
+    }
+
+    // The original vertices move towards their neighbours.
+    let mut new_positions: Vec<vec3::Point3> = positions
+        .iter()
+        .enumerate()
+        .map(|(index, &p)| match boundary_neighbours[index].as_slice() {
+            [] => {
+                let n = neighbours[index].len();
+                if n == 0 {
+                    return p;
+                }
+                let beta = if n == 3 {
+                    3.0 / 16.0
+                } else {
+                    3.0 / (8.0 * n as f32)
+                };
+                neighbours[index]
+                    .iter()
+                    .fold(p * (1.0 - n as f32 * beta), |sum, &neighbour| {
+                        sum + positions[neighbour] * beta
+                    })
+            }
+            &[b0, b1] => p * 0.75 + (positions[b0] + positions[b1]) * 0.125,
+            // Corners, where more than two boundary edges meet.
+            _ => p,
+        })
+        .collect();
+    let mut new_uvs = uvs.map(|uvs| uvs.to_vec());
+    let mut new_colors = colors.map(|colors| colors.to_vec());
+
+    // A new vertex on every edge.
+    let mut edge_vertices = HashMap::with_capacity(edges.len());
+    for (&(a, b), opposite) in &edges {
+        let (pa, pb) = (positions[a], positions[b]);
+        let p = match opposite.as_slice() {
+            &[c, d] => (pa + pb) * 0.375 + (positions[c] + positions[d]) * 0.125,
+            _ => (pa + pb) * 0.5,
+        };
+        edge_vertices.insert((a, b), new_positions.len());
+        new_positions.push(p);
+        if let (Some(new_uvs), Some(uvs)) = (new_uvs.as_mut(), uvs) {
+            new_uvs.push((0.5 * (uvs[a].0 + uvs[b].0), 0.5 * (uvs[a].1 + uvs[b].1)));
+        }
+        if let (Some(new_colors), Some(colors)) = (new_colors.as_mut(), colors) {
+            new_colors.push((colors[a] + colors[b]) * 0.5);
+        }
+    }
+
+    let mut new_indices = Vec::with_capacity(4 * indices.len());
+    for &[a, b, c] in indices {
+        let ab = edge_vertices[&edge_key(a, b)];
+        let bc = edge_vertices[&edge_key(b, c)];
+        let ca = edge_vertices[&edge_key(c, a)];
+        new_indices.extend([[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]);
+    }
+    Surface {
+        positions: new_positions,
+        uvs: new_uvs,
+        colors: new_colors,
+        indices: new_indices,
+    }
+}
+
+// Apply levels of Loop subdivision to the mesh, quadrupling the number of triangles
+// each time.
+pub fn loop_subdivide(mesh: &TriangleMesh, levels: usize) -> TriangleMesh {
+    let mut surface = Surface {
+        positions: mesh.positions().to_vec(),
+        uvs: mesh.uvs().map(|uvs| uvs.to_vec()),
+        colors: mesh.colors().map(|colors| colors.to_vec()),
+        indices: mesh.indices().to_vec(),
+    };
+    for _ in 0..levels {
+        surface = subdivide_once(&surface);
+    }
+
+    let normals = vertex_normals(&surface.positions, &surface.indices);
+    let mut subdivided = TriangleMesh::new(surface.positions, surface.indices, mesh.material())
+        .with_normals(normals);
+    if let Some(uvs) = surface.uvs {
+        subdivided = subdivided.with_uvs(uvs);
+    }
+    if let Some(colors) = surface.colors {
+        subdivided = subdivided.with_colors(colors);
+    }
+    subdivided
+}
+
+// Move every vertex along its normal by the height texture times scale, looked up at
+// the texture coordinates and position of the vertex. The mesh should be finely
+// subdivided first for the detail to show.
+pub fn displace(mesh: &TriangleMesh, height: &dyn Texture, scale: f32) -> TriangleMesh {
+    let computed;
+    let normals = match mesh.normals() {
+        Some(normals) => normals,
+        None => {
+            computed = vertex_normals(mesh.positions(), mesh.indices());
+            &computed
+        }
+    };
+
+    let positions: Vec<vec3::Point3> = mesh
+        .positions()
+        .iter()
+        .enumerate()
+        .map(|(index, &p)| {
+            let (u, v) = mesh.uvs().map_or((0.0, 0.0), |uvs| uvs[index]);
+            p + normals[index] * (scale * height.value_scalar(u, v, &p))
+        })
+        .collect();
+
+    let indices = mesh.indices().to_vec();
+    let normals = vertex_normals(&positions, &indices);
+    let mut displaced =
+        TriangleMesh::new(positions, indices, mesh.material()).with_normals(normals);
+    if let Some(uvs) = mesh.uvs() {
+        displaced = displaced.with_uvs(uvs.to_vec());
+    }
+    if let Some(colors) = mesh.colors() {
+        displaced = displaced.with_colors(colors.to_vec());
+    }
+    displaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::lambertian;
+    use crate::texture::SolidColor;
+
+    // A unit octahedron, wound counter clockwise from the outside.
+    fn octahedron() -> TriangleMesh {
+        let positions = vec![
+            vec3::Point3(1.0, 0.0, 0.0),
+            vec3::Point3(-1.0, 0.0, 0.0),
+            vec3::Point3(0.0, 1.0, 0.0),
+            vec3::Point3(0.0, -1.0, 0.0),
+            vec3::Point3(0.0, 0.0, 1.0),
+            vec3::Point3(0.0, 0.0, -1.0),
+        ];
+        let indices = vec![
+            [0, 2, 4],
+            [2, 1, 4],
+            [1, 3, 4],
+            [3, 0, 4],
+            [2, 0, 5],
+            [1, 2, 5],
+            [3, 1, 5],
+            [0, 3, 5],
+        ];
+        TriangleMesh::new(positions, indices, lambertian())
+    }
+
+    #[test]
+    fn test_loop_subdivide() {
+        // A closed mesh shrinks into a smooth, nearly round shape.
+        let mesh = loop_subdivide(&octahedron(), 3);
+        assert!(mesh.len() == 8 * 64);
+        // Every edge is split once per level: 6 + 12, 18 + 48, 66 + 192 vertices.
+        assert!(mesh.positions().len() == 258);
+        let radii: Vec<f32> = mesh.positions().iter().map(|p| p.norm()).collect();
+        let smallest = radii.iter().cloned().fold(f32::INFINITY, f32::min);
+        let largest = radii.iter().cloned().fold(0.0, f32::max);
+        assert!(largest < 1.0 && smallest > 0.4);
+        assert!(largest - smallest < 0.2);
+        for (p, normal) in mesh.positions().iter().zip(mesh.normals().unwrap()) {
+            assert!(normal.dot(p.unit_vector()) > 0.9);
+        }
+        // Subdividing again gives exactly the same mesh.
+        let again = loop_subdivide(&octahedron(), 3);
+        assert!(again.positions() == mesh.positions() && again.indices() == mesh.indices());
+
+        // An open flat square stays flat, with its texture coordinates in between.
+        let square = TriangleMesh::new(
+            vec![
+                vec3::Point3(0.0, 0.0, 0.0),
+                vec3::Point3(1.0, 0.0, 0.0),
+                vec3::Point3(1.0, 1.0, 0.0),
+                vec3::Point3(0.0, 1.0, 0.0),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+            lambertian(),
+        )
+        .with_uvs(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+        let mesh = loop_subdivide(&square, 2);
+        assert!(mesh.len() == 32);
+        assert!(mesh.positions().iter().all(|p| p.z() == 0.0));
+        assert!(mesh
+            .normals()
+            .unwrap()
+            .iter()
+            .all(|n| (*n - vec3::Vec3(0.0, 0.0, 1.0)).norm() < 1e-6));
+        let uvs = mesh.uvs().unwrap();
+        assert!(uvs.len() == mesh.positions().len());
+        assert!(uvs.contains(&(0.5, 0.5)));
+    }
+
+    #[test]
+    fn test_displace() {
+        let sphere = loop_subdivide(&octahedron(), 2);
+        let height = SolidColor::scalar(1.0);
+        let displaced = displace(&sphere, &height, 0.5);
+        assert!(displaced.len() == sphere.len());
+        for ((p, displaced), normal) in sphere
+            .positions()
+            .iter()
+            .zip(displaced.positions())
+            .zip(sphere.normals().unwrap())
+        {
+            assert!((*displaced - (*p + *normal * 0.5)).norm() < 1e-6);
+        }
+    }
+}
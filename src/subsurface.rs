@@ -0,0 +1,108 @@
+use super::color;
+use super::hittable;
+use super::material::{Evaluation, Material, RoughDielectric, Scattering};
+use super::medium;
+use super::ray;
+use super::vec3;
+
+// Single scattering albedo giving roughly the requested multiple scattering albedo
+// in a thick slab, from Chiang et al. 2016, "Practical and Controllable Subsurface
+// Scattering for Production Path Tracing".
+fn single_scattering_albedo(albedo: f32) -> f32 {
+    let a = albedo.clamp(0.0, 0.999);
+    let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+    (1.0 - s * s).clamp(0.0, 1.0)
+}
+
+// Translucent materials like skin, wax and marble. Light refracts through a dielectric
+// boundary, random walks through a homogeneous medium inside and leaves somewhere else.
+// albedo: Overall color of the material once light has scattered many times.
+// mean_free_path: Average distance light travels inside before scattering, per channel.
+// Both the boundary and the inside are handled by the path tracer, so the object must
+// be closed for light to find its way out.
+pub struct Subsurface {
+    albedo: color::Color,
+    boundary: RoughDielectric,
+    medium: medium::Medium,
+}
+
+impl Subsurface {
+    pub fn new(
+        albedo: color::Color,
+        mean_free_path: color::Color,
+        ior: f32,
+        roughness: f32,
+    ) -> Self {
+        let coefficients = |albedo: f32, mean_free_path: f32| -> (f32, f32) {
+            let extinction = 1.0 / mean_free_path.max(1e-6);
+            let scattering = single_scattering_albedo(albedo) * extinction;
+            (extinction - scattering, scattering)
+        };
+        let red = coefficients(albedo.0, mean_free_path.0);
+        let green = coefficients(albedo.1, mean_free_path.1);
+        let blue = coefficients(albedo.2, mean_free_path.2);
+
+        Subsurface {
+            albedo,
+            boundary: RoughDielectric::new(ior, roughness),
+            medium: medium::Medium::scattering(
+                color::Color(red.0, green.0, blue.0),
+                color::Color(red.1, green.1, blue.1),
+                0.0,
+            ),
+        }
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> Option<Scattering> {
+        self.boundary.scatter(ray, hit_record)
+    }
+
+    fn albedo(&self, _hit_record: &hittable::HitRecord) -> color::Color {
+        self.albedo
+    }
+
+    fn eval(
+        &self,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        direction: &vec3::Vec3,
+    ) -> Option<Evaluation> {
+        self.boundary.eval(ray, hit_record, direction)
+    }
+
+    fn medium(&self) -> Option<medium::Medium> {
+        Some(self.medium)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_scattering_albedo() {
+        assert!(single_scattering_albedo(0.0) < 0.01);
+        assert!(single_scattering_albedo(0.999) > 0.99);
+        // Bright materials need scattering to be nearly lossless.
+        assert!(single_scattering_albedo(0.8) > 0.95);
+        assert!(single_scattering_albedo(0.3) < single_scattering_albedo(0.5));
+    }
+
+    #[test]
+    fn test_subsurface_medium() {
+        let skin = Subsurface::new(
+            color::Color(0.8, 0.5, 0.4),
+            color::Color(1.0, 0.4, 0.2),
+            1.4,
+            0.3,
+        );
+        let medium = skin.medium().unwrap();
+        // Red light travels furthest.
+        let extinction = medium.extinction();
+        assert!((extinction.0 - 1.0).abs() < 1e-5);
+        assert!(extinction.0 < extinction.1 && extinction.1 < extinction.2);
+        assert!(medium.scattering.0 > 0.0 && medium.absorption.0 >= 0.0);
+    }
+}
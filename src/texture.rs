@@ -0,0 +1,146 @@
+use super::color;
+use super::framebuffer::Framebuffer;
+use super::image_reader;
+use super::vec3;
+use std::path::Path;
+use std::rc::Rc;
+
+// A color varying over a surface.
+pub trait Texture {
+    // Look up the texture at surface coordinates (u, v) and world position p.
+    fn value(&self, u: f32, v: f32, p: &vec3::Point3) -> color::Color;
+
+    // The texture as a single number, for textures driving scalar parameters.
+    fn value_scalar(&self, u: f32, v: f32, p: &vec3::Point3) -> f32 {
+        self.value(u, v, p).0
+    }
+}
+
+pub struct SolidColor {
+    color: color::Color,
+}
+
+impl SolidColor {
+    pub fn new(color: color::Color) -> Self {
+        SolidColor { color }
+    }
+
+    // A texture with the same value in every channel.
+    pub fn scalar(value: f32) -> Self {
+        SolidColor::new(color::Color(value, value, value))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _p: &vec3::Point3) -> color::Color {
+        self.color
+    }
+}
+
+// Alternating 3D checker pattern.
+// scale: Number of checks per unit length.
+pub struct CheckerTexture {
+    odd: Rc<dyn Texture>,
+    even: Rc<dyn Texture>,
+    scale: f32,
+}
+
+impl CheckerTexture {
+    pub fn new(odd: Rc<dyn Texture>, even: Rc<dyn Texture>, scale: f32) -> Self {
+        CheckerTexture { odd, even, scale }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f32, v: f32, p: &vec3::Point3) -> color::Color {
+        let cells = (p.x() * self.scale).floor()
+            + (p.y() * self.scale).floor()
+            + (p.z() * self.scale).floor();
+        if cells.rem_euclid(2.0) == 0.0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// An image mapped onto the surface, bilinearly filtered and repeating outside [0, 1].
+// v == 0 is the bottom row of the image.
+pub struct ImageTexture {
+    image: Framebuffer,
+}
+
+impl ImageTexture {
+    pub fn new(image: Framebuffer) -> Self {
+        ImageTexture { image }
+    }
+
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        color_space: image_reader::ColorSpace,
+    ) -> std::io::Result<Self> {
+        Ok(ImageTexture::new(image_reader::load_image(
+            path,
+            color_space,
+        )?))
+    }
+
+    fn texel(&self, x: isize, y: isize) -> color::Color {
+        let width = self.image.width as isize;
+        let height = self.image.height as isize;
+        self.image
+            .get(x.rem_euclid(width) as usize, y.rem_euclid(height) as usize)
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: &vec3::Point3) -> color::Color {
+        if self.image.width == 0 || self.image.height == 0 {
+            return color::Color(0.0, 0.0, 0.0);
+        }
+        // Texel centers are at half integer coordinates.
+        let x = u * self.image.width as f32 - 0.5;
+        let y = (1.0 - v) * self.image.height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let (dx, dy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        self.texel(x0, y0) * ((1.0 - dx) * (1.0 - dy))
+            + self.texel(x0 + 1, y0) * (dx * (1.0 - dy))
+            + self.texel(x0, y0 + 1) * ((1.0 - dx) * dy)
+            + self.texel(x0 + 1, y0 + 1) * (dx * dy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checker() {
+        let checker = CheckerTexture::new(
+            Rc::new(SolidColor::new(color::RED)),
+            Rc::new(SolidColor::new(color::WHITE)),
+            1.0,
+        );
+        assert!(checker.value(0.0, 0.0, &vec3::Point3(0.5, 0.5, 0.5)) == color::WHITE);
+        assert!(checker.value(0.0, 0.0, &vec3::Point3(1.5, 0.5, 0.5)) == color::RED);
+        assert!(checker.value(0.0, 0.0, &vec3::Point3(-0.5, 0.5, 0.5)) == color::RED);
+    }
+
+    #[test]
+    fn test_image_texture() {
+        // Left column black, right column white.
+        let mut image = Framebuffer::new(2, 1);
+        image.set(1, 0, color::WHITE);
+        let texture = ImageTexture::new(image);
+        let p = vec3::Point3(0.0, 0.0, 0.0);
+
+        assert!(texture.value(0.25, 0.5, &p) == color::Color(0.0, 0.0, 0.0));
+        assert!(texture.value(0.75, 0.5, &p) == color::WHITE);
+        assert!(texture.value(0.5, 0.5, &p) == color::WHITE * 0.5);
+        // Wraps around horizontally.
+        assert!(texture.value(1.75, 0.5, &p) == color::WHITE);
+    }
+}
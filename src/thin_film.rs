@@ -0,0 +1,157 @@
+use super::color;
+use super::microfacet::Complex;
+use super::spectrum;
+use std::f32::consts::PI;
+
+// A thin transparent coating, like soap or oil, whose reflections interfere with
+// those of the surface below and produce iridescent colors.
+// thickness: Thickness of the film in nanometers.
+// ior: Index of refraction of the film.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ThinFilm {
+    pub thickness: f32,
+    pub ior: f32,
+}
+
+// Fresnel amplitude coefficients for s and p polarized light crossing from a medium
+// with index n_i into one with index n_j, at the given angle cosines.
+fn amplitudes(n_i: Complex, cos_i: Complex, n_j: Complex, cos_j: Complex) -> (Complex, Complex) {
+    let s = (n_i * cos_i - n_j * cos_j) / (n_i * cos_i + n_j * cos_j);
+    let p = (n_j * cos_i - n_i * cos_j) / (n_j * cos_i + n_i * cos_j);
+    (s, p)
+}
+
+// Cosine of the refracted angle in a medium with index n, by Snell's law.
+// sin_outside: n_1 sin(theta_1), the same in every layer.
+fn refracted_cosine(sin_outside: f32, n: Complex) -> Complex {
+    let sin = Complex::from(sin_outside) / n;
+    (Complex::from(1.0) - sin * sin).sqrt()
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f32, ior: f32) -> Self {
+        ThinFilm { thickness, ior }
+    }
+
+    // Reflectance of unpolarized light at a single wavelength, from the Airy summation
+    // of all the reflections bouncing inside the film.
+    // outside: Index of refraction above the film.
+    // substrate: Complex index of refraction below the film.
+    pub fn reflectance(
+        &self,
+        cos_theta_i: f32,
+        outside: f32,
+        substrate: Complex,
+        wavelength: f32,
+    ) -> f32 {
+        let cos_1 = cos_theta_i.clamp(0.0, 1.0);
+        let sin_outside = outside * (1.0 - cos_1 * cos_1).sqrt();
+        let n_1 = Complex::from(outside);
+        let n_2 = Complex::from(self.ior);
+        let cos_2 = refracted_cosine(sin_outside, n_2);
+        let cos_3 = refracted_cosine(sin_outside, substrate);
+
+        let (r12_s, r12_p) = amplitudes(n_1, Complex::from(cos_1), n_2, cos_2);
+        let (r23_s, r23_p) = amplitudes(n_2, cos_2, substrate, cos_3);
+
+        // Phase difference picked up by one round trip through the film.
+        let delta = n_2 * cos_2 * Complex::from(4.0 * PI * self.thickness / wavelength);
+        let phase = (Complex::new(0.0, 1.0) * delta).exp();
+        let airy = |r12: Complex, r23: Complex| -> f32 {
+            ((r12 + r23 * phase) / (Complex::from(1.0) + r12 * r23 * phase)).norm_squared()
+        };
+        (0.5 * (airy(r12_s, r23_s) + airy(r12_p, r23_p))).clamp(0.0, 1.0)
+    }
+
+    // Reflectance in every color channel. In spectral mode all channels hold the
+    // reflectance at the ray's wavelength, otherwise each channel is evaluated at its
+    // representative wavelength.
+    // substrate: Index of refraction below the film at a wavelength in nanometers.
+    pub fn reflectance_color<F>(
+        &self,
+        cos_theta_i: f32,
+        outside: f32,
+        substrate: F,
+        wavelength: Option<f32>,
+    ) -> color::Color
+    where
+        F: Fn(f32) -> Complex,
+    {
+        let at = |lambda: f32| self.reflectance(cos_theta_i, outside, substrate(lambda), lambda);
+        match wavelength {
+            Some(lambda) => color::WHITE * at(lambda),
+            None => {
+                let [red, green, blue] = spectrum::RGB_WAVELENGTHS;
+                color::Color(at(red), at(green), at(blue))
+            }
+        }
+    }
+}
+
+// A complex index of refraction reproducing a metal's color, after Gulbrandsen 2014,
+// "Artist Friendly Metallic Fresnel".
+// reflectivity: Reflectance at normal incidence.
+// edge_tint: Color of the reflection towards grazing angles.
+pub fn conductor_from_reflectivity(reflectivity: f32, edge_tint: f32) -> Complex {
+    let r = reflectivity.clamp(0.0, 0.99);
+    let g = edge_tint.clamp(0.0, 1.0);
+    let sqrt_r = r.sqrt();
+    let n = g * (1.0 - r) / (1.0 + r) + (1.0 - g) * (1.0 + sqrt_r) / (1.0 - sqrt_r);
+    let k2 = (r * (n + 1.0) * (n + 1.0) - (n - 1.0) * (n - 1.0)) / (1.0 - r);
+    Complex::new(n, k2.max(0.0).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::microfacet;
+
+    #[test]
+    fn test_film_without_effect() {
+        // A film with no thickness, or matching the substrate, isn't there at all.
+        for &cosine in [1.0, 0.7, 0.2].iter() {
+            let bare = microfacet::fresnel_dielectric(cosine, 1.5);
+            let empty = ThinFilm::new(0.0, 1.33);
+            let matching = ThinFilm::new(300.0, 1.5);
+            let substrate = Complex::from(1.5);
+            assert!((empty.reflectance(cosine, 1.0, substrate, 550.0) - bare).abs() < 1e-5);
+            assert!((matching.reflectance(cosine, 1.0, substrate, 550.0) - bare).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_antireflection_coating() {
+        // A quarter wave film with the geometric mean index cancels the reflection.
+        let ior = 1.5_f32.sqrt();
+        let film = ThinFilm::new(550.0 / (4.0 * ior), ior);
+        assert!(film.reflectance(1.0, 1.0, Complex::from(1.5), 550.0) < 1e-5);
+        assert!(film.reflectance(1.0, 1.0, Complex::from(1.5), 450.0) > 1e-3);
+    }
+
+    #[test]
+    fn test_iridescence() {
+        // A soap film reflects different colors at different thicknesses.
+        let thin =
+            ThinFilm::new(250.0, 1.33).reflectance_color(1.0, 1.0, |_| Complex::from(1.0), None);
+        let thick =
+            ThinFilm::new(350.0, 1.33).reflectance_color(1.0, 1.0, |_| Complex::from(1.0), None);
+        assert!((thin.0 - thin.2).abs() > 0.01);
+        assert!((thin.0 - thin.2) * (thick.0 - thick.2) < 0.0);
+
+        let spectral = ThinFilm::new(250.0, 1.33).reflectance_color(
+            1.0,
+            1.0,
+            |_| Complex::from(1.0),
+            Some(650.0),
+        );
+        assert!(spectral == color::WHITE * thin.0);
+    }
+
+    #[test]
+    fn test_conductor_from_reflectivity() {
+        for &r in [0.2, 0.6, 0.95].iter() {
+            let eta = conductor_from_reflectivity(r, r);
+            assert!((microfacet::fresnel_complex(1.0, eta) - r).abs() < 1e-3);
+        }
+    }
+}
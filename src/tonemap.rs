@@ -0,0 +1,140 @@
+use super::color;
+use std::str::FromStr;
+
+// Curves compressing scene referred radiance into the displayable [0, 1] range.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ToneMapper {
+    // Cut off everything above 1.
+    Clamp,
+    // x / (1 + x), never fully saturates.
+    Reinhard,
+    // Krzysztof Narkowicz's fit of the ACES filmic reference rendering transform.
+    AcesFilmic,
+}
+
+impl ToneMapper {
+    pub fn map(&self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        match self {
+            ToneMapper::Clamp => x.min(1.0),
+            ToneMapper::Reinhard => x / (1.0 + x),
+            ToneMapper::AcesFilmic => {
+                let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                ((x * (a * x + b)) / (x * (c * x + d) + e)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMapper::Clamp),
+            "reinhard" => Ok(ToneMapper::Reinhard),
+            "aces" => Ok(ToneMapper::AcesFilmic),
+            _ => Err(format!("unknown tone mapper {}", s)),
+        }
+    }
+}
+
+// The sRGB transfer function (OETF), encoding linear [0, 1] values for display.
+pub fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Inverse of linear_to_srgb, decoding display values back to linear.
+pub fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Converts rendered linear radiance into display values.
+// exposure: Exposure adjustment in stops (EV), each stop doubles the brightness.
+// white_balance: Per channel gains applied before tone mapping.
+// tone_mapper: Curve used to bring the image into [0, 1].
+#[derive(Debug, Copy, Clone)]
+pub struct DisplayTransform {
+    pub exposure: f32,
+    pub white_balance: color::Color,
+    pub tone_mapper: ToneMapper,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        DisplayTransform {
+            exposure: 0.0,
+            white_balance: color::WHITE,
+            tone_mapper: ToneMapper::Clamp,
+        }
+    }
+}
+
+impl DisplayTransform {
+    // Returns the sRGB encoded color, with every channel in [0, 1].
+    pub fn apply(self, pixel_color: color::Color) -> color::Color {
+        let exposed = pixel_color * self.white_balance * 2.0_f32.powf(self.exposure);
+        let encode = |x: f32| linear_to_srgb(self.tone_mapper.map(x));
+        color::Color(encode(exposed.0), encode(exposed.1), encode(exposed.2))
+    }
+
+    // Quantize the display color to integers in [0, max_value].
+    pub fn quantize(self, pixel_color: color::Color, max_value: u16) -> [u16; 3] {
+        let display = self.apply(pixel_color);
+        // NaNs from broken samples cast to 0.
+        let scale = |x: f32| (x * max_value as f32).round() as u16;
+        [scale(display.0), scale(display.1), scale(display.2)]
+    }
+
+    pub fn to_8bit(self, pixel_color: color::Color) -> [u8; 3] {
+        let [r, g, b] = self.quantize(pixel_color, u8::MAX as u16);
+        [r as u8, g as u8, b as u8]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for i in 0..=20 {
+            let x = i as f32 / 20.0;
+            assert!((srgb_to_linear(linear_to_srgb(x)) - x).abs() < 1e-5);
+        }
+        assert!(linear_to_srgb(0.0) == 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-6);
+        assert!((linear_to_srgb(0.18) - 0.4614).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_tone_mappers() {
+        assert!(ToneMapper::Clamp.map(4.0) == 1.0);
+        assert!(ToneMapper::Reinhard.map(1.0) == 0.5);
+        assert!(ToneMapper::AcesFilmic.map(0.0) == 0.0);
+        assert!(ToneMapper::AcesFilmic.map(100.0) == 1.0);
+        assert!(ToneMapper::AcesFilmic.map(0.5) < ToneMapper::AcesFilmic.map(1.0));
+    }
+
+    #[test]
+    fn test_display_transform() {
+        let transform = DisplayTransform::default();
+        assert!(transform.to_8bit(color::Color(0.0, 1.0, 10.0)) == [0, 255, 255]);
+
+        let darker = DisplayTransform {
+            exposure: -1.0,
+            ..Default::default()
+        };
+        assert!(darker.to_8bit(color::Color(2.0, 2.0, 2.0)) == [255, 255, 255]);
+        let quarter = (linear_to_srgb(0.25) * 1000.0).round() as u16;
+        assert!(darker.quantize(color::Color(0.5, 0.5, 0.5), 1000) == [quarter; 3]);
+    }
+}
@@ -0,0 +1,145 @@
+use super::aabb::Aabb;
+use super::hittable;
+use super::material;
+use super::polynomial;
+use super::ray;
+use super::util;
+use super::vec3;
+use std::f32::consts::PI;
+use std::option::Option;
+use std::rc::Rc;
+
+// A ring around center, lying in the plane perpendicular to axis.
+// major_radius: Distance from the center to the middle of the tube.
+// minor_radius: Radius of the tube.
+// u: Angle around the axis.
+// v: Angle around the tube, starting from the outer equator towards the axis.
+pub struct Torus {
+    center: vec3::Point3,
+    frame: vec3::Frame,
+    major_radius: f32,
+    minor_radius: f32,
+    material: Rc<dyn material::Material>,
+}
+
+impl Torus {
+    pub fn new(
+        center: vec3::Point3,
+        axis: vec3::Vec3,
+        major_radius: f32,
+        minor_radius: f32,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        Torus {
+            center,
+            frame: vec3::Frame::from_normal(&axis.unit_vector()),
+            major_radius,
+            minor_radius,
+            material,
+        }
+    }
+}
+
+impl hittable::Hittable for Torus {
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<hittable::HitRecord> {
+        // Skip the quartic for rays that miss the bounds.
+        let (t_enter, t_exit) = self.bounding_box()?.hit(r, t_min, t_max)?;
+
+        // Substituting the ray into (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2).
+        let local = r.to_local(&self.center, &self.frame);
+        let (ox, oy, oz) = (
+            local.origin.x() as f64,
+            local.origin.y() as f64,
+            local.origin.z() as f64,
+        );
+        let (dx, dy, dz) = (
+            local.direction.x() as f64,
+            local.direction.y() as f64,
+            local.direction.z() as f64,
+        );
+        let major_squared = (self.major_radius as f64).powi(2);
+        let minor_squared = (self.minor_radius as f64).powi(2);
+        let alpha = dx * dx + dy * dy + dz * dz;
+        let beta = ox * dx + oy * dy + oz * dz;
+        let k = ox * ox + oy * oy + oz * oz + major_squared - minor_squared;
+        let t = polynomial::solve_quartic(
+            alpha * alpha,
+            4.0 * alpha * beta,
+            4.0 * beta * beta + 2.0 * alpha * k - 4.0 * major_squared * (dx * dx + dy * dy),
+            4.0 * beta * k - 8.0 * major_squared * (ox * dx + oy * dy),
+            k * k - 4.0 * major_squared * (ox * ox + oy * oy),
+        )
+        .into_iter()
+        .map(|t| t as f32)
+        .find(|&t| t >= t_min && t <= t_max && t >= t_enter - 1e-4 && t <= t_exit + 1e-4)?;
+
+        // The normal points away from the nearest point on the center of the tube.
+        let p = local.at(t);
+        let (phi, u) = util::turn(p.y(), p.x());
+        let radial = vec3::Vec3(phi.cos(), phi.sin(), 0.0);
+        let outward_normal = (p - radial * self.major_radius).unit_vector();
+        let mut hit_record = hittable::HitRecord::new(
+            t,
+            r.at(t),
+            r,
+            self.frame.to_world(&outward_normal),
+            self.material.clone(),
+        );
+        let (cos_theta, sin_theta) = (outward_normal.dot(radial), outward_normal.z());
+        let (_, v) = util::turn(sin_theta, cos_theta);
+        hit_record.u = u;
+        hit_record.v = v;
+        let distance = self.major_radius + self.minor_radius * cos_theta;
+        let dpdu = vec3::Vec3(-radial.y(), radial.x(), 0.0) * (2.0 * PI * distance);
+        let dpdv =
+            (vec3::Vec3(0.0, 0.0, cos_theta) - radial * sin_theta) * (2.0 * PI * self.minor_radius);
+        hit_record.dpdu = self.frame.to_world(&dpdu);
+        hit_record.dpdv = self.frame.to_world(&dpdv);
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = self.major_radius + self.minor_radius;
+        let local = Aabb::new(
+            vec3::Point3(-extent, -extent, -self.minor_radius),
+            vec3::Point3(extent, extent, self.minor_radius),
+        );
+        Some(local.to_world(&self.center, &self.frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{check_tangents, lambertian};
+    use crate::hittable::Hittable;
+
+    #[test]
+    fn test_torus_hit() {
+        let torus = Torus::new(
+            vec3::Point3(0.0, 0.0, 0.0),
+            vec3::Vec3(0.0, 1.0, 0.0),
+            2.0,
+            0.5,
+            lambertian(),
+        );
+
+        // Along a diameter, through the outer then the inner wall of the tube.
+        let r = ray::Ray::new(vec3::Point3(0.0, 0.0, 5.0), vec3::Vec3(0.0, 0.0, -1.0));
+        let hit_record = torus.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 2.5).abs() < 1e-4);
+        assert!((hit_record.normal - vec3::Vec3(0.0, 0.0, 1.0)).norm() < 1e-4);
+        check_tangents(&hit_record);
+        let hit_record = torus.hit(&r, 3.0, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 3.5).abs() < 1e-4);
+        assert!(!hit_record.front_face);
+
+        // Down onto the top of the tube, and through the hole.
+        let down = ray::Ray::new(vec3::Point3(2.0, 3.0, 0.0), vec3::Vec3(0.0, -1.0, 0.0));
+        let hit_record = torus.hit(&down, 0.001, f32::INFINITY).unwrap();
+        assert!((hit_record.t - 2.5).abs() < 1e-4);
+        assert!((hit_record.v - 0.25).abs() < 1e-4);
+        let hole = ray::Ray::new(vec3::Point3(0.0, 3.0, 0.0), vec3::Vec3(0.0, -1.0, 0.0));
+        assert!(torus.hit(&hole, 0.001, f32::INFINITY).is_none());
+    }
+}
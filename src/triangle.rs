@@ -0,0 +1,452 @@
+use super::aabb::Aabb;
+use super::color;
+use super::hittable;
+use super::material;
+use super::ray;
+use super::vec3;
+use std::option::Option;
+use std::rc::Rc;
+
+// An indexed triangle mesh sharing one material.
+// positions: Vertex positions.
+// normals: Optional vertex normals, interpolated for smooth shading.
+// uvs: Optional vertex texture coordinates.
+// colors: Optional vertex colors, used by materials that ask for them.
+// indices: Three vertex indices per triangle, counter clockwise seen from the front.
+// nodes, order: Bounding volume hierarchy over the triangles, so rays only test the
+// few near them.
+pub struct TriangleMesh {
+    positions: Vec<vec3::Point3>,
+    normals: Option<Vec<vec3::Vec3>>,
+    uvs: Option<Vec<(f32, f32)>>,
+    colors: Option<Vec<color::Color>>,
+    indices: Vec<[usize; 3]>,
+    material: Rc<dyn material::Material>,
+    nodes: Vec<BvhNode>,
+    order: Vec<usize>,
+}
+
+// Most triangles a leaf of the hierarchy holds.
+static LEAF_SIZE: usize = 4;
+
+// A box around part of the mesh. Leaves hold count triangles of the mesh order,
+// starting at start. Interior nodes have count zero, their first child right after
+// them and the second at second_child.
+struct BvhNode {
+    bounds: Aabb,
+    start: usize,
+    count: usize,
+    second_child: usize,
+}
+
+// Ray parameter and barycentric coordinates of the second and third vertex, using
+// the Moller-Trumbore algorithm.
+pub fn intersect_triangle(
+    r: &ray::Ray,
+    p0: &vec3::Point3,
+    p1: &vec3::Point3,
+    p2: &vec3::Point3,
+) -> Option<(f32, f32, f32)> {
+    let edge1 = *p1 - *p0;
+    let edge2 = *p2 - *p0;
+    let pvec = r.direction.cross(edge2);
+    let determinant = edge1.dot(pvec);
+    // The ray is parallel to the triangle.
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+
+    let tvec = r.origin - *p0;
+    let b1 = tvec.dot(pvec) * inverse_determinant;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = tvec.cross(edge1);
+    let b2 = r.direction.dot(qvec) * inverse_determinant;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    Some((edge2.dot(qvec) * inverse_determinant, b1, b2))
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<vec3::Point3>,
+        indices: Vec<[usize; 3]>,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        let mut mesh = TriangleMesh {
+            positions,
+            normals: None,
+            uvs: None,
+            colors: None,
+            indices,
+            material,
+            nodes: vec![],
+            order: vec![],
+        };
+        mesh.build_bvh();
+        mesh
+    }
+
+    // A mesh holding a single triangle.
+    pub fn triangle(
+        p0: vec3::Point3,
+        p1: vec3::Point3,
+        p2: vec3::Point3,
+        material: Rc<dyn material::Material>,
+    ) -> Self {
+        TriangleMesh::new(vec![p0, p1, p2], vec![[0, 1, 2]], material)
+    }
+
+    pub fn with_normals(mut self, normals: Vec<vec3::Vec3>) -> Self {
+        assert!(
+            normals.len() == self.positions.len(),
+            "one normal per vertex needed"
+        );
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<(f32, f32)>) -> Self {
+        assert!(
+            uvs.len() == self.positions.len(),
+            "one texture coordinate per vertex needed"
+        );
+        self.uvs = Some(uvs);
+        self
+    }
+
+    pub fn with_colors(mut self, colors: Vec<color::Color>) -> Self {
+        assert!(
+            colors.len() == self.positions.len(),
+            "one color per vertex needed"
+        );
+        self.colors = Some(colors);
+        self
+    }
+
+    // Scale the mesh about the origin and then move it by offset, to place models
+    // made in other units or around another center.
+    pub fn transform(mut self, scale: f32, offset: vec3::Vec3) -> Self {
+        for position in self.positions.iter_mut() {
+            *position = *position * scale + offset;
+        }
+        self.build_bvh();
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn positions(&self) -> &[vec3::Point3] {
+        &self.positions
+    }
+
+    pub fn indices(&self) -> &[[usize; 3]] {
+        &self.indices
+    }
+
+    pub fn normals(&self) -> Option<&[vec3::Vec3]> {
+        self.normals.as_deref()
+    }
+
+    pub fn uvs(&self) -> Option<&[(f32, f32)]> {
+        self.uvs.as_deref()
+    }
+
+    pub fn colors(&self) -> Option<&[color::Color]> {
+        self.colors.as_deref()
+    }
+
+    pub fn material(&self) -> Rc<dyn material::Material> {
+        self.material.clone()
+    }
+
+    fn triangle_bounds(&self, index: usize) -> Aabb {
+        Aabb::from_points(
+            self.indices[index]
+                .iter()
+                .map(|&vertex| self.positions[vertex]),
+        )
+        .unwrap()
+    }
+
+    fn build_bvh(&mut self) {
+        self.nodes.clear();
+        self.order = (0..self.len()).collect();
+        if !self.is_empty() {
+            self.build_node(0, self.len());
+        }
+    }
+
+    // Add the node for the triangles order[start..end] and everything below it.
+    // Triangles are split in half along the axis their centers are most spread out on.
+    fn build_node(&mut self, start: usize, end: usize) {
+        let bounds = (start + 1..end).fold(self.triangle_bounds(self.order[start]), |bounds, i| {
+            bounds.surrounding(self.triangle_bounds(self.order[i]))
+        });
+        let node = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds,
+            start,
+            count: end - start,
+            second_child: 0,
+        });
+        if end - start <= LEAF_SIZE {
+            return;
+        }
+
+        let center = |mesh: &TriangleMesh, index: usize| {
+            let bounds = mesh.triangle_bounds(index);
+            (bounds.min + bounds.max) * 0.5
+        };
+        let centers = Aabb::from_points(self.order[start..end].iter().map(|&i| center(self, i)))
+            .unwrap()
+            .size();
+        let axis = if centers.x() >= centers.y() && centers.x() >= centers.z() {
+            0
+        } else if centers.y() >= centers.z() {
+            1
+        } else {
+            2
+        };
+        let key = |mesh: &TriangleMesh, index: usize| {
+            let center = center(mesh, index);
+            [center.x(), center.y(), center.z()][axis]
+        };
+        let middle = (start + end) / 2;
+        let mut order = std::mem::take(&mut self.order);
+        order[start..end].select_nth_unstable_by(middle - start, |&a, &b| {
+            key(self, a).total_cmp(&key(self, b))
+        });
+        self.order = order;
+
+        self.nodes[node].count = 0;
+        self.build_node(start, middle);
+        self.nodes[node].second_child = self.nodes.len();
+        self.build_node(middle, end);
+    }
+
+    fn vertex_uvs(&self, triangle: &[usize; 3]) -> [(f32, f32); 3] {
+        match &self.uvs {
+            Some(uvs) => [uvs[triangle[0]], uvs[triangle[1]], uvs[triangle[2]]],
+            None => [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)],
+        }
+    }
+
+    // Intersect a single triangle of the mesh.
+    pub fn hit_triangle(
+        &self,
+        index: usize,
+        r: &ray::Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<hittable::HitRecord> {
+        let triangle = &self.indices[index];
+        let [p0, p1, p2] = [
+            self.positions[triangle[0]],
+            self.positions[triangle[1]],
+            self.positions[triangle[2]],
+        ];
+        let (t, b1, b2) = intersect_triangle(r, &p0, &p1, &p2)?;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let b0 = 1.0 - b1 - b2;
+
+        let mut geometric_normal = (p1 - p0).cross(p2 - p0).unit_vector();
+        let shading_normal = self.normals.as_ref().map(|normals| {
+            (normals[triangle[0]] * b0 + normals[triangle[1]] * b1 + normals[triangle[2]] * b2)
+                .unit_vector()
+        });
+        // Vertex normals decide which side is the outside.
+        if let Some(shading_normal) = shading_normal {
+            if geometric_normal.dot(shading_normal) < 0.0 {
+                geometric_normal = -geometric_normal;
+            }
+        }
+
+        let uvs = self.vertex_uvs(triangle);
+        let (dpdu, dpdv) = triangle_tangents(&[p0, p1, p2], &uvs, &geometric_normal);
+
+        let mut hit_record =
+            hittable::HitRecord::new(t, r.at(t), r, geometric_normal, self.material.clone());
+        if let Some(shading_normal) = shading_normal {
+            hit_record.normal = if hit_record.front_face {
+                shading_normal
+            } else {
+                -shading_normal
+            };
+        }
+        hit_record.u = b0 * uvs[0].0 + b1 * uvs[1].0 + b2 * uvs[2].0;
+        hit_record.v = b0 * uvs[0].1 + b1 * uvs[1].1 + b2 * uvs[2].1;
+        hit_record.dpdu = dpdu;
+        hit_record.dpdv = dpdv;
+        hit_record.vertex_color = self.colors.as_ref().map(|colors| {
+            colors[triangle[0]] * b0 + colors[triangle[1]] * b1 + colors[triangle[2]] * b2
+        });
+        Some(hit_record)
+    }
+}
+
+// Partial derivatives of the position with respect to the texture coordinates, which
+// are constant across a triangle. Degenerate texture coordinates fall back to an
+// arbitrary frame around the normal.
+fn triangle_tangents(
+    positions: &[vec3::Point3; 3],
+    uvs: &[(f32, f32); 3],
+    normal: &vec3::Vec3,
+) -> (vec3::Vec3, vec3::Vec3) {
+    let duv02 = (uvs[0].0 - uvs[2].0, uvs[0].1 - uvs[2].1);
+    let duv12 = (uvs[1].0 - uvs[2].0, uvs[1].1 - uvs[2].1);
+    let dp02 = positions[0] - positions[2];
+    let dp12 = positions[1] - positions[2];
+    let determinant = duv02.0 * duv12.1 - duv02.1 * duv12.0;
+    if determinant.abs() < 1e-9 {
+        return vec3::orthonormal_basis(normal);
+    }
+    let dpdu = (dp02 * duv12.1 - dp12 * duv02.1) / determinant;
+    let dpdv = (dp12 * duv02.0 - dp02 * duv12.0) / determinant;
+    (dpdu, dpdv)
+}
+
+impl hittable::Hittable for TriangleMesh {
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<hittable::HitRecord> {
+        let mut final_record = None;
+        let mut closest_so_far = t_max;
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds.hit(r, t_min, closest_so_far).is_none() {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.second_child);
+                stack.push(index + 1);
+                continue;
+            }
+            for &triangle in self.order[node.start..node.start + node.count].iter() {
+                if let Some(hit_record) = self.hit_triangle(triangle, r, t_min, closest_so_far) {
+                    closest_so_far = hit_record.t;
+                    final_record = Some(hit_record);
+                }
+            }
+        }
+        final_record
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.nodes.first().map(|root| root.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::lambertian;
+    use crate::hittable::Hittable;
+
+    fn quad() -> TriangleMesh {
+        // A unit square in the xy plane facing +z.
+        TriangleMesh::new(
+            vec![
+                vec3::Point3(0.0, 0.0, 0.0),
+                vec3::Point3(1.0, 0.0, 0.0),
+                vec3::Point3(1.0, 1.0, 0.0),
+                vec3::Point3(0.0, 1.0, 0.0),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+            lambertian(),
+        )
+        .with_uvs(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)])
+    }
+
+    #[test]
+    fn test_triangle_hit() {
+        let mesh = quad();
+        let r = ray::Ray::new(vec3::Point3(0.25, 0.75, 2.0), vec3::Vec3(0.0, 0.0, -1.0));
+        let hit_record = mesh.hit(&r, 0.001, f32::INFINITY).unwrap();
+
+        assert!(hit_record.t == 2.0);
+        assert!(hit_record.front_face);
+        assert!(hit_record.normal == vec3::Vec3(0.0, 0.0, 1.0));
+        assert!((hit_record.u - 0.25).abs() < 1e-6 && (hit_record.v - 0.75).abs() < 1e-6);
+        assert!((hit_record.dpdu - vec3::Vec3(1.0, 0.0, 0.0)).norm() < 1e-6);
+        assert!((hit_record.dpdv - vec3::Vec3(0.0, 1.0, 0.0)).norm() < 1e-6);
+
+        let miss = ray::Ray::new(vec3::Point3(1.5, 0.5, 2.0), vec3::Vec3(0.0, 0.0, -1.0));
+        assert!(mesh.hit(&miss, 0.001, f32::INFINITY).is_none());
+        assert!(mesh.hit(&r, 0.001, 1.0).is_none());
+    }
+
+    #[test]
+    fn test_vertex_normals() {
+        let mesh = TriangleMesh::triangle(
+            vec3::Point3(0.0, 0.0, 0.0),
+            vec3::Point3(1.0, 0.0, 0.0),
+            vec3::Point3(0.0, 1.0, 0.0),
+            lambertian(),
+        )
+        .with_normals(vec![
+            vec3::Vec3(0.0, 0.0, 1.0),
+            vec3::Vec3(1.0, 0.0, 1.0).unit_vector(),
+            vec3::Vec3(0.0, 1.0, 1.0).unit_vector(),
+        ]);
+
+        // Seen from behind, the interpolated normal is flipped to face the ray.
+        let r = ray::Ray::new(vec3::Point3(0.5, 0.0, -1.0), vec3::Vec3(0.0, 0.0, 1.0));
+        let hit_record = mesh.hit(&r, 0.001, f32::INFINITY).unwrap();
+        assert!(!hit_record.front_face);
+        assert!(hit_record.normal.z() < 0.0 && hit_record.normal.x() < 0.0);
+        assert!((hit_record.normal.norm() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_bvh_matches_every_triangle() {
+        // A bumpy grid, big enough to need several levels of the hierarchy.
+        let size = 16;
+        let mut positions = vec![];
+        for j in 0..=size {
+            for i in 0..=size {
+                let (x, y) = (i as f32 / size as f32, j as f32 / size as f32);
+                positions.push(vec3::Point3(x, y, (7.0 * x).sin() * (5.0 * y).cos() * 0.1));
+            }
+        }
+        let mut indices = vec![];
+        for j in 0..size {
+            for i in 0..size {
+                let corner = j * (size + 1) + i;
+                indices.push([corner, corner + 1, corner + size + 2]);
+                indices.push([corner, corner + size + 2, corner + size + 1]);
+            }
+        }
+        let mesh = TriangleMesh::new(positions, indices, lambertian());
+        assert!(mesh.nodes.len() > 1);
+
+        for k in 0..200 {
+            let origin = vec3::Point3(k as f32 * 0.0137 - 0.3, k as f32 * 0.0071 - 0.2, 1.0);
+            let r = ray::Ray::new(origin, vec3::Vec3(0.3, 0.2, -1.0));
+            let brute_force = (0..mesh.len())
+                .filter_map(|index| mesh.hit_triangle(index, &r, 0.001, f32::INFINITY))
+                .map(|hit_record| hit_record.t)
+                .fold(None, |closest: Option<f32>, t| {
+                    Some(closest.map_or(t, |c| c.min(t)))
+                });
+            assert!(
+                mesh.hit(&r, 0.001, f32::INFINITY)
+                    .map(|hit_record| hit_record.t)
+                    == brute_force
+            );
+        }
+    }
+}
@@ -1,18 +1,36 @@
 use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::cell::RefCell;
+use std::f32::consts::PI;
+
+thread_local! {
+    // Random numbers for each thread, seeded from the OS unless seed_random is called.
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+// Make the random numbers drawn on this thread repeatable, for Monte Carlo tests.
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
 
 pub fn random_float() -> f32 {
-    let mut rng = rand::thread_rng();
-    rng.gen()
+    RNG.with(|rng| rng.borrow_mut().gen())
 }
 
 pub fn random_float_bounds(min: f32, max: f32) -> f32 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(min, max)
+    RNG.with(|rng| rng.borrow_mut().gen_range(min, max))
 }
-static PI: f32 = 3.1415926535897932385;
 
 // Utility Functions
 
 pub fn degrees_to_radians(degrees: f32) -> f32 {
     (degrees * PI) / 180.0
 }
+
+// The angle of the point (x, y) around the origin from the x axis, from 0 to 2 pi,
+// and that angle as a fraction of a full turn for use as a texture coordinate.
+pub fn turn(y: f32, x: f32) -> (f32, f32) {
+    let angle = y.atan2(x);
+    let angle = if angle < 0.0 { angle + 2.0 * PI } else { angle };
+    (angle, angle / (2.0 * PI))
+}
@@ -62,7 +62,7 @@ impl Vec3 {
 
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
-        self.0.abs() < s && self.0.abs() < s && self.2.abs() < s
+        self.0.abs() < s && self.1.abs() < s && self.2.abs() < s
     }
 }
 
@@ -103,6 +103,49 @@ pub fn random_in_unit_disk() -> Vec3 {
     }
 }
 
+// Two unit vectors which together with the unit vector n form an orthonormal basis.
+// See Duff et al. 2017, "Building an Orthonormal Basis, Revisited".
+pub fn orthonormal_basis(n: &Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0_f32.copysign(n.z());
+    let a = -1.0 / (sign + n.z());
+    let b = n.x() * n.y() * a;
+    (
+        Vec3(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x()),
+        Vec3(b, sign + n.y() * n.y() * a, -n.y()),
+    )
+}
+
+// A local coordinate frame around a normal, which becomes the z axis.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub normal: Vec3,
+}
+
+impl Frame {
+    pub fn from_normal(normal: &Vec3) -> Self {
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        Frame {
+            tangent,
+            bitangent,
+            normal: *normal,
+        }
+    }
+
+    pub fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3(
+            v.dot(self.tangent),
+            v.dot(self.bitangent),
+            v.dot(self.normal),
+        )
+    }
+
+    pub fn to_world(&self, v: &Vec3) -> Vec3 {
+        self.tangent * v.x() + self.bitangent * v.y() + self.normal * v.z()
+    }
+}
+
 pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
     *v - (*n * v.dot(*n) * 2.0)
 }
@@ -110,7 +153,7 @@ pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
 pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f32) -> Vec3 {
     let cos_theta = (-(*uv).dot(*n)).min(1.0);
     let r_out_perp = (*uv + *n * cos_theta) * etai_over_etat;
-    let r_out_parallel = *n * (1.0 - r_out_perp.norm_squared()).abs().sqrt();
+    let r_out_parallel = -*n * (1.0 - r_out_perp.norm_squared()).abs().sqrt();
     r_out_perp + r_out_parallel
 }
 
@@ -195,11 +238,36 @@ mod tests {
 
         assert!(v1.dot(v2) == 6.0);
         assert!(v2.norm_squared() == 12.0);
-        assert!(v2.norm() == (12.0 as f32).sqrt());
+        assert!(v2.norm() == 12.0_f32.sqrt());
         assert!(v1.cross(v2) == Vec3(0.0, 0.0, 0.0));
         assert!(Vec3(1.0, 0.0, 0.0).unit_vector() == Vec3(1.0, 0.0, 0.0));
     }
 
+    #[test]
+    fn test_refract() {
+        let normal = Vec3(0.0, 1.0, 0.0);
+        let incoming = Vec3(1.0, -1.0, 0.0).unit_vector();
+        // Matching indices leave the ray going straight on through the surface.
+        assert!((refract(&incoming, &normal, 1.0) - incoming).norm() < 1e-6);
+        // Entering a denser medium bends it towards the inward normal.
+        let refracted = refract(&incoming, &normal, 1.0 / 1.5);
+        assert!((refracted.x() - incoming.x() / 1.5).abs() < 1e-6);
+        assert!(refracted.y() < incoming.y() && (refracted.norm() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_orthonormal_basis() {
+        for n in [
+            Vec3(0.0, 0.0, 1.0),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(1.0, 2.0, 3.0).unit_vector(),
+        ] {
+            let (t, b) = orthonormal_basis(&n);
+            assert!((t.norm() - 1.0).abs() < 1e-6 && (b.norm() - 1.0).abs() < 1e-6);
+            assert!(t.dot(n).abs() < 1e-6 && b.dot(n).abs() < 1e-6 && t.dot(b).abs() < 1e-6);
+        }
+    }
+
     #[test]
     fn test_near_zero() {
         let v1 = Vec3(1.0, 1.0, 1.0);
@@ -207,5 +275,6 @@ mod tests {
 
         assert!(!v1.near_zero());
         assert!(v2.near_zero());
+        assert!(!Vec3(0.0, 1.0, 0.0).near_zero());
     }
 }
@@ -0,0 +1,371 @@
+use super::aabb::Aabb;
+use super::color;
+use super::hittable;
+use super::material::{Evaluation, Material, Scattering};
+use super::medium;
+use super::ppm;
+use super::ray;
+use super::util;
+use super::vec3;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read};
+use std::path::Path;
+use std::rc::Rc;
+
+// How values are stored in a raw voxel file.
+// U8: One byte per voxel, mapped to densities in [0, 1].
+// F32: Little endian floats.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum VoxelFormat {
+    U8,
+    F32,
+}
+
+// A dense 3D grid of densities, stored x fastest, then y, then z.
+pub struct VoxelGrid {
+    width: usize,
+    height: usize,
+    depth: usize,
+    values: Vec<f32>,
+    max_value: f32,
+}
+
+impl VoxelGrid {
+    pub fn new(width: usize, height: usize, depth: usize, values: Vec<f32>) -> Self {
+        assert!(width > 0 && height > 0 && depth > 0, "empty voxel grid");
+        assert!(values.len() == width * height * depth);
+        let max_value = values.iter().cloned().fold(0.0, f32::max);
+        VoxelGrid {
+            width,
+            height,
+            depth,
+            values,
+            max_value,
+        }
+    }
+
+    // Read a headerless grid of the given dimensions.
+    pub fn read_raw<R: Read>(
+        reader: R,
+        width: usize,
+        height: usize,
+        depth: usize,
+        format: VoxelFormat,
+    ) -> std::io::Result<Self> {
+        let bytes_per_voxel = match format {
+            VoxelFormat::U8 => 1,
+            VoxelFormat::F32 => 4,
+        };
+        let size = width
+            .checked_mul(height)
+            .and_then(|area| area.checked_mul(depth))
+            .and_then(|count| count.checked_mul(bytes_per_voxel))
+            .filter(|&size| size > 0)
+            .ok_or_else(|| ppm::invalid_data("invalid voxel grid size"))?;
+        // Read what is there before allocating, so a bad size can't exhaust memory.
+        let mut bytes = vec![];
+        reader.take(size as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < size {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "voxel grid ends early",
+            ));
+        }
+        let values: Vec<f32> = match format {
+            VoxelFormat::U8 => bytes.iter().map(|&byte| byte as f32 / 255.0).collect(),
+            VoxelFormat::F32 => bytes
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect(),
+        };
+        // Tracking steps by the inverse of the largest density, which must be finite.
+        if values.iter().any(|value| !value.is_finite()) {
+            return Err(ppm::invalid_data("voxel density is not finite"));
+        }
+        let values = values.into_iter().map(|value| value.max(0.0)).collect();
+        Ok(VoxelGrid::new(width, height, depth, values))
+    }
+
+    pub fn load_raw<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        depth: usize,
+        format: VoxelFormat,
+    ) -> std::io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        VoxelGrid::read_raw(reader, width, height, depth, format)
+    }
+
+    pub fn max_value(&self) -> f32 {
+        self.max_value
+    }
+
+    fn get(&self, x: isize, y: isize, z: isize) -> f32 {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        let z = z.clamp(0, self.depth as isize - 1) as usize;
+        self.values[(z * self.height + y) * self.width + x]
+    }
+
+    // Trilinearly interpolated value at a point in [0, 1]^3, voxel centers being at
+    // half integer positions once scaled by the dimensions.
+    pub fn lookup(&self, p: &vec3::Point3) -> f32 {
+        let x = p.x() * self.width as f32 - 0.5;
+        let y = p.y() * self.height as f32 - 0.5;
+        let z = p.z() * self.depth as f32 - 0.5;
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (dx, dy, dz) = (x - x0, y - y0, z - z0);
+        let (x0, y0, z0) = (x0 as isize, y0 as isize, z0 as isize);
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let row = |y: isize, z: isize| lerp(self.get(x0, y, z), self.get(x0 + 1, y, z), dx);
+        let slice = |z: isize| lerp(row(y0, z), row(y0 + 1, z), dy);
+        lerp(slice(z0), slice(z0 + 1), dz)
+    }
+}
+
+// Scatters light into directions around the one it arrived from, used for the hits
+// inside volumes. There is no surface, so directions aren't weighed by a cosine.
+// albedo: Fraction of light scattered rather than absorbed.
+// anisotropy: Asymmetry of the phase function, see medium::henyey_greenstein.
+#[derive(Debug, Copy, Clone)]
+pub struct HenyeyGreenstein {
+    albedo: color::Color,
+    anisotropy: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: color::Color, anisotropy: f32) -> Self {
+        HenyeyGreenstein { albedo, anisotropy }
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray: &ray::Ray, hit_record: &hittable::HitRecord) -> Option<Scattering> {
+        let direction = ray.direction.unit_vector();
+        let scattered = medium::sample_henyey_greenstein(&direction, self.anisotropy);
+        Some(Scattering {
+            scattered: ray::Ray::new(hit_record.p, scattered),
+            attenuation: self.albedo,
+            pdf: Some(medium::henyey_greenstein(
+                direction.dot(scattered),
+                self.anisotropy,
+            )),
+        })
+    }
+
+    fn albedo(&self, _hit_record: &hittable::HitRecord) -> color::Color {
+        self.albedo
+    }
+
+    fn eval(
+        &self,
+        ray: &ray::Ray,
+        _hit_record: &hittable::HitRecord,
+        direction: &vec3::Vec3,
+    ) -> Option<Evaluation> {
+        let phase =
+            medium::henyey_greenstein(ray.direction.unit_vector().dot(*direction), self.anisotropy);
+        Some(Evaluation {
+            value: self.albedo * phase,
+            pdf: phase,
+        })
+    }
+
+    fn is_surface(&self) -> bool {
+        false
+    }
+}
+
+// Smoke or clouds, with the density given by a voxel grid stretched over a box.
+// Rays are tracked through it with delta tracking, hitting the volume where they
+// scatter, and shadow rays estimate how much light gets through with ratio tracking.
+// density_scale: Extinction coefficient per unit length for a grid value of one.
+pub struct HeterogeneousVolume {
+    bounds: Aabb,
+    grid: VoxelGrid,
+    density_scale: f32,
+    phase_function: Rc<dyn Material>,
+}
+
+impl HeterogeneousVolume {
+    pub fn new(
+        bounds: Aabb,
+        grid: VoxelGrid,
+        density_scale: f32,
+        albedo: color::Color,
+        anisotropy: f32,
+    ) -> Self {
+        HeterogeneousVolume {
+            bounds,
+            grid,
+            density_scale,
+            phase_function: Rc::new(HenyeyGreenstein::new(albedo, anisotropy)),
+        }
+    }
+
+    // Extinction coefficient at a point in world space.
+    fn extinction(&self, p: &vec3::Point3) -> f32 {
+        let local = *p - self.bounds.min;
+        let size = self.bounds.size();
+        let local = vec3::Point3(
+            local.x() / size.x(),
+            local.y() / size.y(),
+            local.z() / size.z(),
+        );
+        self.grid.lookup(&local) * self.density_scale
+    }
+
+    fn max_extinction(&self) -> f32 {
+        self.grid.max_value() * self.density_scale
+    }
+
+    // Step through tentative collisions against the majorant from t_enter to t_exit,
+    // calling visit with each position and its extinction until it returns false.
+    fn track<F>(&self, r: &ray::Ray, t_enter: f32, t_exit: f32, mut visit: F)
+    where
+        F: FnMut(f32, f32) -> bool,
+    {
+        let max_extinction = self.max_extinction();
+        // Steps would be too small to move along the ray when it isn't finite.
+        if max_extinction <= 0.0 || !max_extinction.is_finite() {
+            return;
+        }
+        // Steps are in units of t, so account for the length of the direction.
+        let step_scale = 1.0 / (max_extinction * r.direction.norm());
+        let mut t = t_enter;
+        loop {
+            t -= (1.0 - util::random_float()).ln() * step_scale;
+            if t >= t_exit || !visit(t, self.extinction(&r.at(t)) / max_extinction) {
+                return;
+            }
+        }
+    }
+}
+
+impl hittable::Hittable for HeterogeneousVolume {
+    // Delta tracking: tentative collisions are real with probability of the density
+    // over the majorant, and the first real one is where the ray scatters.
+    fn hit(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> Option<hittable::HitRecord> {
+        let (t_enter, t_exit) = self.bounds.hit(r, t_min, t_max)?;
+        let mut collision = None;
+        self.track(r, t_enter, t_exit, |t, probability| {
+            if util::random_float() < probability {
+                collision = Some(t);
+                return false;
+            }
+            true
+        });
+        let t = collision?;
+
+        // There is no surface, face the normal back along the ray.
+        let outward_normal = -r.direction.unit_vector();
+        Some(hittable::HitRecord::new(
+            t,
+            r.at(t),
+            r,
+            outward_normal,
+            self.phase_function.clone(),
+        ))
+    }
+
+    // Ratio tracking: each tentative collision lets through the fraction of the
+    // majorant that is null, giving an unbiased estimate of the transmittance.
+    fn transmittance(&self, r: &ray::Ray, t_min: f32, t_max: f32) -> color::Color {
+        let (t_enter, t_exit) = match self.bounds.hit(r, t_min, t_max) {
+            Some(range) => range,
+            None => return color::WHITE,
+        };
+        let mut transmittance = 1.0;
+        self.track(r, t_enter, t_exit, |_, probability| {
+            transmittance *= 1.0 - probability.min(1.0);
+            transmittance > 0.0
+        });
+        color::WHITE * transmittance
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::material::tests::check_consistent;
+
+    fn unit_cube(grid: VoxelGrid, density_scale: f32) -> HeterogeneousVolume {
+        HeterogeneousVolume::new(
+            Aabb::new(vec3::Point3(0.0, 0.0, 0.0), vec3::Point3(1.0, 1.0, 1.0)),
+            grid,
+            density_scale,
+            color::WHITE,
+            0.0,
+        )
+    }
+
+    #[test]
+    fn test_read_raw() {
+        let bytes: Vec<u8> = vec![0, 255, 51, 102, 0, 0, 0, 0];
+        let grid = VoxelGrid::read_raw(&bytes[..], 2, 2, 2, VoxelFormat::U8).unwrap();
+        assert!(grid.max_value() == 1.0);
+        assert!(grid.get(1, 0, 0) == 1.0);
+        assert!((grid.get(0, 1, 0) - 0.2).abs() < 1e-6);
+        // Halfway between the two first voxels.
+        assert!((grid.lookup(&vec3::Point3(0.5, 0.25, 0.25)) - 0.5).abs() < 1e-6);
+
+        let floats: Vec<u8> = [0.5_f32, 2.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let grid = VoxelGrid::read_raw(&floats[..], 2, 1, 1, VoxelFormat::F32).unwrap();
+        assert!(grid.max_value() == 2.0);
+        assert!(VoxelGrid::read_raw(&floats[..], 2, 2, 1, VoxelFormat::F32).is_err());
+        for value in [f32::INFINITY, f32::NAN] {
+            let bytes = value.to_le_bytes();
+            assert!(VoxelGrid::read_raw(&bytes[..], 1, 1, 1, VoxelFormat::F32).is_err());
+        }
+        assert!(VoxelGrid::read_raw(&floats[..], 0, 1, 1, VoxelFormat::F32).is_err());
+        assert!(VoxelGrid::read_raw(&floats[..], usize::MAX, 2, 1, VoxelFormat::U8).is_err());
+    }
+
+    #[test]
+    fn test_constant_density() {
+        // Light crossing a unit thickness with unit extinction is left with 1/e.
+        let volume = unit_cube(VoxelGrid::new(1, 1, 1, vec![0.5]), 2.0);
+        let r = ray::Ray::new(vec3::Point3(0.5, 0.5, -1.0), vec3::Vec3(0.0, 0.0, 2.0));
+        let samples = 20000;
+        let mut passed = 0;
+        let mut transmittance = 0.0;
+        for _ in 0..samples {
+            match volume.hit(&r, 0.001, f32::INFINITY) {
+                Some(hit_record) => assert!(hit_record.t >= 0.5 && hit_record.t <= 1.0),
+                None => passed += 1,
+            }
+            transmittance += volume.transmittance(&r, 0.001, f32::INFINITY).0;
+        }
+        let expected = (-1.0_f32).exp();
+        assert!((passed as f32 / samples as f32 - expected).abs() < 0.02);
+        assert!((transmittance / samples as f32 - expected).abs() < 0.02);
+
+        // Empty grids let everything through.
+        let empty = unit_cube(VoxelGrid::new(1, 1, 1, vec![0.0]), 2.0);
+        assert!(empty.hit(&r, 0.001, f32::INFINITY).is_none());
+        assert!(empty.transmittance(&r, 0.001, f32::INFINITY) == color::WHITE);
+
+        // Overflowing densities give up rather than stepping nowhere forever.
+        let overflowing = unit_cube(VoxelGrid::new(1, 1, 1, vec![f32::MAX]), 2.0);
+        assert!(overflowing.hit(&r, 0.001, f32::INFINITY).is_none());
+        overflowing.transmittance(&r, 0.001, f32::INFINITY);
+    }
+
+    #[test]
+    fn test_phase_function_consistent() {
+        check_consistent(
+            Rc::new(HenyeyGreenstein::new(color::Color(0.9, 0.8, 0.7), 0.6)),
+            true,
+        );
+    }
+}